@@ -2,7 +2,6 @@ use std::{net::SocketAddr, time::Duration};
 
 use bevy::{core::FixedTimestep, log::LogPlugin, prelude::*};
 use bevy_stokes::*;
-use laminar::Packet;
 
 const PING_ADDR: &str = "127.0.0.1:8000";
 const PONG_ADDR: &str = "127.0.0.1:8001";
@@ -10,13 +9,14 @@ const PONG_ADDR: &str = "127.0.0.1:8001";
 fn setup(mut commands: Commands) {
     let addr: SocketAddr = PING_ADDR.parse().unwrap();
     let socket_bundle = bind(addr, Duration::from_millis(10)).unwrap();
-    commands.spawn_bundle(socket_bundle);
+    let handle = commands.spawn_socket(socket_bundle);
+    commands.insert_resource(handle);
 }
 
-fn ping(mut socket_query: Query<&mut SendQueue, With<SocketMarker>>) {
-    let mut packet_queue = socket_query.single_mut();
-    let ping = Packet::reliable_unordered(PONG_ADDR.parse().unwrap(), b"DEADBEEF".to_vec());
-    packet_queue.send(ping);
+fn ping(handle: Res<SocketHandle>, mut socket_query: Query<&mut SendQueue>) {
+    let mut packet_queue = socket_query.get_mut(handle.0).unwrap();
+    let addr = PONG_ADDR.parse().unwrap();
+    packet_queue.send_with(Delivery::ReliableUnordered, addr, b"DEADBEEF".to_vec());
     info!("sent ping");
 }
 
@@ -35,7 +35,7 @@ pub fn main() {
 
     App::new()
         .add_plugins(MinimalPlugins)
-        .add_plugin(LogPlugin::default())
+        .add_plugin(LogPlugin)
         .add_plugin(NetworkPlugin::always())
         .add_startup_system(setup)
         .add_system_set(ping_interval)
@@ -0,0 +1,216 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    time::Instant,
+};
+
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::{
+    AddressRejected, ConnectionIndex, ConnectionState, ConnectionTransition, DisconnectedTraffic,
+    NetworkClock, SendError, SessionExpired,
+};
+
+/// Something which happened to a connection, recorded in its [`AuditLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AuditEvent {
+    /// The connection changed [`ConnectionState`], or was spawned if `from` is `None`.
+    Transition {
+        /// The previous state.
+        from: Option<ConnectionState>,
+        /// The new state.
+        to: ConnectionState,
+    },
+    /// A packet to the peer failed to send, see [`SendError`].
+    SendError(String),
+    /// Packets arrived while disconnected, see [`DisconnectedTraffic`].
+    DisconnectedTraffic {
+        /// The number of packets received.
+        packets: usize,
+    },
+    /// The connection's [`SessionTtl`](crate::SessionTtl) elapsed.
+    SessionExpired,
+    /// Packets from the peer were dropped by an [`AddressFilter`](crate::AddressFilter).
+    AddressRejected,
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transition { from: None, to } => write!(f, "spawned {:?}", to),
+            Self::Transition {
+                from: Some(from),
+                to,
+            } => write!(f, "{:?} -> {:?}", from, to),
+            Self::SendError(error) => write!(f, "send error: {}", error),
+            Self::DisconnectedTraffic { packets } => {
+                write!(f, "{} packets while disconnected", packets)
+            }
+            Self::SessionExpired => write!(f, "session expired"),
+            Self::AddressRejected => write!(f, "address rejected"),
+        }
+    }
+}
+
+/// An [`AuditEvent`] and the network time, see [`NetworkClock`], at which it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuditEntry {
+    /// The network time of the event.
+    pub at: Instant,
+    /// The event.
+    pub event: AuditEvent,
+}
+
+/// A [`Component`] which, when present on a connection entity, records its lifecycle transitions,
+/// errors and security events in a ring buffer, to aid post-mortem debugging of dropped peers.
+///
+/// Once full, the oldest entries are overwritten. Insert it as connections are spawned, with a
+/// [`ConnectionBuilder`](crate::ConnectionBuilder), to capture the whole lifecycle.
+#[derive(Debug, Clone, Component, PartialEq, Eq)]
+pub struct AuditLog {
+    capacity: usize,
+    dump_on_disconnect: bool,
+    entries: VecDeque<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Creates a new [`AuditLog`] keeping the latest `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            dump_on_disconnect: false,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Logs the [`AuditLog::dump`] once the connection is disconnected.
+    pub fn with_dump_on_disconnect(mut self) -> Self {
+        self.dump_on_disconnect = true;
+        self
+    }
+
+    /// Returns the maximum number of entries kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the entries, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Formats the entries one per line, timed relative to the oldest.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        let first_opt = self.entries.front().map(|entry| entry.at);
+        for entry in &self.entries {
+            let elapsed = first_opt
+                .map(|first| entry.at.saturating_duration_since(first))
+                .unwrap_or_default();
+            let _ = writeln!(dump, "+{:?} {}", elapsed, entry.event);
+        }
+        dump
+    }
+
+    fn record(&mut self, at: Instant, event: AuditEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(AuditEntry { at, event });
+    }
+}
+
+/// Records a transition in the connection's [`AuditLog`], if it has one.
+pub(crate) struct RecordTransition(pub(crate) ConnectionTransition);
+
+impl Command for RecordTransition {
+    fn write(self, world: &mut World) {
+        let now = match (
+            world.get_resource::<Time>(),
+            world.get_resource::<NetworkClock>(),
+        ) {
+            (Some(time), Some(clock)) => clock.now(time),
+            _ => None,
+        }
+        .unwrap_or_else(Instant::now);
+
+        let transition = self.0;
+        let mut log = match world.get_mut::<AuditLog>(transition.connection) {
+            Some(log) => log,
+            None => return,
+        };
+        log.record(
+            now,
+            AuditEvent::Transition {
+                from: transition.from,
+                to: transition.to,
+            },
+        );
+
+        let disconnected = transition.from.is_none_or(|from| !from.is_disconnected())
+            && transition.to.is_disconnected();
+        if disconnected && log.dump_on_disconnect {
+            info!(message = "connection audit log", connection = ?transition.connection, address = %transition.address, log = %log.dump());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_audit_events(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    index: Res<ConnectionIndex>,
+    mut log_query: Query<&mut AuditLog>,
+    mut send_error_reader: EventReader<SendError>,
+    mut traffic_reader: EventReader<DisconnectedTraffic>,
+    mut expired_reader: EventReader<SessionExpired>,
+    mut rejected_reader: EventReader<AddressRejected>,
+) {
+    let now = clock.now(&time).unwrap_or_else(Instant::now);
+
+    let mut record = |connection: Entity, event: AuditEvent| {
+        if let Ok(mut log) = log_query.get_mut(connection) {
+            log.record(now, event);
+        }
+    };
+
+    for error in send_error_reader.iter() {
+        for connection in index.get(error.socket, error.address) {
+            record(*connection, AuditEvent::SendError(error.error.to_string()));
+        }
+    }
+    for traffic in traffic_reader.iter() {
+        record(
+            traffic.connection,
+            AuditEvent::DisconnectedTraffic {
+                packets: traffic.packets,
+            },
+        );
+    }
+    for expired in expired_reader.iter() {
+        record(expired.connection, AuditEvent::SessionExpired);
+    }
+    for rejected in rejected_reader.iter() {
+        for connection in index.get(rejected.socket, rejected.address) {
+            record(*connection, AuditEvent::AddressRejected);
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use hmac::{Hmac, Mac};
+use laminar::Packet;
+use sha2::Sha256;
+
+use crate::{
+    connection::QueuedPayload,
+    handshake::{self, PUBLIC_LEN},
+    wire::AUTH_TAG,
+    ConnectionMarker, ConnectionSendQueue, ConnectionState, Delivery, HostId, SocketId,
+    SocketMarker,
+};
+#[cfg(feature = "encryption")]
+use crate::{encryption::KeyExchange, Protocol};
+
+/// Credentials are resent by laminar until acknowledged.
+const AUTH_DELIVERY: Delivery = Delivery::ReliableUnordered;
+
+/// The length of the body of a signed connect token: client id and expiry.
+const BODY_LEN: usize = 8 + 8;
+
+/// The length of a signed connect token: body and signature.
+const SIGNED_LEN: usize = BODY_LEN + 32;
+
+/// Packets received from a peer before it authenticates are held back, up to this many.
+const MAX_HELD: usize = 64;
+
+/// Peers waiting to authenticate are tracked, up to this many per socket.
+const MAX_PENDING: usize = 1024;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A connect token to be signed by a trusted party, such as a matchmaker sharing its key with the
+/// servers, and handed to a client to present as its [`Credentials`].
+///
+/// Once verified by an [`Authenticator::signed`], the client id is inserted on the connection
+/// entity as a [`ClientId`]. Tokens may be presented by anyone holding them until they expire, so
+/// they should be kept short-lived. Only encrypted protocols keep the signature off the wire, see
+/// [`Credentials`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectToken {
+    /// The id of the client the token was issued to.
+    pub client_id: u64,
+    /// The time after which the token is refused.
+    pub expires_at: SystemTime,
+}
+
+impl ConnectToken {
+    /// Creates a new [`ConnectToken`] expiring `valid_for` from now.
+    pub fn new(client_id: u64, valid_for: Duration) -> Self {
+        Self {
+            client_id,
+            expires_at: SystemTime::now() + valid_for,
+        }
+    }
+
+    /// Signs the token with HMAC-SHA256 under `key`, returning its encoding.
+    pub fn sign(&self, key: &[u8]) -> Vec<u8> {
+        let expires_at = self
+            .expires_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |expiry| expiry.as_secs());
+
+        let mut token = Vec::with_capacity(SIGNED_LEN);
+        token.extend_from_slice(&self.client_id.to_be_bytes());
+        token.extend_from_slice(&expires_at.to_be_bytes());
+        let signature = mac(key, &token).finalize().into_bytes();
+        token.extend_from_slice(&signature);
+        token
+    }
+}
+
+fn mac(key: &[u8], body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&AUTH_TAG);
+    mac.update(body);
+    mac
+}
+
+/// A [`Component`] which, when present on a connection entity, or on a socket entity for all of
+/// its connections, presents a token to the peer ahead of any other payload, see
+/// [`Authenticator`].
+///
+/// Without encryption the token is sent as is, and may be replayed by anyone capturing it. When
+/// the [`Protocol`](crate::Protocol) is encrypted, the token is instead bound to the public key
+/// the connection offers in its handshake: a pre-shared token is replaced by its HMAC over the
+/// key, and the signature of a [`ConnectToken`] likewise. The peer checks the binding against the
+/// key offered, then refuses to complete the handshake with any other, so that captured
+/// credentials only ever open sessions keyed for their owner.
+#[derive(Clone, Component, PartialEq, Eq, Hash)]
+pub struct Credentials {
+    method: Method,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials").finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    /// Creates new [`Credentials`] presenting `token` to an [`Authenticator::pre_shared`].
+    pub fn pre_shared(token: impl Into<Vec<u8>>) -> Self {
+        Self {
+            method: Method::PreShared(token.into()),
+        }
+    }
+
+    /// Creates new [`Credentials`] presenting a [`ConnectToken`], as encoded by
+    /// [`ConnectToken::sign`], to an [`Authenticator::signed`].
+    pub fn signed(token: impl Into<Vec<u8>>) -> Self {
+        Self {
+            method: Method::Signed(token.into()),
+        }
+    }
+
+    /// Frames the token, bound to `key_opt` should the handshake offer a public key.
+    fn frame(&self, key_opt: Option<&[u8; PUBLIC_LEN]>) -> Vec<u8> {
+        let mut frame = AUTH_TAG.to_vec();
+        match (&self.method, key_opt) {
+            (Method::PreShared(token) | Method::Signed(token), None) => {
+                frame.extend_from_slice(token)
+            }
+            (Method::PreShared(token), Some(key)) => {
+                frame.extend(mac(token, key).finalize().into_bytes())
+            }
+            (Method::Signed(token), Some(key)) => {
+                let (body, signature) = token.split_at(BODY_LEN.min(token.len()));
+                frame.extend_from_slice(body);
+                frame.extend(mac(signature, key).finalize().into_bytes());
+            }
+        }
+        frame
+    }
+}
+
+/// A [`Component`] holding the id of a client authenticated with a signed [`ConnectToken`].
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct ClientId(pub u64);
+
+/// An event emitted when a peer fails to authenticate with a socket's [`Authenticator`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuthenticationFailed {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// Why the peer failed to authenticate.
+    pub reason: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Method {
+    PreShared(Vec<u8>),
+    Signed(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct PendingPeer {
+    since: Option<Instant>,
+    state: Option<ConnectionState>,
+    packets: VecDeque<Packet>,
+    /// Bound credentials, kept until the handshake offers the key they are bound to.
+    token: Option<Vec<u8>>,
+    key: Option<[u8; PUBLIC_LEN]>,
+}
+
+/// A [`Component`] which, when present on a socket entity, requires peers to present
+/// [`Credentials`] before a connection entity is spawned for them.
+///
+/// Until then, packets from a peer are held back and its connection is invisible to every
+/// system. Peers presenting an invalid token, or none within the timeout, are dropped along with
+/// their packets and an [`AuthenticationFailed`] event is emitted. They are never answered, so
+/// anonymous traffic is not reflected back.
+///
+/// Connections opened with [`Connect`](crate::Connect) are trusted, as are connections which
+/// already exist when the component is inserted. When the [`Protocol`](crate::Protocol) is
+/// encrypted, peers must present [`Credentials`] bound to the key of their handshake, which are
+/// only checked once their hello frame is received as well.
+#[derive(Clone, Component)]
+pub struct Authenticator {
+    method: Method,
+    timeout: Duration,
+    pending: HashMap<(SocketAddr, Option<HostId>), PendingPeer>,
+}
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let method = match self.method {
+            Method::PreShared(_) => "PreShared",
+            Method::Signed(_) => "Signed",
+        };
+        f.debug_struct("Authenticator")
+            .field("method", &method)
+            .field("timeout", &self.timeout)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+pub(crate) enum Verdict {
+    Pending,
+    Accepted {
+        packets: VecDeque<Packet>,
+        state: Option<ConnectionState>,
+        client_id: Option<ClientId>,
+        /// The public key the credentials were bound to, which the handshake must offer.
+        key_opt: Option<[u8; PUBLIC_LEN]>,
+    },
+    Failed(String),
+}
+
+impl Authenticator {
+    /// Creates a new [`Authenticator`] accepting peers presenting exactly `token`.
+    pub fn pre_shared(token: impl Into<Vec<u8>>) -> Self {
+        Self::with_method(Method::PreShared(token.into()))
+    }
+
+    /// Creates a new [`Authenticator`] accepting peers presenting an unexpired [`ConnectToken`]
+    /// signed under `key`.
+    pub fn signed(key: impl Into<Vec<u8>>) -> Self {
+        Self::with_method(Method::Signed(key.into()))
+    }
+
+    fn with_method(method: Method) -> Self {
+        Self {
+            method,
+            timeout: DEFAULT_TIMEOUT,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Sets how long peers are given to authenticate, defaulting to 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the number of peers waiting to authenticate.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn verify(
+        &self,
+        token: &[u8],
+        key_opt: Option<&[u8; PUBLIC_LEN]>,
+    ) -> Result<Option<ClientId>, String> {
+        match (&self.method, key_opt) {
+            (Method::PreShared(expected), Some(key)) => mac(expected, key)
+                .verify_slice(token)
+                .map(|_| None)
+                .map_err(|_| "invalid token".to_string()),
+            (Method::PreShared(expected), None) => {
+                // Compare every byte, so that timing does not reveal the matching prefix
+                let matches = token.len() == expected.len()
+                    && token
+                        .iter()
+                        .zip(expected)
+                        .fold(0, |diff, (lhs, rhs)| diff | (lhs ^ rhs))
+                        == 0;
+                if matches {
+                    Ok(None)
+                } else {
+                    Err("invalid token".to_string())
+                }
+            }
+            (Method::Signed(secret), key_opt) => {
+                // A bound token replaces its signature with a MAC of the same length
+                if token.len() != SIGNED_LEN {
+                    return Err("malformed connect token".to_string());
+                }
+                let (body, signature) = token.split_at(BODY_LEN);
+                let result = match key_opt {
+                    Some(key) => {
+                        let expected = mac(secret, body).finalize().into_bytes();
+                        mac(&expected, key).verify_slice(signature)
+                    }
+                    None => mac(secret, body).verify_slice(signature),
+                };
+                result.map_err(|_| "invalid signature".to_string())?;
+
+                let client_id = u64::from_be_bytes(body[..8].try_into().unwrap());
+                let expires_at = u64::from_be_bytes(body[8..].try_into().unwrap());
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |now| now.as_secs());
+                if expires_at < now {
+                    return Err("connect token expired".to_string());
+                }
+                Ok(Some(ClientId(client_id)))
+            }
+        }
+    }
+
+    /// Looks for credentials among the packets of a peer without a connection, holding them back
+    /// until they are found.
+    ///
+    /// When `is_bound`, credentials are bound to the public key of the peer's handshake, and only
+    /// checked once its hello frame is found as well.
+    pub(crate) fn admit(
+        &mut self,
+        address: SocketAddr,
+        host_opt: Option<HostId>,
+        packets: VecDeque<Packet>,
+        state_opt: Option<ConnectionState>,
+        now: Option<Instant>,
+        is_bound: bool,
+    ) -> Verdict {
+        let key = (address, host_opt);
+        if !self.pending.contains_key(&key) && self.pending.len() >= MAX_PENDING {
+            trace!(message = "too many peers authenticating", %address);
+            return Verdict::Pending;
+        }
+
+        let mut peer = self.pending.remove(&key).unwrap_or_else(|| PendingPeer {
+            since: now,
+            state: None,
+            packets: VecDeque::new(),
+            token: None,
+            key: None,
+        });
+        peer.since = peer.since.or(now);
+        peer.state = state_opt.or(peer.state);
+
+        let mut packets = packets.into_iter();
+        for packet in packets.by_ref() {
+            let token = match packet.payload().strip_prefix(&AUTH_TAG) {
+                Some(token) => token,
+                None => {
+                    if is_bound && peer.key.is_none() {
+                        peer.key = handshake::offered_key(packet.payload());
+                    }
+                    if peer.packets.len() < MAX_HELD {
+                        peer.packets.push_back(packet);
+                    } else {
+                        trace!(message = "dropping packet before authentication", %address);
+                    }
+                    continue;
+                }
+            };
+            if is_bound {
+                peer.token.get_or_insert_with(|| token.to_vec());
+                continue;
+            }
+
+            return match self.verify(token, None) {
+                Ok(client_id) => {
+                    trace!(message = "peer authenticated", %address);
+                    peer.packets.extend(packets);
+                    Verdict::Accepted {
+                        packets: peer.packets,
+                        state: peer.state,
+                        client_id,
+                        key_opt: None,
+                    }
+                }
+                Err(reason) => Verdict::Failed(reason),
+            };
+        }
+
+        if let (Some(token), Some(public)) = (&peer.token, peer.key) {
+            return match self.verify(token, Some(&public)) {
+                Ok(client_id) => {
+                    trace!(message = "peer authenticated", %address);
+                    Verdict::Accepted {
+                        packets: peer.packets,
+                        state: peer.state,
+                        client_id,
+                        key_opt: Some(public),
+                    }
+                }
+                Err(reason) => Verdict::Failed(reason),
+            };
+        }
+
+        self.pending.insert(key, peer);
+        Verdict::Pending
+    }
+
+    /// Drops peers which have not authenticated within the timeout, returning their addresses.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<SocketAddr> {
+        let timeout = self.timeout;
+        let mut expired = Vec::new();
+        self.pending.retain(|(address, _), peer| {
+            let since = *peer.since.get_or_insert(now);
+            let keep = now.saturating_duration_since(since) < timeout;
+            if !keep {
+                expired.push(*address);
+            }
+            keep
+        });
+        expired
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn send_credentials(
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            Option<&Credentials>,
+            &mut ConnectionSendQueue,
+        ),
+        Added<ConnectionMarker>,
+    >,
+    credentials_query: Query<&Credentials, With<SocketMarker>>,
+    #[cfg(feature = "encryption")] protocol_opt: Option<Res<Protocol>>,
+    #[cfg(feature = "encryption")] exchange_query: Query<&KeyExchange>,
+    #[cfg(feature = "encryption")] mut commands: Commands,
+) {
+    for (connection, socket_id, credentials_opt, mut send_queue) in connection_query.iter_mut() {
+        let credentials = match credentials_opt.or_else(|| credentials_query.get(socket_id.0).ok())
+        {
+            Some(some) => some,
+            None => continue,
+        };
+
+        // Bind the credentials to the key of the handshake, generating it should the handshake
+        // not have started yet
+        #[cfg(feature = "encryption")]
+        let key_opt = protocol_opt
+            .as_ref()
+            .filter(|protocol| protocol.is_encrypted())
+            .map(|_| match exchange_query.get(connection) {
+                Ok(exchange) => exchange.public(),
+                Err(_) => {
+                    let exchange = KeyExchange::new();
+                    let public = exchange.public();
+                    commands.entity(connection).insert(exchange);
+                    public
+                }
+            });
+        #[cfg(not(feature = "encryption"))]
+        let key_opt = {
+            let _ = connection;
+            None
+        };
+
+        send_queue.payloads.insert(
+            0,
+            QueuedPayload::control(AUTH_DELIVERY, credentials.frame(key_opt.as_ref())),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"matchmaker secret";
+
+    /// Returns the token within a credentials frame.
+    fn token(credentials: &Credentials, key_opt: Option<&[u8; PUBLIC_LEN]>) -> Vec<u8> {
+        credentials.frame(key_opt)[AUTH_TAG.len()..].to_vec()
+    }
+
+    #[test]
+    fn signed_token() {
+        let authenticator = Authenticator::signed(SECRET);
+        let signed = ConnectToken::new(42, Duration::from_secs(60)).sign(SECRET);
+        let token = token(&Credentials::signed(signed), None);
+        assert_eq!(authenticator.verify(&token, None), Ok(Some(ClientId(42))));
+    }
+
+    #[test]
+    fn bad_signature() {
+        let authenticator = Authenticator::signed(SECRET);
+
+        let forged = ConnectToken::new(42, Duration::from_secs(60)).sign(b"another secret");
+        assert_eq!(
+            authenticator.verify(&forged, None),
+            Err("invalid signature".to_string())
+        );
+
+        // The signature covers the client id
+        let mut tampered = ConnectToken::new(42, Duration::from_secs(60)).sign(SECRET);
+        tampered[7] ^= 1;
+        assert_eq!(
+            authenticator.verify(&tampered, None),
+            Err("invalid signature".to_string())
+        );
+    }
+
+    #[test]
+    fn expired_token() {
+        let authenticator = Authenticator::signed(SECRET);
+        let expired = ConnectToken {
+            client_id: 42,
+            expires_at: SystemTime::now() - Duration::from_secs(60),
+        };
+        assert_eq!(
+            authenticator.verify(&expired.sign(SECRET), None),
+            Err("connect token expired".to_string())
+        );
+    }
+
+    #[test]
+    fn token_bound_to_another_key() {
+        let key = [1; PUBLIC_LEN];
+        let other = [2; PUBLIC_LEN];
+
+        let signed = ConnectToken::new(42, Duration::from_secs(60)).sign(SECRET);
+        let credentials = Credentials::signed(signed.clone());
+        let authenticator = Authenticator::signed(SECRET);
+        let bound = token(&credentials, Some(&key));
+        // Binding replaces the signature, which is never sent
+        assert_ne!(bound, signed);
+        assert_eq!(
+            authenticator.verify(&bound, Some(&key)),
+            Ok(Some(ClientId(42)))
+        );
+        assert_eq!(
+            authenticator.verify(&bound, Some(&other)),
+            Err("invalid signature".to_string())
+        );
+
+        let credentials = Credentials::pre_shared(b"token".to_vec());
+        let authenticator = Authenticator::pre_shared(b"token".to_vec());
+        let bound = token(&credentials, Some(&key));
+        assert_eq!(authenticator.verify(&bound, Some(&key)), Ok(None));
+        assert_eq!(
+            authenticator.verify(&bound, Some(&other)),
+            Err("invalid token".to_string())
+        );
+        assert_eq!(
+            authenticator.verify(b"token", Some(&key)),
+            Err("invalid token".to_string())
+        );
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn bound_credentials_wait_for_hello() {
+        let addr = "127.0.0.1:4000".parse().unwrap();
+        let key = [1; PUBLIC_LEN];
+        let credentials = Credentials::pre_shared(b"token".to_vec());
+        let mut authenticator = Authenticator::pre_shared(b"token".to_vec());
+
+        let frame = Packet::reliable_unordered(addr, credentials.frame(Some(&key)));
+        let verdict = authenticator.admit(addr, None, [frame].into(), None, None, true);
+        assert!(matches!(verdict, Verdict::Pending));
+        assert_eq!(authenticator.pending(), 1);
+
+        let protocol = Protocol::new(7, 1).with_encryption();
+        let hello = protocol.frame(handshake::HELLO_KIND, Some(key), None);
+        let hello = Packet::reliable_unordered(addr, hello);
+        let verdict = authenticator.admit(addr, None, [hello].into(), None, None, true);
+        assert!(matches!(
+            verdict,
+            Verdict::Accepted { packets, key_opt: Some(bound), .. }
+                if packets.len() == 1 && bound == key
+        ));
+        assert_eq!(authenticator.pending(), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+};
+
+use bevy::prelude::*;
+use laminar::Packet;
+
+use crate::{ConnectionSendQueue, Delivery, ReceiveQueue, SendQueue};
+
+/// Laminar's default stream, used by deliveries without a stream id.
+const DEFAULT_STREAM: u8 = u8::MAX;
+
+/// A named logical channel carried on its own laminar stream, see [`AddChannel`].
+///
+/// Channels are obtained from the [`Channels`] resource. Peers must register the same channels in
+/// the same order for their streams to agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Channel {
+    delivery: Delivery,
+}
+
+impl Channel {
+    /// Returns the [`Delivery`] of the channel, including its stream id.
+    pub fn delivery(&self) -> Delivery {
+        self.delivery
+    }
+
+    /// Returns the laminar stream id of the channel.
+    pub fn stream_id(&self) -> u8 {
+        match self.delivery {
+            Delivery::UnreliableSequenced(Some(stream_id))
+            | Delivery::ReliableSequenced(Some(stream_id))
+            | Delivery::ReliableOrdered(Some(stream_id)) => stream_id,
+            _ => DEFAULT_STREAM,
+        }
+    }
+
+    /// Creates a [`Packet`] to `addr` carrying `payload` on the channel.
+    pub fn packet(self, addr: SocketAddr, payload: Vec<u8>) -> Packet {
+        self.delivery.packet(addr, payload)
+    }
+
+    /// Returns `true` if the received `packet` arrived on the channel.
+    pub fn carries(&self, packet: &Packet) -> bool {
+        Delivery::from(packet) == self.delivery
+    }
+}
+
+/// A resource holding the [`Channel`]s registered with [`AddChannel`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Channels {
+    channels: HashMap<String, Channel>,
+}
+
+impl Channels {
+    /// Returns the channel registered under `name`.
+    pub fn get(&self, name: &str) -> Option<Channel> {
+        self.channels.get(name).copied()
+    }
+
+    /// Iterates over the names and channels registered.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Channel)> {
+        self.channels
+            .iter()
+            .map(|(name, channel)| (name.as_str(), *channel))
+    }
+
+    /// Returns the number of channels registered.
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns `true` if no channels are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn register(&mut self, name: String, delivery: Delivery) -> Option<Channel> {
+        let stream_id = match self.channels.get(&name) {
+            Some(channel) => channel.stream_id(),
+            None => u8::try_from(self.channels.len())
+                .ok()
+                .filter(|stream_id| *stream_id != DEFAULT_STREAM)?,
+        };
+        let delivery = match delivery {
+            Delivery::UnreliableSequenced(_) => Delivery::UnreliableSequenced(Some(stream_id)),
+            Delivery::ReliableSequenced(_) => Delivery::ReliableSequenced(Some(stream_id)),
+            Delivery::ReliableOrdered(_) => Delivery::ReliableOrdered(Some(stream_id)),
+            Delivery::Unreliable | Delivery::ReliableUnordered => return None,
+        };
+
+        let channel = Channel { delivery };
+        self.channels.insert(name, channel);
+        Some(channel)
+    }
+}
+
+/// An extension trait for [`App`] registering logical channels.
+pub trait AddChannel {
+    /// Registers a [`Channel`] named `name`, allocating it a laminar stream.
+    ///
+    /// Only sequenced and ordered deliveries carry a stream id, so other deliveries, or
+    /// registering more than 255 channels, log an error and register nothing. The stream id of
+    /// `delivery` is ignored. Registering a name again replaces its delivery, keeping its stream.
+    fn add_channel(&mut self, name: impl Into<String>, delivery: Delivery) -> &mut Self;
+}
+
+impl AddChannel for App {
+    fn add_channel(&mut self, name: impl Into<String>, delivery: Delivery) -> &mut Self {
+        let name = name.into();
+        let mut channels = self.world.get_resource_or_insert_with(Channels::default);
+        if channels.register(name.clone(), delivery).is_none() {
+            error!(message = "failed to register channel", %name, ?delivery);
+        }
+        self
+    }
+}
+
+impl SendQueue {
+    /// Sends a payload to a peer on the given [`Channel`].
+    pub fn send_on(&mut self, channel: Channel, addr: SocketAddr, payload: Vec<u8>) {
+        self.send_with(channel.delivery(), addr, payload)
+    }
+}
+
+impl ConnectionSendQueue {
+    /// Sends a payload to the peer on the given [`Channel`].
+    pub fn send_on(&mut self, channel: Channel, payload: Vec<u8>) {
+        self.send(channel.delivery(), payload)
+    }
+}
+
+impl ReceiveQueue {
+    /// Iterates over the stored packets which arrived on `channel`.
+    pub fn iter_channel(&self, channel: Channel) -> impl Iterator<Item = &Packet> {
+        self.iter().filter(move |packet| channel.carries(packet))
+    }
+
+    /// Iterates over the stored packets which arrived on `channel` while consuming them, leaving
+    /// other packets in place.
+    pub fn drain_channel(&mut self, channel: Channel) -> impl Iterator<Item = Packet> {
+        let (drained, kept): (VecDeque<_>, VecDeque<_>) =
+            self.0.drain(..).partition(|packet| channel.carries(packet));
+        self.0 = kept;
+        drained.into_iter()
+    }
+}
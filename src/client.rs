@@ -0,0 +1,158 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    descriptor::spawn_descriptor, Connect, ConnectionAddress, ConnectionIndex, ConnectionOptions,
+    Credentials, Delivery, DescriptorBindError, NetworkPlugin, NetworkStage, NewConnection,
+    SocketDescriptor,
+};
+
+/// The poll interval of sockets spawned by the [`ClientPlugin`] and
+/// [`ServerPlugin`](crate::ServerPlugin), unless overridden.
+pub(crate) const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A [`Plugin`] setting up a client connected to a single server.
+///
+/// At startup an ephemeral socket is bound, a connection to the server is opened with
+/// [`Connect::connect_with_handshake`], and the [`ServerConnection`] resource is inserted. By
+/// default the handshake is an empty payload, sent reliably, which spawns the client's connection
+/// on the server and reaches its [`ReceiveQueue`](crate::ReceiveQueue). The connection moves on
+/// from [`ConnectionState::Connecting`](crate::ConnectionState::Connecting) once the server sends
+/// something back. Should the socket fail to bind, a [`DescriptorBindError`] event is emitted
+/// instead. With the `serde` feature, the connection to the server is given `AcceptSettings`,
+/// applying the `ClientSettings` the server pushes, and ignoring those of any other peer.
+///
+/// The [`NetworkPlugin`] is added with its defaults, unless it was added beforehand.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientPlugin {
+    server: SocketAddr,
+    descriptor: SocketDescriptor,
+    handshake: (Delivery, Vec<u8>),
+    credentials: Option<Credentials>,
+}
+
+impl ClientPlugin {
+    /// Creates a new [`ClientPlugin`] connecting to `server`.
+    pub fn connect(server: SocketAddr) -> Self {
+        let unspecified = if server.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        Self {
+            server,
+            descriptor: SocketDescriptor::new(unspecified, DEFAULT_POLL_INTERVAL),
+            handshake: (Delivery::ReliableUnordered, Vec::new()),
+            credentials: None,
+        }
+    }
+
+    /// Sets the address to bind to, defaulting to an ephemeral port on all interfaces.
+    pub fn with_bind_address(mut self, address: SocketAddr) -> Self {
+        self.descriptor.address = address;
+        self
+    }
+
+    /// Sets the socket's [`PollInterval`](crate::PollInterval), defaulting to 10 milliseconds.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.descriptor.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the socket's [`ConnectionOptions`].
+    pub fn with_options(mut self, options: ConnectionOptions) -> Self {
+        self.descriptor.options = options;
+        self
+    }
+
+    /// Sets the payload sent to the server once the connection is opened.
+    pub fn with_handshake(mut self, delivery: Delivery, payload: Vec<u8>) -> Self {
+        self.handshake = (delivery, payload);
+        self
+    }
+
+    /// Sets the [`Credentials`] presented to the server, inserted on the socket.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        if app.world.get_resource::<ConnectionIndex>().is_none() {
+            app.add_plugin(NetworkPlugin::always());
+        }
+
+        let plugin = self.clone();
+        let start_client = move |mut error_writer: EventWriter<DescriptorBindError>,
+                                 mut commands: Commands| {
+            let socket =
+                match spawn_descriptor(plugin.descriptor.clone(), &mut error_writer, &mut commands)
+                {
+                    Some(some) => some,
+                    None => return,
+                };
+            if let Some(credentials) = plugin.credentials.clone() {
+                commands.entity(socket).insert(credentials);
+            }
+
+            let (delivery, payload) = plugin.handshake.clone();
+            let connection =
+                commands.connect_with_handshake(socket, plugin.server, delivery, payload);
+            #[cfg(feature = "serde")]
+            commands.entity(connection).insert(crate::AcceptSettings);
+            commands.insert_resource(ServerConnection {
+                socket,
+                connection,
+                address: plugin.server,
+            });
+        };
+
+        app.add_startup_system(start_client)
+            .add_system_to_stage(NetworkStage::PostRecv, track_server_connection);
+    }
+}
+
+/// A resource holding the connection to the server, inserted by the [`ClientPlugin`].
+///
+/// Should the server be heard from again after its connection was despawned, for example by a
+/// [`DespawnPolicy`](crate::DespawnPolicy), the resource is updated with the new connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServerConnection {
+    /// The client's socket entity.
+    pub socket: Entity,
+    /// The connection entity to the server.
+    pub connection: Entity,
+    /// The server's address.
+    pub address: SocketAddr,
+}
+
+fn track_server_connection(
+    server_opt: Option<ResMut<ServerConnection>>,
+    mut new_reader: EventReader<NewConnection>,
+    #[cfg(feature = "serde")] mut commands: Commands,
+) {
+    let mut server = match server_opt {
+        Some(some) => some,
+        None => return,
+    };
+
+    for new in new_reader.iter() {
+        if new.socket == server.socket
+            && new.connection != server.connection
+            && ConnectionAddress(new.address).matches(&server.address)
+        {
+            trace!(message = "server connection respawned", connection = ?new.connection);
+            server.connection = new.connection;
+            #[cfg(feature = "serde")]
+            commands
+                .entity(new.connection)
+                .insert(crate::AcceptSettings);
+        }
+    }
+}
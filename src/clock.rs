@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+/// A resource offsetting the time seen by the network systems from bevy's [`Time`].
+///
+/// Sockets are polled and sessions expire against this clock, so advancing it lets tests of idle
+/// timeouts, heartbeats and [`SessionTtl`](crate::SessionTtl) run without waiting in real time.
+/// The offset only grows, keeping the clock monotonic.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetworkClock {
+    offset: Duration,
+}
+
+impl NetworkClock {
+    /// Moves the clock forward by `duration`, taking effect from the next network update.
+    pub fn advance(&mut self, duration: Duration) {
+        self.offset += duration;
+    }
+
+    /// Returns the total duration the clock has been advanced by.
+    pub fn offset(&self) -> Duration {
+        self.offset
+    }
+
+    /// Returns the current network time, or `None` if [`Time`] has not been updated yet.
+    pub fn now(&self, time: &Time) -> Option<Instant> {
+        time.last_update().map(|instant| instant + self.offset)
+    }
+}
@@ -0,0 +1,227 @@
+use std::{convert::TryInto, mem};
+
+use bevy::prelude::*;
+use zstd::{
+    bulk::{Compressor, Decompressor},
+    dict::{DecoderDictionary, EncoderDictionary},
+};
+
+use crate::{
+    group, packet::with_payload, wire::COMPRESSED_TAG, ConnectionMarker, ConnectionSendQueue,
+    Protocol, ReceiveQueue,
+};
+
+const PLAIN_KIND: u8 = 0;
+const DICTIONARY_KIND: u8 = 1;
+
+/// Decompressed payloads larger than this are dropped, so that small packets cannot expand into
+/// large allocations.
+const MAX_DECOMPRESSED_LEN: usize = 64 * 1024;
+
+/// A [`Component`] holding the compression negotiated with the peer of a connection during the
+/// handshake, see [`Protocol::with_compression`].
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// Payloads are sent as is.
+    None,
+    /// Payloads are compressed with zstd, using the [`CompressionDictionary`] of the given id
+    /// should both peers share it.
+    Zstd {
+        /// The id of the shared dictionary.
+        dictionary: Option<u32>,
+    },
+}
+
+impl Compression {
+    /// Returns the compression both peers support, preferring a shared dictionary.
+    pub(crate) fn negotiate(
+        protocol: &Protocol,
+        dictionary_opt: Option<u32>,
+        peer: &Protocol,
+        peer_dictionary_opt: Option<u32>,
+    ) -> Self {
+        if !protocol.is_compressed() || !peer.is_compressed() {
+            return Self::None;
+        }
+        Self::Zstd {
+            dictionary: dictionary_opt.filter(|_| dictionary_opt == peer_dictionary_opt),
+        }
+    }
+}
+
+/// A resource holding a zstd dictionary, trained on payloads typical of the app, which
+/// connections compress with when their peer holds the same dictionary id.
+///
+/// Small payloads, such as those of most games, compress poorly on their own and markedly better
+/// with a dictionary. Dictionaries may be trained with `zstd --train` or `zstd::dict::from_samples`.
+/// The id is advertised during the handshake and must change along with the dictionary.
+pub struct CompressionDictionary {
+    id: u32,
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+impl std::fmt::Debug for CompressionDictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressionDictionary")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CompressionDictionary {
+    /// Creates a new [`CompressionDictionary`] from the bytes of a zstd dictionary.
+    pub fn new(id: u32, dictionary: &[u8]) -> Self {
+        Self {
+            id,
+            encoder: EncoderDictionary::copy(dictionary, zstd::DEFAULT_COMPRESSION_LEVEL),
+            decoder: DecoderDictionary::copy(dictionary),
+        }
+    }
+
+    /// Returns the id advertised to peers.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Compresses the payloads queued on connections which negotiated [`Compression::Zstd`], keeping
+/// those which would not shrink as they are.
+#[allow(clippy::type_complexity)]
+pub(crate) fn compress_payloads(
+    dictionary_opt: Option<Res<CompressionDictionary>>,
+    mut connection_query: Query<
+        (&Compression, &mut ConnectionSendQueue),
+        (With<ConnectionMarker>, Changed<ConnectionSendQueue>),
+    >,
+) {
+    // Contexts are only created once a payload needs them
+    let mut plain = None;
+    let mut with_dictionary = None;
+
+    for (compression, mut queue) in connection_query.iter_mut() {
+        let dictionary_id_opt = match compression {
+            Compression::Zstd { dictionary } if !queue.is_empty() => *dictionary,
+            _ => continue,
+        };
+        // The dictionary may have been replaced since the handshake
+        let dictionary_opt = dictionary_id_opt.and_then(|id| {
+            dictionary_opt
+                .as_ref()
+                .filter(|dictionary| dictionary.id == id)
+        });
+
+        for queued in queue.payloads.iter_mut() {
+            let payload = &queued.payload;
+            if queued.control || payload.starts_with(&COMPRESSED_TAG) {
+                continue;
+            }
+
+            let mut frame = COMPRESSED_TAG.to_vec();
+            let compressed = match dictionary_opt {
+                Some(dictionary) => {
+                    frame.push(DICTIONARY_KIND);
+                    frame.extend_from_slice(&dictionary.id.to_be_bytes());
+                    with_dictionary
+                        .get_or_insert_with(|| {
+                            Compressor::with_prepared_dictionary(&dictionary.encoder)
+                        })
+                        .as_mut()
+                        .ok()
+                        .and_then(|compressor| compressor.compress(payload).ok())
+                }
+                None => {
+                    frame.push(PLAIN_KIND);
+                    plain
+                        .get_or_insert_with(|| Compressor::new(zstd::DEFAULT_COMPRESSION_LEVEL))
+                        .as_mut()
+                        .ok()
+                        .and_then(|compressor| compressor.compress(payload).ok())
+                }
+            };
+
+            match compressed {
+                Some(compressed) if frame.len() + compressed.len() < payload.len() => {
+                    frame.extend_from_slice(&compressed);
+                    queued.payload = frame;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Decompresses the packets received by connections, after their handshake has released them.
+///
+/// Compressed packets are recognized by their tag alone, so that packets compressed before a
+/// peer restarted its side of the handshake are still read.
+#[allow(clippy::type_complexity)]
+pub(crate) fn decompress_packets(
+    dictionary_opt: Option<Res<CompressionDictionary>>,
+    mut connection_query: Query<
+        (Entity, &mut ReceiveQueue),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+) {
+    let mut plain = None;
+    let mut with_dictionary = None;
+
+    for (connection, mut queue) in connection_query.iter_mut() {
+        // Avoid flagging the queue as changed unless there are packets to decompress
+        if !queue
+            .iter()
+            .any(|packet| packet.payload().starts_with(&COMPRESSED_TAG))
+        {
+            continue;
+        }
+
+        let mut decompress = |body: &[u8]| -> Option<Vec<u8>> {
+            let (kind, body) = body.split_first()?;
+            match *kind {
+                PLAIN_KIND => plain
+                    .get_or_insert_with(Decompressor::new)
+                    .as_mut()
+                    .ok()?
+                    .decompress(body, MAX_DECOMPRESSED_LEN)
+                    .ok(),
+                DICTIONARY_KIND if body.len() >= 4 => {
+                    let (id, body) = body.split_at(4);
+                    let id = u32::from_be_bytes(id.try_into().ok()?);
+                    let dictionary = dictionary_opt
+                        .as_ref()
+                        .filter(|dictionary| dictionary.id == id)?;
+                    with_dictionary
+                        .get_or_insert_with(|| {
+                            Decompressor::with_prepared_dictionary(&dictionary.decoder)
+                        })
+                        .as_mut()
+                        .ok()?
+                        .decompress(body, MAX_DECOMPRESSED_LEN)
+                        .ok()
+                }
+                _ => None,
+            }
+        };
+
+        queue.0 = mem::take(&mut queue.0)
+            .into_iter()
+            .flat_map(|packet| {
+                let body = match packet.payload().strip_prefix(&COMPRESSED_TAG) {
+                    Some(body) => body,
+                    None => return vec![packet],
+                };
+                match decompress(body) {
+                    // Groups are only split once decompressed
+                    Some(payload) => group::split(with_payload(&packet, payload)),
+                    None => {
+                        trace!(
+                            message = "dropping packet failing to decompress",
+                            ?connection
+                        );
+                        Vec::new()
+                    }
+                }
+            })
+            .collect();
+    }
+}
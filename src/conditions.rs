@@ -0,0 +1,213 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use laminar::Packet;
+
+use crate::Delivery;
+
+/// A small xorshift generator, enough to draw losses and jitter reproducibly from a seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Spread the seed with a splitmix64 step, xorshift must not start from zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    /// Returns a number in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.next_f32() < probability
+    }
+}
+
+/// The packets delayed in one direction, ordered by release time.
+#[derive(Debug, Default, Clone)]
+struct Link {
+    delayed: VecDeque<(Instant, Packet)>,
+    /// The release time of the latest reliable packet, which later ones may not overtake.
+    last_reliable: Option<Instant>,
+}
+
+impl Link {
+    fn push(&mut self, at: Instant, packet: Packet) {
+        let index = self.delayed.partition_point(|(other, _)| *other <= at);
+        self.delayed.insert(index, (at, packet));
+    }
+
+    fn release(&mut self, now: Instant) -> Vec<Packet> {
+        let due = self.delayed.partition_point(|(at, _)| *at <= now);
+        self.delayed
+            .drain(..due)
+            .map(|(_, packet)| packet)
+            .collect()
+    }
+}
+
+/// A [`Component`] which, when present on a socket entity, simulates a poor network by delaying,
+/// dropping and duplicating its packets, so that games may be tested under bad conditions
+/// without external tools.
+///
+/// Conditions apply to packets both as they are sent and as they are received, so the round trip
+/// time of connections grows by twice the latency. They apply at the wire, after the socket's
+/// [`MiddlewareChain`](crate::MiddlewareChain), and are counted by
+/// [`NetworkStats`](crate::NetworkStats) when the packets are actually sent or received.
+///
+/// Packets are impaired above laminar, which would otherwise resend them, so only unreliable
+/// packets are dropped or duplicated, and reliable packets are delayed without overtaking one
+/// another so that their order is kept. Packets are released once per tick, so delays are
+/// rounded up to the tick rate.
+#[derive(Debug, Clone, Component)]
+pub struct NetworkConditions {
+    latency: Duration,
+    jitter: Duration,
+    loss: f32,
+    duplication: f32,
+    rng: Rng,
+    sent: Link,
+    received: Link,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos() as u64);
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplication: 0.0,
+            rng: Rng::new(seed),
+            sent: Link::default(),
+            received: Link::default(),
+        }
+    }
+}
+
+impl NetworkConditions {
+    /// Creates new [`NetworkConditions`] leaving packets unimpaired.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the latency added to each packet.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the jitter, a random delay of up to `jitter` added to each packet on top of the
+    /// latency.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the probability, between 0 and 1, of an unreliable packet being dropped.
+    pub fn with_loss(mut self, loss: f32) -> Self {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the probability, between 0 and 1, of an unreliable packet being duplicated.
+    pub fn with_duplication(mut self, duplication: f32) -> Self {
+        self.duplication = duplication.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Seeds the random draws, so that a run may be reproduced. Seeded from the clock by default.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Returns the latency added to each packet.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns the maximum jitter added to each packet.
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    /// Returns the probability of an unreliable packet being dropped.
+    pub fn loss(&self) -> f32 {
+        self.loss
+    }
+
+    /// Returns the probability of an unreliable packet being duplicated.
+    pub fn duplication(&self) -> f32 {
+        self.duplication
+    }
+
+    /// Returns the number of packets currently delayed, in both directions.
+    pub fn delayed(&self) -> usize {
+        self.sent.delayed.len() + self.received.delayed.len()
+    }
+
+    /// Delays packets about to be sent, returning those due by `now`.
+    pub(crate) fn send(&mut self, packets: Vec<Packet>, now: Instant) -> Vec<Packet> {
+        for packet in packets {
+            self.impair(packet, now, true);
+        }
+        self.sent.release(now)
+    }
+
+    /// Delays a packet just received, returning the received packets due by `now`.
+    pub(crate) fn recv(&mut self, packet: Packet, now: Instant) -> Vec<Packet> {
+        self.impair(packet, now, false);
+        self.received.release(now)
+    }
+
+    /// Returns the received packets due by `now`.
+    pub(crate) fn release_received(&mut self, now: Instant) -> Vec<Packet> {
+        self.received.release(now)
+    }
+
+    fn impair(&mut self, packet: Packet, now: Instant, is_sent: bool) {
+        let is_reliable = !matches!(
+            Delivery::from(&packet),
+            Delivery::Unreliable | Delivery::UnreliableSequenced(_)
+        );
+        let copies = if is_reliable {
+            1
+        } else if self.rng.chance(self.loss) {
+            trace!(message = "simulating packet loss", address = %packet.addr());
+            0
+        } else if self.rng.chance(self.duplication) {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let jitter = self.jitter.mul_f32(self.rng.next_f32());
+            let mut at = now + self.latency + jitter;
+            let link = if is_sent {
+                &mut self.sent
+            } else {
+                &mut self.received
+            };
+            if is_reliable {
+                at = link.last_reliable.map_or(at, |last| at.max(last));
+                link.last_reliable = Some(at);
+            }
+            link.push(at, packet.clone());
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{collections::VecDeque, net::SocketAddr};
+
+use bevy::{
+    app::Events,
+    ecs::system::{Command, CommandQueue},
+    prelude::*,
+};
+
+use crate::{
+    limit::SpawnCounter, ConnectionBuilder, ConnectionBundle, ConnectionHooks, ConnectionState,
+    ConnectionTransition, Delivery, NewConnection,
+};
+
+/// An extension trait for [`Commands`] opening connections to peers.
+pub trait Connect {
+    /// Spawns a connection entity to `address` on `socket` in [`ConnectionState::Connecting`],
+    /// returning it immediately.
+    ///
+    /// The connection runs the socket's [`ConnectionBuilder`] and [`ConnectionHooks`] and emits
+    /// [`NewConnection`] like any other, but is not subject to the
+    /// [`ConnectionLimit`](crate::ConnectionLimit). It moves on once the peer sends something
+    /// back. Laminar only tracks peers which have been sent to, so nothing times out until a
+    /// packet is sent, see [`Connect::connect_with_handshake`].
+    fn connect(&mut self, socket: Entity, address: SocketAddr) -> Entity;
+
+    /// Like [`Connect::connect`], additionally queueing `payload` on the new connection's
+    /// [`ConnectionSendQueue`](crate::ConnectionSendQueue).
+    fn connect_with_handshake(
+        &mut self,
+        socket: Entity,
+        address: SocketAddr,
+        delivery: Delivery,
+        payload: Vec<u8>,
+    ) -> Entity;
+}
+
+impl Connect for Commands<'_, '_> {
+    fn connect(&mut self, socket: Entity, address: SocketAddr) -> Entity {
+        let connection = self.spawn().id();
+        self.add(OpenConnection {
+            connection,
+            socket,
+            address,
+            handshake: None,
+        });
+        connection
+    }
+
+    fn connect_with_handshake(
+        &mut self,
+        socket: Entity,
+        address: SocketAddr,
+        delivery: Delivery,
+        payload: Vec<u8>,
+    ) -> Entity {
+        let connection = self.spawn().id();
+        self.add(OpenConnection {
+            connection,
+            socket,
+            address,
+            handshake: Some((delivery, payload)),
+        });
+        connection
+    }
+}
+
+struct OpenConnection {
+    connection: Entity,
+    socket: Entity,
+    address: SocketAddr,
+    handshake: Option<(Delivery, Vec<u8>)>,
+}
+
+impl Command for OpenConnection {
+    fn write(self, world: &mut World) {
+        let state = ConnectionState::Connecting;
+        let order = world
+            .get_resource_or_insert_with(SpawnCounter::default)
+            .next();
+        let mut bundle =
+            ConnectionBundle::new(self.socket, self.address, VecDeque::new(), state, order);
+        if let Some((delivery, payload)) = self.handshake {
+            bundle.send_queue.send(delivery, payload);
+        }
+
+        if let Some(mut entity) = world.get_entity_mut(self.connection) {
+            entity.insert_bundle(bundle);
+        } else {
+            return;
+        }
+
+        // Builders and hooks expect commands, apply them against the world straight away
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        if let Some(builder) = world.get::<ConnectionBuilder>(self.socket) {
+            builder.build(self.address, &mut commands.entity(self.connection));
+        }
+        if let Some(hooks) = world.get_resource::<ConnectionHooks>() {
+            let transition = ConnectionTransition {
+                connection: self.connection,
+                socket: self.socket,
+                address: self.address,
+                from: None,
+                to: state,
+            };
+            hooks.run(&transition, &mut commands);
+        }
+        queue.apply(world);
+
+        if let Some(mut events) = world.get_resource_mut::<Events<NewConnection>>() {
+            events.send(NewConnection {
+                connection: self.connection,
+                socket: self.socket,
+                address: self.address,
+                state,
+            });
+        }
+    }
+}
@@ -1,8 +1,17 @@
-use std::{collections::VecDeque, net::SocketAddr};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
 
 use bevy::prelude::*;
 use laminar::Packet;
 
+use crate::{
+    diagnostics::FlushMetrics, hooks, pool::DespawnConnection, ConnectionHooks, ConnectionState,
+    Delivery, DespawnPolicy, DisconnectedPolicy, HostId, NetworkClock, SendQueue, SocketMarker,
+};
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -16,11 +25,125 @@ pub struct ConnectionMarker;
 #[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
 pub struct ConnectionAddress(pub SocketAddr);
 
+impl ConnectionAddress {
+    /// Returns the address with IPv4-mapped IPv6 addresses, as reported by dual-stack sockets,
+    /// converted to IPv4.
+    pub fn normalized(&self) -> SocketAddr {
+        normalize_address(self.0)
+    }
+
+    /// Returns `true` if the addresses are equal once normalized.
+    pub fn matches(&self, other: &SocketAddr) -> bool {
+        self.normalized() == normalize_address(*other)
+    }
+
+    /// Returns `true` if the addresses refer to the same host, regardless of port.
+    pub fn same_host(&self, other: &SocketAddr) -> bool {
+        self.normalized().ip() == normalize_address(*other).ip()
+    }
+
+    /// Returns `true` if the addresses refer to the same host on a different port, as happens
+    /// when a peer rebinds or its NAT mapping changes.
+    pub fn same_host_different_port(&self, other: &SocketAddr) -> bool {
+        self.same_host(other) && self.0.port() != other.port()
+    }
+}
+
+/// Converts IPv4-mapped IPv6 addresses to IPv4, leaving other addresses untouched.
+pub fn normalize_address(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), address.port()),
+            None => address,
+        },
+        IpAddr::V4(_) => address,
+    }
+}
+
 /// A [`Component`] used to relate connections with their socket.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
 pub struct SocketId(pub Entity);
 
+/// A resource indexing connection entities by their socket and normalized peer address.
+///
+/// Several connections may share a socket and address, as with [`HostId`](crate::HostId)s or a
+/// [`DisconnectedPolicy::SpawnNew`], so each key maps to a list of entities. The index is updated
+/// by the plugin as connections are spawned and despawned, entities despawned since may linger
+/// until then.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConnectionIndex {
+    connections: HashMap<(Entity, SocketAddr), Vec<Entity>>,
+    keys: HashMap<Entity, (Entity, SocketAddr)>,
+}
+
+impl ConnectionIndex {
+    /// Returns the connections of the socket with the given peer address.
+    pub fn get(&self, socket: Entity, address: SocketAddr) -> &[Entity] {
+        self.connections
+            .get(&(socket, normalize_address(address)))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of connections indexed.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if no connections are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert(&mut self, connection: Entity, socket: Entity, address: SocketAddr) {
+        let key = (socket, normalize_address(address));
+        if self.keys.get(&connection) == Some(&key) {
+            return;
+        }
+
+        self.remove(connection);
+        self.keys.insert(connection, key);
+        self.connections.entry(key).or_default().push(connection);
+    }
+
+    fn remove(&mut self, connection: Entity) {
+        let key = if let Some(key) = self.keys.remove(&connection) {
+            key
+        } else {
+            return;
+        };
+
+        if let Some(connections) = self.connections.get_mut(&key) {
+            connections.retain(|entity| *entity != connection);
+            if connections.is_empty() {
+                self.connections.remove(&key);
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn index_connections(
+    mut index: ResMut<ConnectionIndex>,
+    changed_query: Query<
+        (Entity, &SocketId, &ConnectionAddress),
+        (
+            With<ConnectionMarker>,
+            Or<(Changed<SocketId>, Changed<ConnectionAddress>)>,
+        ),
+    >,
+    removed: RemovedComponents<ConnectionMarker>,
+) {
+    for connection in removed.iter() {
+        index.remove(connection);
+    }
+
+    for (connection, socket_id, addr) in changed_query.iter() {
+        index.insert(connection, socket_id.0, addr.0);
+    }
+}
+
 /// A [`Component`] storing all packets received from a peer.
 #[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
 pub struct ReceiveQueue(pub(crate) VecDeque<Packet>);
@@ -46,3 +169,289 @@ impl ReceiveQueue {
         self.0.drain(..)
     }
 }
+
+/// A [`Component`] storing payloads to be sent to a connection's peer.
+///
+/// Payloads are addressed to the [`ConnectionAddress`] and moved to the socket's
+/// [`SendQueue`] before it is flushed.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
+pub struct ConnectionSendQueue {
+    pub(crate) payloads: Vec<QueuedPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct QueuedPayload {
+    pub(crate) delivery: Delivery,
+    pub(crate) payload: Vec<u8>,
+    /// The payload is a control frame, see [`SendQueue::send_control`].
+    pub(crate) control: bool,
+    /// The ID and type name of a typed message.
+    #[cfg(feature = "serde")]
+    pub(crate) message: Option<(u16, &'static str)>,
+}
+
+impl QueuedPayload {
+    pub(crate) fn new(delivery: Delivery, payload: Vec<u8>) -> Self {
+        Self {
+            delivery,
+            payload,
+            control: false,
+            #[cfg(feature = "serde")]
+            message: None,
+        }
+    }
+
+    pub(crate) fn control(delivery: Delivery, payload: Vec<u8>) -> Self {
+        Self {
+            control: true,
+            ..Self::new(delivery, payload)
+        }
+    }
+}
+
+impl ConnectionSendQueue {
+    /// Sends a payload to the peer with the given [`Delivery`].
+    pub fn send(&mut self, delivery: Delivery, payload: Vec<u8>) {
+        self.payloads.push(QueuedPayload::new(delivery, payload))
+    }
+
+    /// Sends a control frame to the peer, see [`SendQueue::send_control`].
+    pub(crate) fn send_control(&mut self, delivery: Delivery, payload: Vec<u8>) {
+        self.payloads
+            .push(QueuedPayload::control(delivery, payload))
+    }
+
+    /// Returns the number of payloads.
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    /// Returns `true` if the queue has a length of 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn forward_connection_sends(
+    mut connection_query: Query<
+        (
+            &SocketId,
+            &ConnectionAddress,
+            &mut ConnectionSendQueue,
+            Option<&HostId>,
+        ),
+        Changed<ConnectionSendQueue>,
+    >,
+    mut socket_query: Query<&mut SendQueue, With<SocketMarker>>,
+    mut metrics: ResMut<FlushMetrics>,
+) {
+    let start = Instant::now();
+
+    for (socket_id, addr, mut queue, host_opt) in connection_query.iter_mut() {
+        if queue.is_empty() {
+            continue;
+        }
+
+        if let Ok(mut send_queue) = socket_query.get_mut(socket_id.0) {
+            for queued in queue.payloads.drain(..) {
+                let payload = match host_opt {
+                    Some(host) => host.prefix(&queued.payload),
+                    None => queued.payload,
+                };
+                let packet = queued.delivery.packet(addr.0, payload);
+                if queued.control {
+                    send_queue.send_control(packet);
+                } else {
+                    send_queue.send(packet);
+                }
+            }
+        } else {
+            trace!(message = "dropping sends to connection without socket", address = %addr.0);
+            queue.payloads.clear();
+        }
+    }
+
+    metrics.serialize_time += start.elapsed();
+}
+
+/// An event emitted when a connection entity is spawned.
+///
+/// Unlike [`ConnectionBuilder`](crate::ConnectionBuilder), which runs while the entity is being
+/// spawned, this allows any system to react to new connections with full [`World`] access once the
+/// entity exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NewConnection {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The socket entity which spawned the connection.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// The initial state of the connection.
+    pub state: ConnectionState,
+}
+
+/// An event emitted when packets arrive for a connection in
+/// [`ConnectionState::Disconnected`](crate::ConnectionState::Disconnected) or
+/// [`ConnectionState::TimedOut`](crate::ConnectionState::TimedOut).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DisconnectedTraffic {
+    /// The disconnected connection entity.
+    pub connection: Entity,
+    /// The number of packets received.
+    pub packets: usize,
+    /// The [`DisconnectedPolicy`] applied.
+    pub policy: DisconnectedPolicy,
+}
+
+/// A [`Component`] limiting how long a connection may last.
+///
+/// Once the duration has elapsed since the component was added, or last changed, the connection
+/// is moved to [`ConnectionState::Disconnected`] and a [`SessionExpired`] event is emitted. Traffic
+/// from the peer afterwards is handled according to the socket's [`DisconnectedPolicy`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct SessionTtl(pub Duration);
+
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub(crate) struct SessionDeadline(pub(crate) Instant);
+
+/// An event emitted when a connection's [`SessionTtl`] has elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionExpired {
+    /// The expired connection entity.
+    pub connection: Entity,
+}
+
+/// An event emitted when a connection is despawned by its socket's
+/// [`DespawnPolicy`](crate::DespawnPolicy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionReaped {
+    /// The despawned connection entity.
+    pub connection: Entity,
+    /// The socket entity owning the connection.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// The connection's final state.
+    pub state: ConnectionState,
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn reap_connections(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut disconnected: Local<HashMap<Entity, Instant>>,
+    changed_query: Query<(Entity, &ConnectionState), Changed<ConnectionState>>,
+    connection_query: Query<
+        (&SocketId, &ConnectionAddress, &ConnectionState),
+        With<ConnectionMarker>,
+    >,
+    policy_query: Query<&DespawnPolicy>,
+    removed: RemovedComponents<ConnectionMarker>,
+    mut reaped_writer: EventWriter<ConnectionReaped>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, state) in changed_query.iter() {
+        if state.is_disconnected() {
+            disconnected.entry(connection).or_insert(now);
+        } else {
+            disconnected.remove(&connection);
+        }
+    }
+
+    for connection in removed.iter() {
+        disconnected.remove(&connection);
+    }
+
+    disconnected.retain(|&connection, since| {
+        let (socket_id, addr, state) = if let Ok(item) = connection_query.get(connection) {
+            item
+        } else {
+            return false;
+        };
+
+        let grace = match policy_query.get(socket_id.0) {
+            Ok(DespawnPolicy::After(grace)) => *grace,
+            _ => return true,
+        };
+        if *since + grace > now {
+            return true;
+        }
+
+        trace!(message = "reaping connection", ?connection, address = %addr.0);
+
+        commands.add(DespawnConnection(connection));
+        reaped_writer.send(ConnectionReaped {
+            connection,
+            socket: socket_id.0,
+            address: addr.0,
+            state: *state,
+        });
+        false
+    });
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn expire_sessions(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    hooks: Res<ConnectionHooks>,
+    ttl_query: Query<(Entity, &SessionTtl), Changed<SessionTtl>>,
+    mut deadline_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &SessionDeadline,
+            &mut ConnectionState,
+        ),
+        With<SessionTtl>,
+    >,
+    mut expired_writer: EventWriter<SessionExpired>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, ttl) in ttl_query.iter() {
+        commands
+            .entity(connection)
+            .insert(SessionDeadline(now + ttl.0));
+    }
+
+    for (connection, socket_id, addr, deadline, mut state) in deadline_query.iter_mut() {
+        if deadline.0 > now {
+            continue;
+        }
+
+        trace!(message = "session expired", ?connection);
+
+        if !state.is_disconnected() {
+            hooks::transition(
+                &mut state,
+                ConnectionState::Disconnected,
+                (connection, socket_id.0, addr.0),
+                &hooks,
+                &mut commands,
+            );
+        }
+        commands
+            .entity(connection)
+            .remove::<SessionTtl>()
+            .remove::<SessionDeadline>();
+        expired_writer.send(SessionExpired { connection });
+    }
+}
@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{bind_with_options, validate_bind, ConnectionOptions, NetworkError};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A description of a socket to bind, suitable for loading from configuration.
+///
+/// Descriptors pushed to the [`SocketDescriptors`] resource are bound and spawned by the
+/// [`NetworkPlugin`](crate::NetworkPlugin).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SocketDescriptor {
+    /// The address to bind to.
+    pub address: SocketAddr,
+    /// The socket's [`PollInterval`](crate::PollInterval).
+    pub poll_interval: Duration,
+    /// The socket's [`ConnectionOptions`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub options: ConnectionOptions,
+    /// A label inserted as a [`SocketLabel`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub label: Option<String>,
+}
+
+impl SocketDescriptor {
+    /// Creates a new [`SocketDescriptor`] with default [`ConnectionOptions`] and no label.
+    pub fn new(address: SocketAddr, poll_interval: Duration) -> Self {
+        Self {
+            address,
+            poll_interval,
+            options: ConnectionOptions::default(),
+            label: None,
+        }
+    }
+
+    /// Sets the [`ConnectionOptions`].
+    pub fn with_options(mut self, options: ConnectionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the label.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Checks, without spawning anything, that the descriptor's options are consistent and that
+    /// its address can be bound, see [`validate_bind`].
+    pub fn validate(&self) -> Result<(), NetworkError> {
+        self.options.validate(self.poll_interval)?;
+        validate_bind(self.address)
+    }
+}
+
+/// A [`Component`] naming a socket spawned from a [`SocketDescriptor`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Component, PartialEq, Eq, Hash)]
+pub struct SocketLabel(pub String);
+
+/// A resource holding [`SocketDescriptor`]s waiting to be spawned.
+///
+/// The list is drained as sockets are spawned, so descriptors may be pushed at any time.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct SocketDescriptors(pub Vec<SocketDescriptor>);
+
+/// An event emitted when a [`SocketDescriptor`] fails to bind.
+#[derive(Debug)]
+pub struct DescriptorBindError {
+    /// The descriptor which failed to bind.
+    pub descriptor: SocketDescriptor,
+    /// The error returned by laminar.
+    pub error: laminar::ErrorKind,
+}
+
+pub(crate) fn spawn_described_sockets(
+    descriptors_opt: Option<ResMut<SocketDescriptors>>,
+    mut error_writer: EventWriter<DescriptorBindError>,
+    mut commands: Commands,
+) {
+    let mut descriptors = match descriptors_opt {
+        Some(descriptors) if !descriptors.0.is_empty() => descriptors,
+        _ => return,
+    };
+
+    for descriptor in descriptors.0.drain(..) {
+        spawn_descriptor(descriptor, &mut error_writer, &mut commands);
+    }
+}
+
+/// Binds and spawns a described socket, returning the socket entity, or emitting a
+/// [`DescriptorBindError`] should it fail to bind.
+pub(crate) fn spawn_descriptor(
+    descriptor: SocketDescriptor,
+    error_writer: &mut EventWriter<DescriptorBindError>,
+    commands: &mut Commands,
+) -> Option<Entity> {
+    if let Err(error) = descriptor.options.validate(descriptor.poll_interval) {
+        warn!(message = "invalid connection options", address = %descriptor.address, %error);
+    }
+
+    match bind_with_options(
+        descriptor.address,
+        descriptor.poll_interval,
+        descriptor.options,
+    ) {
+        Ok(bundle) => {
+            trace!(message = "spawning described socket", address = %descriptor.address);
+
+            let mut entity_commands = commands.spawn_bundle(bundle);
+            if let Some(label) = descriptor.label {
+                entity_commands.insert(SocketLabel(label));
+            }
+            Some(entity_commands.id())
+        }
+        Err(error) => {
+            error!(message = "failed to bind", address = %descriptor.address, %error);
+            error_writer.send(DescriptorBindError { descriptor, error });
+            None
+        }
+    }
+}
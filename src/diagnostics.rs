@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+};
+
+/// A plugin registering [`Diagnostics`] measuring each flush of the send path.
+///
+/// A measurement of each is recorded per tick, once the queued payloads have been handed to
+/// laminar. Regardless of this plugin, each flush is also traced by a `flush` span at the debug
+/// level, whose fields aggregate the tick's traffic rather than logging every packet.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NetworkDiagnosticsPlugin;
+
+impl NetworkDiagnosticsPlugin {
+    /// The time spent building packets from the payloads queued on connections and sockets, in
+    /// milliseconds.
+    pub const SERIALIZE_TIME: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e01);
+    /// The time spent handing packets to laminar, in milliseconds.
+    pub const LAMINAR_SEND_TIME: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e02);
+    /// The number of packets sent.
+    pub const PACKETS_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e03);
+    /// The number of payload bytes sent.
+    pub const BYTES_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e04);
+    /// The number of distinct peers sent to.
+    pub const PEERS_SENT_TO: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e05);
+    /// The largest number of packets sent to a single peer.
+    pub const MAX_PACKETS_PER_PEER: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e06);
+    /// The number of packets which failed to send.
+    pub const SEND_ERRORS: DiagnosticId =
+        DiagnosticId::from_u128(0x6b5f_1d2e_8c3a_4f71_9e04_b7a2_3c6d_5e07);
+
+    const MAX_HISTORY: usize = 20;
+}
+
+impl Plugin for NetworkDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Diagnostics>();
+        let mut diagnostics = app
+            .world
+            .get_resource_mut::<Diagnostics>()
+            .expect("diagnostics were initialized");
+
+        for (id, name, suffix) in [
+            (Self::SERIALIZE_TIME, "network_serialize_time", "ms"),
+            (Self::LAMINAR_SEND_TIME, "network_laminar_send_time", "ms"),
+            (Self::PACKETS_SENT, "network_packets_sent", ""),
+            (Self::BYTES_SENT, "network_bytes_sent", "B"),
+            (Self::PEERS_SENT_TO, "network_peers_sent_to", ""),
+            (
+                Self::MAX_PACKETS_PER_PEER,
+                "network_max_packets_per_peer",
+                "",
+            ),
+            (Self::SEND_ERRORS, "network_send_errors", ""),
+        ] {
+            diagnostics.add(Diagnostic::new(id, name, Self::MAX_HISTORY).with_suffix(suffix));
+        }
+    }
+}
+
+/// A resource accumulating the measurements of the current flush.
+#[derive(Debug, Default)]
+pub(crate) struct FlushMetrics {
+    pub(crate) serialize_time: Duration,
+    pub(crate) laminar_send_time: Duration,
+    pub(crate) packets: u64,
+    pub(crate) bytes: u64,
+    pub(crate) peers: u64,
+    pub(crate) max_packets_per_peer: u64,
+    pub(crate) errors: u64,
+}
+
+impl FlushMetrics {
+    /// Records the flush into `diagnostics`, if present, and resets the measurements.
+    pub(crate) fn finish(&mut self, diagnostics_opt: Option<&mut Diagnostics>) {
+        let metrics = std::mem::take(self);
+        let diagnostics = match diagnostics_opt {
+            Some(some) => some,
+            None => return,
+        };
+
+        for (id, value) in [
+            (
+                NetworkDiagnosticsPlugin::SERIALIZE_TIME,
+                metrics.serialize_time.as_secs_f64() * 1000.0,
+            ),
+            (
+                NetworkDiagnosticsPlugin::LAMINAR_SEND_TIME,
+                metrics.laminar_send_time.as_secs_f64() * 1000.0,
+            ),
+            (
+                NetworkDiagnosticsPlugin::PACKETS_SENT,
+                metrics.packets as f64,
+            ),
+            (NetworkDiagnosticsPlugin::BYTES_SENT, metrics.bytes as f64),
+            (
+                NetworkDiagnosticsPlugin::PEERS_SENT_TO,
+                metrics.peers as f64,
+            ),
+            (
+                NetworkDiagnosticsPlugin::MAX_PACKETS_PER_PEER,
+                metrics.max_packets_per_peer as f64,
+            ),
+            (NetworkDiagnosticsPlugin::SEND_ERRORS, metrics.errors as f64),
+        ] {
+            diagnostics.add_measurement(id, value);
+        }
+    }
+}
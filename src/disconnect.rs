@@ -0,0 +1,149 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::SessionKey;
+use crate::{
+    connection::QueuedPayload, hooks, pool::despawn_connection, BlockedAddresses,
+    ConnectionAddress, ConnectionSendQueue, ConnectionState, Delivery, HostId, SendQueue, SocketId,
+};
+
+/// A [`Command`] terminating a connection.
+///
+/// Payloads pending on the connection's [`ConnectionSendQueue`] are moved to the socket's
+/// [`SendQueue`], followed by the goodbye payload if any, so they are still flushed. The peer's
+/// address is added to the socket's [`BlockedAddresses`] unless disabled, since laminar would
+/// otherwise deliver its next packet as usual. Finally the connection is moved to
+/// [`ConnectionState::Disconnected`], running the
+/// [`ConnectionHooks`](crate::ConnectionHooks), or despawned.
+///
+/// Queue it with [`Commands::add`], or use [`CloseConnection::disconnect`] for the defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Disconnect {
+    connection: Entity,
+    goodbye: Option<QueuedPayload>,
+    block: bool,
+    despawn: bool,
+}
+
+impl Disconnect {
+    /// Creates a new [`Disconnect`] which blocks the peer and keeps the connection entity.
+    pub fn new(connection: Entity) -> Self {
+        Self {
+            connection,
+            goodbye: None,
+            block: true,
+            despawn: false,
+        }
+    }
+
+    /// Sends a final payload to the peer.
+    pub fn with_goodbye(mut self, delivery: Delivery, payload: Vec<u8>) -> Self {
+        self.goodbye = Some(QueuedPayload::new(delivery, payload));
+        self
+    }
+
+    /// Sends a final control frame to the peer, see [`SendQueue::send_control`].
+    pub(crate) fn with_control_goodbye(mut self, delivery: Delivery, payload: Vec<u8>) -> Self {
+        self.goodbye = Some(QueuedPayload::control(delivery, payload));
+        self
+    }
+
+    /// Keeps accepting packets from the peer, subject to the socket's
+    /// [`DisconnectedPolicy`](crate::DisconnectedPolicy).
+    pub fn without_block(mut self) -> Self {
+        self.block = false;
+        self
+    }
+
+    /// Despawns the connection entity rather than marking it disconnected.
+    pub fn despawn(mut self) -> Self {
+        self.despawn = true;
+        self
+    }
+}
+
+impl Command for Disconnect {
+    fn write(self, world: &mut World) {
+        let mut entity = if let Some(entity) = world.get_entity_mut(self.connection) {
+            entity
+        } else {
+            return;
+        };
+        let (socket, address) = match (entity.get::<SocketId>(), entity.get::<ConnectionAddress>())
+        {
+            (Some(socket_id), Some(addr)) => (socket_id.0, addr.0),
+            _ => return,
+        };
+        let pending = entity
+            .get_mut::<ConnectionSendQueue>()
+            .map(|mut queue| std::mem::take(&mut queue.payloads))
+            .unwrap_or_default();
+        let state_opt = entity.get::<ConnectionState>().copied();
+        let host_opt = entity.get::<HostId>().copied();
+        // The connection may be despawned before its payloads are sent, seal them now
+        #[cfg(feature = "encryption")]
+        let key_opt = entity.get::<SessionKey>().cloned();
+
+        trace!(message = "disconnecting", connection = ?self.connection, %address);
+
+        if let Some(mut socket_entity) = world.get_entity_mut(socket) {
+            if let Some(mut send_queue) = socket_entity.get_mut::<SendQueue>() {
+                for queued in pending.into_iter().chain(self.goodbye) {
+                    let payload = match host_opt {
+                        Some(host) => host.prefix(&queued.payload),
+                        None => queued.payload,
+                    };
+                    // Payloads sealed here are sent as is, like control frames
+                    #[cfg(feature = "encryption")]
+                    let (payload, control) = match key_opt.as_ref() {
+                        Some(key) if !queued.control => (key.seal(&payload), true),
+                        _ => (payload, queued.control),
+                    };
+                    #[cfg(not(feature = "encryption"))]
+                    let control = queued.control;
+                    let packet = queued.delivery.packet(address, payload);
+                    if control {
+                        send_queue.send_control(packet);
+                    } else {
+                        send_queue.send(packet);
+                    }
+                }
+            }
+
+            if self.block {
+                if let Some(mut blocked) = socket_entity.get_mut::<BlockedAddresses>() {
+                    blocked.block(address);
+                } else {
+                    let mut blocked = BlockedAddresses::default();
+                    blocked.block(address);
+                    socket_entity.insert(blocked);
+                }
+            }
+        }
+
+        if self.despawn {
+            despawn_connection(world, self.connection);
+            return;
+        }
+
+        if state_opt.is_some_and(|state| !state.is_disconnected()) {
+            hooks::transition_world(
+                world,
+                ConnectionState::Disconnected,
+                (self.connection, socket, address),
+            );
+        }
+    }
+}
+
+/// An extension trait for [`Commands`] terminating connections.
+pub trait CloseConnection {
+    /// Disconnects a connection, blocking the peer and keeping the entity, see [`Disconnect`].
+    fn disconnect(&mut self, connection: Entity);
+}
+
+impl CloseConnection for Commands<'_, '_> {
+    fn disconnect(&mut self, connection: Entity) {
+        self.add(Disconnect::new(connection));
+    }
+}
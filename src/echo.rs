@@ -0,0 +1,158 @@
+use std::net::SocketAddr;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use laminar::Packet;
+
+use crate::{
+    wire::{REPLY_TAG, REQUEST_TAG},
+    ConnectionMarker, Heartbeat, Liveness, Mesh, Rtt, SendQueue, SocketMarker,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const PROBE_LEN: usize = 8;
+
+/// A marker [`Component`] which, when present on a connection entity, echoes [`Probe`]s from the
+/// peer back to it. When present on a socket entity it does so for every peer.
+///
+/// Probes received by a socket or connection with a [`DiagnosticEcho`] are never delivered to a
+/// [`ReceiveQueue`](crate::ReceiveQueue), and bypass [`VirtualHosts`](crate::VirtualHosts)
+/// routing.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct DiagnosticEcho;
+
+/// A diagnostic probe, echoed by peers with [`DiagnosticEcho`].
+///
+/// Round trip times and loss may be measured by recording when each probe was sent with
+/// [`SendQueue::send_probe`] and matching the echoes, found in the
+/// [`ReceiveQueue`](crate::ReceiveQueue), using [`Probe::from_echo`].
+///
+/// Probe requests are only intercepted by sockets opted into probes, by a [`DiagnosticEcho`],
+/// [`Mesh`] or [`Liveness`] on the socket, or a [`DiagnosticEcho`], [`Heartbeat`], [`Rtt`] or
+/// [`Liveness`] on the connection to the peer. Elsewhere they are delivered to the
+/// [`ReceiveQueue`](crate::ReceiveQueue) like any other payload, so that applications are free to
+/// send payloads starting with the same bytes.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Probe {
+    /// Identifies the probe within its echo.
+    pub id: u32,
+}
+
+impl Probe {
+    /// Creates an unreliable [`Packet`] carrying the probe.
+    pub fn packet(self, addr: SocketAddr) -> Packet {
+        let mut payload = Vec::with_capacity(PROBE_LEN);
+        payload.extend_from_slice(&REQUEST_TAG);
+        payload.extend_from_slice(&self.id.to_be_bytes());
+        Packet::unreliable(addr, payload)
+    }
+
+    /// Parses the echo of a probe from a received [`Packet`].
+    pub fn from_echo(packet: &Packet) -> Option<Self> {
+        Self::parse(packet.payload(), REPLY_TAG)
+    }
+
+    fn parse(payload: &[u8], tag: [u8; 4]) -> Option<Self> {
+        if payload.len() != PROBE_LEN || payload[..4] != tag {
+            return None;
+        }
+
+        let id = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+        Some(Self { id })
+    }
+
+    /// Parses a probe request from a received [`Packet`].
+    pub(crate) fn from_request(packet: &Packet) -> Option<Self> {
+        Self::parse(packet.payload(), REQUEST_TAG)
+    }
+
+    /// Creates the echo of the probe, sent unreliably so that loss is measured faithfully.
+    pub(crate) fn echo(self, addr: SocketAddr) -> Packet {
+        let mut payload = Vec::with_capacity(PROBE_LEN);
+        payload.extend_from_slice(&REPLY_TAG);
+        payload.extend_from_slice(&self.id.to_be_bytes());
+        Packet::unreliable(addr, payload)
+    }
+}
+
+impl SendQueue {
+    /// Sends a [`Probe`] to a peer.
+    ///
+    /// Probes carry no application data, so unlike packets sent with [`SendQueue::send`] they are
+    /// never encrypted, and reach peers whatever their state.
+    pub fn send_probe(&mut self, addr: SocketAddr, probe: Probe) {
+        self.send_control(probe.packet(addr))
+    }
+}
+
+/// The sockets and connections opted into intercepting probe requests, see [`Probe`].
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub(crate) struct ProbeListeners<'w, 's> {
+    socket_query: Query<
+        'w,
+        's,
+        Option<&'static DiagnosticEcho>,
+        (
+            With<SocketMarker>,
+            Or<(With<DiagnosticEcho>, With<Mesh>, With<Liveness>)>,
+        ),
+    >,
+    connection_query: Query<
+        'w,
+        's,
+        Option<&'static DiagnosticEcho>,
+        (
+            With<ConnectionMarker>,
+            Or<(
+                With<DiagnosticEcho>,
+                With<Heartbeat>,
+                With<Rtt>,
+                With<Liveness>,
+            )>,
+        ),
+    >,
+}
+
+impl ProbeListeners<'_, '_> {
+    /// Returns `true` if probe requests reaching `socket` from the peer of `connections` are
+    /// intercepted rather than delivered.
+    pub(crate) fn intercepts(&self, socket: Entity, connections: &[Entity]) -> bool {
+        self.socket_query.get(socket).is_ok()
+            || connections
+                .iter()
+                .any(|connection| self.connection_query.get(*connection).is_ok())
+    }
+
+    /// Returns `true` if probe requests reaching `socket` from the peer of `connections` are
+    /// echoed.
+    pub(crate) fn echoes(&self, socket: Entity, connections: &[Entity]) -> bool {
+        matches!(self.socket_query.get(socket), Ok(Some(_)))
+            || connections
+                .iter()
+                .any(|connection| matches!(self.connection_query.get(*connection), Ok(Some(_))))
+    }
+}
+
+/// Returns `true` if `payload` is a probe request or echo.
+#[cfg(feature = "encryption")]
+pub(crate) fn is_probe(payload: &[u8]) -> bool {
+    Probe::parse(payload, REQUEST_TAG)
+        .or_else(|| Probe::parse(payload, REPLY_TAG))
+        .is_some()
+}
+
+/// Parses a probe request or echo and builds it again, see [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    let tag = payload.get(..4)?.try_into().ok()?;
+    let probe = Probe::parse(payload, tag)?;
+    let mut reencoded = Vec::with_capacity(PROBE_LEN);
+    reencoded.extend_from_slice(&tag);
+    reencoded.extend_from_slice(&probe.id.to_be_bytes());
+    Some(reencoded)
+}
@@ -0,0 +1,309 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use laminar::Packet;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, ReusableSecret};
+
+use crate::{
+    echo,
+    handshake::{self, Handshake},
+    packet::with_payload,
+    wire::SEALED_TAG,
+    ConnectionIndex, ConnectionMarker, HostId, Protocol,
+};
+
+const NONCE_LEN: usize = 24;
+
+/// The public key exchanged during the handshake.
+pub(crate) type PublicBytes = [u8; 32];
+
+/// The public key a connection's [`Credentials`](crate::Credentials) were bound to, which its
+/// handshake must offer.
+#[derive(Debug, Component)]
+pub(crate) struct BoundKey(pub(crate) PublicBytes);
+
+/// The key pair of a connection, generated when its handshake starts and kept for its lifetime
+/// so that peers restarting their side of the handshake are answered with the same key.
+#[derive(Component)]
+pub(crate) struct KeyExchange {
+    secret: ReusableSecret,
+    public: PublicKey,
+}
+
+impl KeyExchange {
+    pub(crate) fn new() -> Self {
+        let secret = ReusableSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub(crate) fn public(&self) -> PublicBytes {
+        self.public.to_bytes()
+    }
+
+    /// Derives the keys of a session with the peer, or `None` should its key be degenerate.
+    pub(crate) fn derive(&self, peer: PublicBytes) -> Option<SessionKey> {
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer));
+        if !shared.was_contributory() {
+            return None;
+        }
+
+        // Each direction has a key of its own, so that packets cannot be reflected to the sender
+        let key = |sender: &PublicBytes, receiver: &PublicBytes| {
+            let digest = Sha256::new()
+                .chain_update(SEALED_TAG)
+                .chain_update(shared.as_bytes())
+                .chain_update(sender)
+                .chain_update(receiver)
+                .finalize();
+            XChaCha20Poly1305::new(&digest)
+        };
+        let public = self.public();
+        Some(SessionKey {
+            peer,
+            send: key(&public, &peer),
+            recv: key(&peer, &public),
+        })
+    }
+}
+
+/// The keys sealing the payloads exchanged with the peer of a connection.
+#[derive(Clone, Component)]
+pub(crate) struct SessionKey {
+    peer: PublicBytes,
+    send: XChaCha20Poly1305,
+    recv: XChaCha20Poly1305,
+}
+
+impl SessionKey {
+    /// Returns `true` if the key was derived from the given public key of the peer.
+    pub(crate) fn is_for(&self, peer: &PublicBytes) -> bool {
+        self.peer == *peer
+    }
+
+    pub(crate) fn seal(&self, payload: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .send
+            .encrypt(&nonce, payload)
+            .expect("payloads fit within the cipher's limits");
+
+        let mut sealed = Vec::with_capacity(SEALED_TAG.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&SEALED_TAG);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    fn open(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let body = payload.strip_prefix(&SEALED_TAG)?;
+        if body.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        self.recv
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+
+    /// Opens a packet received before the key was derived.
+    ///
+    /// A peer holds back its payloads until it has derived its own key, so unsealed packets other
+    /// than probes were not sent by it and are dropped.
+    pub(crate) fn open_held(&self, packet: Packet) -> Option<Packet> {
+        let payload = packet.payload();
+        if !is_sealed(payload) {
+            if is_control_frame(payload) {
+                return Some(packet);
+            }
+            trace!(message = "dropping unsealed packet held during handshake", address = %packet.addr());
+            return None;
+        }
+        self.open(payload)
+            .map(|payload| with_payload(&packet, payload))
+    }
+}
+
+fn is_sealed(payload: &[u8]) -> bool {
+    payload.starts_with(&SEALED_TAG)
+}
+
+/// Returns `true` if a payload received unsealed is a frame carrying no application data, which
+/// encrypted connections accept: a handshake frame, so that peers restarting their side of the
+/// handshake are heard, or a probe. Frames are parsed in full, rather than told by their tag.
+fn is_control_frame(payload: &[u8]) -> bool {
+    handshake::is_frame(payload) || echo::is_probe(payload)
+}
+
+/// What becomes of a packet about to be sent.
+pub(crate) enum Outgoing {
+    Send(Packet),
+    /// The connection is yet to derive its key.
+    Hold(Packet),
+    Drop,
+}
+
+/// Seals and opens the packets of connections which derived a [`SessionKey`], when the
+/// [`Protocol`] requires encryption.
+///
+/// Connections behind [`VirtualHosts`](crate::VirtualHosts) share their address and are rejected
+/// by the handshake, their packets are dropped rather than sent or delivered unsealed.
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub(crate) struct Encryption<'w, 's> {
+    protocol_opt: Option<Res<'w, Protocol>>,
+    index: Res<'w, ConnectionIndex>,
+    key_query: Query<
+        'w,
+        's,
+        (
+            Option<&'static SessionKey>,
+            Option<&'static Handshake>,
+            Option<&'static HostId>,
+        ),
+        With<ConnectionMarker>,
+    >,
+}
+
+impl Encryption<'_, '_> {
+    pub(crate) fn is_encrypted(&self) -> bool {
+        self.protocol_opt
+            .as_ref()
+            .is_some_and(|protocol| protocol.is_encrypted())
+    }
+
+    /// Seals a packet sent by `socket`, holding it back until its connection has derived a key.
+    ///
+    /// Control frames, see [`SendQueue::send_control`](crate::SendQueue::send_control), are sent
+    /// as is. Other packets to addresses without a connection are dropped.
+    pub(crate) fn seal(
+        &self,
+        socket: Entity,
+        packet: Packet,
+        is_control: bool,
+        is_disconnected: bool,
+    ) -> Outgoing {
+        if !self.is_encrypted() || is_control {
+            return Outgoing::Send(packet);
+        }
+
+        let payload = packet.payload();
+        let connection = match self.index.get(socket, packet.addr()).last() {
+            Some(connection) => *connection,
+            None => {
+                trace!(message = "dropping unsealed packet without connection", address = %packet.addr());
+                return Outgoing::Drop;
+            }
+        };
+
+        match self.key_query.get(connection) {
+            Ok((_, _, Some(_))) => {
+                trace!(message = "dropping packet to virtual host, which is not encrypted", address = %packet.addr());
+                Outgoing::Drop
+            }
+            Ok((Some(key), _, None)) => {
+                let sealed = key.seal(payload);
+                Outgoing::Send(with_payload(&packet, sealed))
+            }
+            Ok((None, Some(Handshake::Rejected), None)) => Outgoing::Drop,
+            Ok((None, _, None)) if is_disconnected => {
+                trace!(message = "dropping packet to disconnected peer without key", address = %packet.addr());
+                Outgoing::Drop
+            }
+            Ok((None, _, None)) => Outgoing::Hold(packet),
+            Err(_) => {
+                trace!(message = "dropping unsealed packet without connection", address = %packet.addr());
+                Outgoing::Drop
+            }
+        }
+    }
+
+    /// Opens a packet received by `socket`, returning `None` should it fail to authenticate.
+    ///
+    /// Packets are passed through until the connection has derived its key, those sealed being
+    /// opened once the handshake completes. Afterwards unsealed packets are dropped, unless they
+    /// are handshake frames or probes.
+    pub(crate) fn open(&self, socket: Entity, packet: Packet) -> Option<Packet> {
+        let key = match self
+            .index
+            .get(socket, packet.addr())
+            .last()
+            .and_then(|connection| self.key_query.get(*connection).ok())
+        {
+            Some((_, _, Some(_))) if self.is_encrypted() => {
+                trace!(message = "dropping packet from virtual host, which is not encrypted", address = %packet.addr());
+                return None;
+            }
+            Some((Some(key), _, _)) => key,
+            _ => return Some(packet),
+        };
+
+        let payload = packet.payload();
+        if is_sealed(payload) {
+            let opened = key.open(payload);
+            if opened.is_none() {
+                trace!(message = "dropping packet failing to open", address = %packet.addr());
+            }
+            opened.map(|payload| with_payload(&packet, payload))
+        } else if is_control_frame(payload) {
+            Some(packet)
+        } else {
+            trace!(message = "dropping unsealed packet", address = %packet.addr());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Derives the keys of both sides of a session.
+    fn session() -> (SessionKey, SessionKey) {
+        let (client, server) = (KeyExchange::new(), KeyExchange::new());
+        let client_key = client.derive(server.public()).unwrap();
+        let server_key = server.derive(client.public()).unwrap();
+        (client_key, server_key)
+    }
+
+    #[test]
+    fn seal_open_round_trip() {
+        let (client, server) = session();
+        assert_eq!(
+            server.open(&client.seal(b"ping")).as_deref(),
+            Some(&b"ping"[..])
+        );
+        assert_eq!(
+            client.open(&server.seal(b"pong")).as_deref(),
+            Some(&b"pong"[..])
+        );
+    }
+
+    #[test]
+    fn open_with_wrong_direction() {
+        let (client, server) = session();
+        // A packet reflected back to its sender does not open
+        assert_eq!(client.open(&client.seal(b"ping")), None);
+        assert_eq!(server.open(&server.seal(b"pong")), None);
+    }
+
+    #[test]
+    fn open_tampered() {
+        let (client, server) = session();
+        let mut sealed = client.seal(b"ping");
+        *sealed.last_mut().unwrap() ^= 1;
+        assert_eq!(server.open(&sealed), None);
+        assert_eq!(
+            server.open(&sealed[..SEALED_TAG.len() + NONCE_LEN - 1]),
+            None
+        );
+    }
+
+    #[test]
+    fn degenerate_public_key() {
+        assert!(KeyExchange::new().derive([0; 32]).is_none());
+    }
+}
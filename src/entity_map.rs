@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::Replicated;
+
+/// A [`Component`] identifying an entity across peers, assigned by the plugin.
+///
+/// Every [`Replicated`] entity is assigned one, which is how replication refers to it on the
+/// wire. Ids may be sent within messages and resolved on either side with the
+/// [`NetworkEntityMap`]. Ids are unique among the entities of a single app, so ids received from
+/// a peer must be resolved against the connection they arrived on.
+#[derive(
+    Debug, Clone, Copy, Component, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct NetworkId(pub(crate) u64);
+
+impl NetworkId {
+    /// Returns the raw id.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+/// A resource resolving [`NetworkId`]s to local entities.
+///
+/// Ids assigned locally resolve with [`NetworkEntityMap::get`], while ids assigned by a peer
+/// resolve to the [`Replica`](crate::Replica) mirroring the peer's entity with
+/// [`NetworkEntityMap::get_remote`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NetworkEntityMap {
+    next: u64,
+    local: HashMap<NetworkId, Entity>,
+    ids: HashMap<Entity, NetworkId>,
+    remote: HashMap<(Entity, NetworkId), Entity>,
+}
+
+impl NetworkEntityMap {
+    /// Returns the local entity assigned `id`.
+    pub fn get(&self, id: NetworkId) -> Option<Entity> {
+        self.local.get(&id).copied()
+    }
+
+    /// Returns the [`NetworkId`] assigned to a local entity.
+    pub fn id_of(&self, entity: Entity) -> Option<NetworkId> {
+        self.ids.get(&entity).copied()
+    }
+
+    /// Returns the local replica of the entity a peer assigned `id`, replicated by `connection`.
+    pub fn get_remote(&self, connection: Entity, id: NetworkId) -> Option<Entity> {
+        self.remote.get(&(connection, id)).copied()
+    }
+
+    /// Returns the number of local entities assigned an id.
+    pub fn len(&self) -> usize {
+        self.local.len()
+    }
+
+    /// Returns `true` if no local entity is assigned an id.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of replicas of remote entities.
+    pub fn remote_len(&self) -> usize {
+        self.remote.len()
+    }
+
+    pub(crate) fn assign(&mut self, entity: Entity) -> NetworkId {
+        if let Some(id) = self.id_of(entity) {
+            return id;
+        }
+        self.next += 1;
+        let id = NetworkId(self.next);
+        self.local.insert(id, entity);
+        self.ids.insert(entity, id);
+        id
+    }
+
+    pub(crate) fn release(&mut self, entity: Entity) {
+        if let Some(id) = self.ids.remove(&entity) {
+            self.local.remove(&id);
+        }
+    }
+
+    pub(crate) fn insert_remote(&mut self, connection: Entity, id: NetworkId, replica: Entity) {
+        self.remote.insert((connection, id), replica);
+    }
+
+    pub(crate) fn remove_remote(&mut self, connection: Entity, id: NetworkId) -> Option<Entity> {
+        self.remote.remove(&(connection, id))
+    }
+
+    /// Removes the replicas of a connection, returning them.
+    pub(crate) fn drain_connection(&mut self, connection: Entity) -> Vec<Entity> {
+        let mut replicas = Vec::new();
+        self.remote.retain(|(replica_connection, _), replica| {
+            if *replica_connection != connection {
+                return true;
+            }
+            replicas.push(*replica);
+            false
+        });
+        replicas
+    }
+}
+
+pub(crate) fn assign_network_ids(
+    mut map: ResMut<NetworkEntityMap>,
+    query: Query<Entity, (With<Replicated>, Without<NetworkId>)>,
+    mut commands: Commands,
+) {
+    for entity in query.iter() {
+        let id = map.assign(entity);
+        commands.entity(entity).insert(id);
+    }
+}
+
+/// Releases the ids of despawned entities, including those despawned before their id was
+/// inserted.
+#[allow(clippy::type_complexity)]
+pub(crate) fn release_network_ids(
+    mut map: ResMut<NetworkEntityMap>,
+    removed_ids: RemovedComponents<NetworkId>,
+    removed_replicated: RemovedComponents<Replicated>,
+    query: Query<(), Or<(With<NetworkId>, With<Replicated>)>>,
+) {
+    for entity in removed_ids.iter().chain(removed_replicated.iter()) {
+        if query.get(entity).is_err() {
+            map.release(entity);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::net::{IpAddr, SocketAddr};
+
+use bevy::prelude::*;
+
+use crate::normalize_address;
+
+/// A pattern matched against peer addresses by an [`AddressFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressPattern {
+    /// Matches a single address, including its port.
+    Address(SocketAddr),
+    /// Matches every address whose IP shares the first `len` bits of the given IP, regardless of
+    /// port. Prefixes longer than the IP are treated as the full IP.
+    Prefix(IpAddr, u8),
+}
+
+impl AddressPattern {
+    /// Returns `true` if the address matches the pattern once normalized.
+    pub fn matches(&self, address: &SocketAddr) -> bool {
+        let address = normalize_address(*address);
+        match *self {
+            AddressPattern::Address(pattern) => normalize_address(pattern) == address,
+            AddressPattern::Prefix(ip, len) => match (normalize_ip(ip), address.ip()) {
+                (IpAddr::V4(prefix), IpAddr::V4(ip)) => {
+                    let shift = 32 - u32::from(len.min(32));
+                    let mask = u32::MAX.checked_shl(shift).unwrap_or(0);
+                    u32::from(prefix) & mask == u32::from(ip) & mask
+                }
+                (IpAddr::V6(prefix), IpAddr::V6(ip)) => {
+                    let shift = 128 - u32::from(len.min(128));
+                    let mask = u128::MAX.checked_shl(shift).unwrap_or(0);
+                    u128::from(prefix) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl From<SocketAddr> for AddressPattern {
+    fn from(address: SocketAddr) -> Self {
+        AddressPattern::Address(address)
+    }
+}
+
+impl From<IpAddr> for AddressPattern {
+    fn from(ip: IpAddr) -> Self {
+        let len = match ip {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        AddressPattern::Prefix(ip, len)
+    }
+}
+
+fn normalize_ip(ip: IpAddr) -> IpAddr {
+    normalize_address(SocketAddr::new(ip, 0)).ip()
+}
+
+/// A [`Component`] which, when present on a socket entity, drops packets from peers it rejects
+/// before any connection entity is spawned for them.
+///
+/// A deny filter acts as a ban list, rejecting peers matching any of its patterns, whereas an
+/// allow filter acts as a whitelist, rejecting peers matching none of them. Packets from rejected
+/// peers never reach a [`ReceiveQueue`](crate::ReceiveQueue), and an [`AddressRejected`] event is
+/// emitted once per peer for each tick they are heard from.
+#[derive(Debug, Clone, Component, PartialEq, Eq)]
+pub struct AddressFilter {
+    allow: bool,
+    patterns: Vec<AddressPattern>,
+}
+
+impl AddressFilter {
+    /// Creates an empty ban list, rejecting no peer.
+    pub fn deny() -> Self {
+        Self {
+            allow: false,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Creates an empty whitelist, rejecting every peer.
+    pub fn allow() -> Self {
+        Self {
+            allow: true,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Adds a pattern to the filter.
+    pub fn with(mut self, pattern: impl Into<AddressPattern>) -> Self {
+        self.push(pattern);
+        self
+    }
+
+    /// Adds a pattern to the filter.
+    pub fn push(&mut self, pattern: impl Into<AddressPattern>) {
+        self.patterns.push(pattern.into());
+    }
+
+    /// Removes a pattern from the filter, returning `true` if it was present.
+    pub fn remove(&mut self, pattern: &AddressPattern) -> bool {
+        let len = self.patterns.len();
+        self.patterns.retain(|other| other != pattern);
+        self.patterns.len() != len
+    }
+
+    /// Iterates over the patterns of the filter.
+    pub fn patterns(&self) -> impl Iterator<Item = &AddressPattern> {
+        self.patterns.iter()
+    }
+
+    /// Returns `true` if the filter is a whitelist.
+    pub fn is_whitelist(&self) -> bool {
+        self.allow
+    }
+
+    /// Returns `true` if packets from the address are rejected.
+    pub fn rejects(&self, address: &SocketAddr) -> bool {
+        let matched = self.patterns.iter().any(|pattern| pattern.matches(address));
+        matched != self.allow
+    }
+}
+
+/// An event emitted when packets from a peer are dropped by an [`AddressFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressRejected {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The rejected address.
+    pub address: SocketAddr,
+}
@@ -0,0 +1,204 @@
+use std::{error::Error, fmt, net::SocketAddr};
+
+use laminar::Packet;
+
+use crate::{packet::with_payload, wire::GROUP_TAG, ConnectionSendQueue, Delivery, SendQueue};
+
+const COUNT_LEN: usize = 2;
+const LENGTH_LEN: usize = 4;
+
+/// A group of payloads sent as a single packet, so the peer receives either all or none of them.
+///
+/// The payloads are framed into one packet, fragmented by laminar if need be, and split back into
+/// consecutive packets before reaching the peer's [`ReceiveQueue`](crate::ReceiveQueue) within the
+/// same tick. A group holds at most 65535 payloads of at most 4 GiB each, the framed group must
+/// also fit within laminar's maximum packet size, and a reliable [`Delivery`] should be used to
+/// guarantee the group arrives.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct MessageGroup {
+    payloads: Vec<Vec<u8>>,
+}
+
+impl MessageGroup {
+    /// Creates an empty [`MessageGroup`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a payload to the group, failing should the group be full or the payload too large to
+    /// frame.
+    pub fn with(mut self, payload: Vec<u8>) -> Result<Self, GroupError> {
+        self.push(payload)?;
+        Ok(self)
+    }
+
+    /// Adds a payload to the group, failing should the group be full or the payload too large to
+    /// frame.
+    pub fn push(&mut self, payload: Vec<u8>) -> Result<(), GroupError> {
+        if u16::try_from(self.payloads.len() + 1).is_err() {
+            return Err(GroupError::TooManyPayloads);
+        }
+        if u32::try_from(payload.len()).is_err() {
+            return Err(GroupError::PayloadTooLarge(payload.len()));
+        }
+        self.payloads.push(payload);
+        Ok(())
+    }
+
+    /// Returns the number of payloads.
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    /// Returns `true` if the group has a length of 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Frames the payloads into a single payload, failing should they not fit the framing.
+    pub fn encode(&self) -> Result<Vec<u8>, GroupError> {
+        let count = u16::try_from(self.payloads.len()).map_err(|_| GroupError::TooManyPayloads)?;
+        let len = self
+            .payloads
+            .iter()
+            .map(|payload| LENGTH_LEN + payload.len())
+            .sum::<usize>();
+        let mut framed = Vec::with_capacity(GROUP_TAG.len() + COUNT_LEN + len);
+        framed.extend_from_slice(&GROUP_TAG);
+        framed.extend_from_slice(&count.to_be_bytes());
+        for payload in &self.payloads {
+            let len = u32::try_from(payload.len())
+                .map_err(|_| GroupError::PayloadTooLarge(payload.len()))?;
+            framed.extend_from_slice(&len.to_be_bytes());
+            framed.extend_from_slice(payload);
+        }
+        Ok(framed)
+    }
+
+    /// Parses a framed group, returning `None` if the payload is not one.
+    fn decode(framed: &[u8]) -> Option<Vec<&[u8]>> {
+        let rest = framed.strip_prefix(&GROUP_TAG)?;
+        if rest.len() < COUNT_LEN {
+            return None;
+        }
+        let (count, mut rest) = rest.split_at(COUNT_LEN);
+        let count = u16::from_be_bytes([count[0], count[1]]);
+
+        // The count is the peer's to claim, each payload takes at least its length
+        let mut payloads = Vec::with_capacity(usize::from(count).min(rest.len() / LENGTH_LEN));
+        for _ in 0..count {
+            if rest.len() < LENGTH_LEN {
+                return None;
+            }
+            let (len, tail) = rest.split_at(LENGTH_LEN);
+            let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            if tail.len() < len {
+                return None;
+            }
+            let (payload, tail) = tail.split_at(len);
+            payloads.push(payload);
+            rest = tail;
+        }
+
+        rest.is_empty().then_some(payloads)
+    }
+}
+
+/// Splits a received group into its payloads, yielding other packets unchanged.
+pub(crate) fn split(packet: Packet) -> Vec<Packet> {
+    match MessageGroup::decode(packet.payload()) {
+        Some(payloads) => payloads
+            .into_iter()
+            .map(|payload| with_payload(&packet, payload.to_vec()))
+            .collect(),
+        None => vec![packet],
+    }
+}
+
+/// Parses a group and frames its payloads again, see [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    let payloads = MessageGroup::decode(payload)?;
+    let group = payloads
+        .into_iter()
+        .try_fold(MessageGroup::new(), |group, payload| {
+            group.with(payload.to_vec())
+        })
+        .ok()?;
+    group.encode().ok()
+}
+
+/// An error returned when payloads do not fit the framing of a [`MessageGroup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupError {
+    /// The group already holds the most payloads a frame can count.
+    TooManyPayloads,
+    /// A payload, of the given length, is longer than a frame can describe.
+    PayloadTooLarge(usize),
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyPayloads => write!(f, "a group holds at most {} payloads", u16::MAX),
+            Self::PayloadTooLarge(len) => {
+                write!(f, "a payload of {} bytes is too large to group", len)
+            }
+        }
+    }
+}
+
+impl Error for GroupError {}
+
+impl SendQueue {
+    /// Sends a [`MessageGroup`] to a peer with the given [`Delivery`], failing should the group
+    /// not fit the framing.
+    pub fn send_group(
+        &mut self,
+        delivery: Delivery,
+        addr: SocketAddr,
+        group: &MessageGroup,
+    ) -> Result<(), GroupError> {
+        self.send_with(delivery, addr, group.encode()?);
+        Ok(())
+    }
+}
+
+impl ConnectionSendQueue {
+    /// Sends a [`MessageGroup`] to the peer with the given [`Delivery`], failing should the group
+    /// not fit the framing.
+    pub fn send_group(
+        &mut self,
+        delivery: Delivery,
+        group: &MessageGroup,
+    ) -> Result<(), GroupError> {
+        self.send(delivery, group.encode()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_group() {
+        let mut group = MessageGroup::new();
+        for _ in 0..u16::MAX {
+            group.push(Vec::new()).unwrap();
+        }
+        assert_eq!(group.push(Vec::new()), Err(GroupError::TooManyPayloads));
+        assert_eq!(
+            group.encode().unwrap().len(),
+            6 + usize::from(u16::MAX) * LENGTH_LEN
+        );
+    }
+
+    #[test]
+    fn overstated_count() {
+        let mut framed = GROUP_TAG.to_vec();
+        framed.extend_from_slice(&u16::MAX.to_be_bytes());
+        assert_eq!(MessageGroup::decode(&framed), None);
+    }
+}
@@ -0,0 +1,806 @@
+use std::{
+    convert::TryInto,
+    mem,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use laminar::Packet;
+
+#[cfg(feature = "compression")]
+use crate::compression::{Compression, CompressionDictionary};
+#[cfg(feature = "encryption")]
+use crate::encryption::{BoundKey, KeyExchange, SessionKey};
+#[cfg(feature = "encryption")]
+use crate::HostId;
+use crate::{
+    wire::HANDSHAKE_TAG, ConnectionAddress, ConnectionMarker, ConnectionRejected,
+    ConnectionSendQueue, Delivery, Disconnect, NetworkClock, ReceiveQueue, SocketId,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub(crate) const HELLO_KIND: u8 = 0;
+pub(crate) const ACCEPT_KIND: u8 = 1;
+const REJECT_KIND: u8 = 2;
+
+/// The length of the protocol within a hello or accept frame: id, version, minimum version and
+/// flags, followed by a public key when encrypted and a dictionary id when advertising one.
+const PROTOCOL_LEN: usize = 8 + 4 + 4 + 1;
+
+pub(crate) const PUBLIC_LEN: usize = 32;
+
+const DICTIONARY_LEN: usize = 4;
+
+const ENCRYPTED_FLAG: u8 = 1;
+const COMPRESSED_FLAG: u8 = 2;
+const DICTIONARY_FLAG: u8 = 4;
+
+/// Packets received before the handshake completes are held back, up to this many.
+const MAX_HELD: usize = 256;
+
+/// Handshake frames are resent by laminar until acknowledged.
+const HANDSHAKE_DELIVERY: Delivery = Delivery::ReliableUnordered;
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Identifies the protocol spoken by an app, exchanged with peers when connecting, see
+/// [`NetworkPlugin::with_protocol`](crate::NetworkPlugin::with_protocol).
+///
+/// Peers are compatible when their ids are equal, the version of each is at least the minimum
+/// version of the other and they agree on encryption. They then speak the older of their two
+/// versions, and compress payloads should both support it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Protocol {
+    id: u64,
+    version: u32,
+    min_version: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    encrypted: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    compressed: bool,
+}
+
+impl Protocol {
+    /// Creates a new [`Protocol`] only compatible with peers of the same version.
+    pub fn new(id: u64, version: u32) -> Self {
+        Self {
+            id,
+            version,
+            min_version: version,
+            encrypted: false,
+            compressed: false,
+        }
+    }
+
+    /// Sets the oldest version of peers which is still supported.
+    pub fn with_min_version(mut self, min_version: u32) -> Self {
+        self.min_version = min_version.min(self.version);
+        self
+    }
+
+    /// Requires payloads to be encrypted with XChaCha20-Poly1305, keyed per connection by an
+    /// X25519 key exchange during the handshake.
+    ///
+    /// Payloads sent before a connection has derived its key are held back, and unsealed payloads
+    /// from the peer are dropped, whether received before or after the key. Handshake frames,
+    /// [`Credentials`] and [`Probe`]s are never encrypted, credentials being bound to the key
+    /// exchanged instead so that they cannot be replayed for another session. The exchange is not
+    /// authenticated, so this guards against eavesdropping rather than an active attacker
+    /// relaying the handshake. Connections behind [`VirtualHosts`](crate::VirtualHosts), which
+    /// share their address with other hosts, are rejected.
+    ///
+    /// Sealed packets are not protected against replays: nonces are random and no window of
+    /// received packets is kept, since laminar delivers packets of different streams out of order
+    /// and a window would drop late retransmissions. A packet captured on the wire may therefore
+    /// be sent again by an attacker and is accepted as often. Apps which must not act twice on a
+    /// payload, such as a purchase, should number their messages and discard repeats.
+    ///
+    /// [`Credentials`]: crate::Credentials
+    /// [`Probe`]: crate::Probe
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    /// Supports compressing payloads with zstd, using the [`CompressionDictionary`] resource when
+    /// the peer holds the same dictionary.
+    ///
+    /// Unlike encryption, compression is not required of peers: connections to peers which do not
+    /// support it, such as builds for constrained platforms without the `compression` feature,
+    /// send payloads as is. The outcome is inserted on each connection as a [`Compression`] once
+    /// its handshake completes. Payloads are compressed once queued, so that game systems are
+    /// unaware of it, and only when they shrink. Payloads queued before the handshake completes,
+    /// control frames, broadcasts and payloads sent along with [`Disconnect`] are sent as is.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    /// Returns the protocol id.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the version.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the oldest version of peers which is still supported.
+    pub fn min_version(&self) -> u32 {
+        self.min_version
+    }
+
+    /// Returns `true` if payloads are encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// Returns `true` if payloads may be compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Returns the version spoken with a `peer`, or the reason it is incompatible.
+    pub fn negotiate(&self, peer: &Protocol) -> Result<u32, String> {
+        if self.id != peer.id {
+            return Err(format!(
+                "protocol id {:#x} does not match {:#x}",
+                peer.id, self.id
+            ));
+        }
+        if peer.version < self.min_version {
+            return Err(format!(
+                "version {} is older than the minimum version {}",
+                peer.version, self.min_version
+            ));
+        }
+        if self.version < peer.min_version {
+            return Err(format!(
+                "version {} is older than the peer's minimum version {}",
+                self.version, peer.min_version
+            ));
+        }
+        if self.encrypted != peer.encrypted {
+            return Err(if peer.encrypted {
+                "encryption is required by the peer".to_string()
+            } else {
+                "encryption is not supported by the peer".to_string()
+            });
+        }
+        Ok(self.version.min(peer.version))
+    }
+
+    pub(crate) fn frame(
+        &self,
+        kind: u8,
+        public_opt: Option<[u8; PUBLIC_LEN]>,
+        dictionary_opt: Option<u32>,
+    ) -> Vec<u8> {
+        let dictionary_opt = dictionary_opt.filter(|_| self.compressed);
+        let mut flags = 0;
+        if self.encrypted {
+            flags |= ENCRYPTED_FLAG;
+        }
+        if self.compressed {
+            flags |= COMPRESSED_FLAG;
+        }
+        if dictionary_opt.is_some() {
+            flags |= DICTIONARY_FLAG;
+        }
+
+        let mut frame = Vec::with_capacity(
+            HANDSHAKE_TAG.len() + 1 + PROTOCOL_LEN + PUBLIC_LEN + DICTIONARY_LEN,
+        );
+        frame.extend_from_slice(&HANDSHAKE_TAG);
+        frame.push(kind);
+        frame.extend_from_slice(&self.id.to_be_bytes());
+        frame.extend_from_slice(&self.version.to_be_bytes());
+        frame.extend_from_slice(&self.min_version.to_be_bytes());
+        frame.push(flags);
+        if let Some(public) = public_opt {
+            frame.extend_from_slice(&public);
+        }
+        if let Some(dictionary) = dictionary_opt {
+            frame.extend_from_slice(&dictionary.to_be_bytes());
+        }
+        frame
+    }
+}
+
+/// The protocol of a peer, along with what it advertised for encryption and compression.
+#[derive(Debug)]
+pub(crate) struct Offer {
+    protocol: Protocol,
+    public_opt: Option<[u8; PUBLIC_LEN]>,
+    dictionary_opt: Option<u32>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Frame {
+    Hello(Offer),
+    Accept(Offer),
+    Reject(String),
+}
+
+impl Frame {
+    fn parse(payload: &[u8]) -> Option<Self> {
+        let body = payload.strip_prefix(&HANDSHAKE_TAG)?;
+        let (kind, body) = body.split_first()?;
+        let offer = || {
+            if body.len() < PROTOCOL_LEN {
+                return None;
+            }
+            let (fields, rest) = body.split_at(PROTOCOL_LEN);
+            let flags = fields[16];
+            let encrypted = flags & ENCRYPTED_FLAG != 0;
+            let public_len = if encrypted { PUBLIC_LEN } else { 0 };
+            let dictionary_len = if flags & DICTIONARY_FLAG != 0 {
+                DICTIONARY_LEN
+            } else {
+                0
+            };
+            if rest.len() != public_len + dictionary_len {
+                return None;
+            }
+            let (public, dictionary) = rest.split_at(public_len);
+            let protocol = Protocol {
+                id: u64::from_be_bytes(fields[..8].try_into().ok()?),
+                version: u32::from_be_bytes(fields[8..12].try_into().ok()?),
+                min_version: u32::from_be_bytes(fields[12..16].try_into().ok()?),
+                encrypted,
+                compressed: flags & COMPRESSED_FLAG != 0,
+            };
+            Some(Offer {
+                protocol,
+                public_opt: public.try_into().ok(),
+                dictionary_opt: dictionary.try_into().ok().map(u32::from_be_bytes),
+            })
+        };
+
+        match *kind {
+            HELLO_KIND => offer().map(Self::Hello),
+            ACCEPT_KIND => offer().map(Self::Accept),
+            REJECT_KIND => Some(Self::Reject(String::from_utf8_lossy(body).into_owned())),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn reject(reason: &str) -> Vec<u8> {
+        let mut frame = HANDSHAKE_TAG.to_vec();
+        frame.push(REJECT_KIND);
+        frame.extend_from_slice(reason.as_bytes());
+        frame
+    }
+}
+
+/// Returns the public key offered by `payload`, should it be a hello frame of an encrypted
+/// protocol.
+pub(crate) fn offered_key(payload: &[u8]) -> Option<[u8; PUBLIC_LEN]> {
+    match Frame::parse(payload)? {
+        Frame::Hello(offer) => offer.public_opt,
+        _ => None,
+    }
+}
+
+/// Returns `true` if `payload` is a well-formed handshake frame.
+#[cfg(feature = "encryption")]
+pub(crate) fn is_frame(payload: &[u8]) -> bool {
+    Frame::parse(payload).is_some()
+}
+
+/// Parses a handshake frame and builds it again, see [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    Some(match Frame::parse(payload)? {
+        Frame::Hello(offer) => {
+            offer
+                .protocol
+                .frame(HELLO_KIND, offer.public_opt, offer.dictionary_opt)
+        }
+        Frame::Accept(offer) => {
+            offer
+                .protocol
+                .frame(ACCEPT_KIND, offer.public_opt, offer.dictionary_opt)
+        }
+        Frame::Reject(reason) => Frame::reject(&reason),
+    })
+}
+
+/// An event emitted when the handshake of a connection completes, after which packets from the
+/// peer are delivered to its [`ReceiveQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandshakeCompleted {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The protocol version spoken with the peer, see [`Protocol::negotiate`].
+    pub version: u32,
+}
+
+/// A resource holding how long connections are given to complete their handshake, measured
+/// against the [`NetworkClock`], defaulting to 5 seconds.
+///
+/// Connections still handshaking past it are rejected as they would be by an incompatible peer,
+/// dropping the packets held back, and a [`ConnectionRejected`] event is emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandshakeTimeout(pub Duration);
+
+impl Default for HandshakeTimeout {
+    fn default() -> Self {
+        Self(DEFAULT_HANDSHAKE_TIMEOUT)
+    }
+}
+
+/// The handshake of a connection, holding back packets until it completes.
+#[derive(Debug, Component)]
+pub(crate) enum Handshake {
+    /// Packets held back, and when the handshake started.
+    Pending(Vec<Packet>, Option<Instant>),
+    Complete,
+    Rejected,
+}
+
+/// Disconnects a connection whose handshake failed, sending the reason to the peer when `reply`.
+///
+/// The peer is not blocked: a lost hello or an outdated client is no reason to refuse its address
+/// for good, so blocking is left to the application.
+fn reject(
+    commands: &mut Commands,
+    rejected_writer: &mut EventWriter<ConnectionRejected>,
+    (connection, socket, address): (Entity, &SocketId, &ConnectionAddress),
+    reason: String,
+    reply: bool,
+) {
+    debug!(message = "handshake rejected", ?connection, address = %address.0, %reason);
+    let mut disconnect = Disconnect::new(connection).without_block();
+    if reply {
+        disconnect = disconnect.with_control_goodbye(HANDSHAKE_DELIVERY, Frame::reject(&reason));
+    }
+    commands.add(disconnect);
+    rejected_writer.send(ConnectionRejected {
+        socket: socket.0,
+        address: address.0,
+        reason,
+    });
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn drive_handshakes(
+    protocol: Res<Protocol>,
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &mut ReceiveQueue,
+            &mut ConnectionSendQueue,
+            Option<&mut Handshake>,
+        ),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+    #[cfg(feature = "encryption")] key_query: Query<(
+        Option<&KeyExchange>,
+        Option<&SessionKey>,
+        Option<&HostId>,
+        Option<&BoundKey>,
+    )>,
+    #[cfg(feature = "compression")] dictionary: Option<Res<CompressionDictionary>>,
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut completed_writer: EventWriter<HandshakeCompleted>,
+    mut rejected_writer: EventWriter<ConnectionRejected>,
+    mut commands: Commands,
+) {
+    let now = clock.now(&time);
+    #[cfg(feature = "compression")]
+    let dictionary_opt = dictionary.as_ref().map(|dictionary| dictionary.id());
+    #[cfg(not(feature = "compression"))]
+    let dictionary_opt = None;
+
+    for (connection, socket_id, addr, mut queue, mut send_queue, handshake_opt) in
+        connection_query.iter_mut()
+    {
+        #[cfg(feature = "encryption")]
+        let (exchange_opt, key_opt, host_opt, bound_opt) = key_query
+            .get(connection)
+            .unwrap_or((None, None, None, None));
+        #[cfg(feature = "encryption")]
+        let mut new_exchange = None;
+        #[cfg(feature = "encryption")]
+        let exchange_opt = match exchange_opt {
+            None if protocol.is_encrypted() && handshake_opt.is_none() => {
+                Some(&*new_exchange.insert(KeyExchange::new()))
+            }
+            exchange_opt => exchange_opt,
+        };
+        #[cfg(feature = "encryption")]
+        let public_opt = exchange_opt.map(KeyExchange::public);
+        #[cfg(not(feature = "encryption"))]
+        let public_opt = None;
+
+        let mut reject = |commands: &mut Commands, reason: String, reply: bool| {
+            reject(
+                commands,
+                &mut rejected_writer,
+                (connection, socket_id, addr),
+                reason,
+                reply,
+            )
+        };
+
+        // Connections behind virtual hosts share their address, which keys are derived for
+        #[cfg(feature = "encryption")]
+        if protocol.is_encrypted() && host_opt.is_some() {
+            if handshake_opt.is_none() {
+                reject(
+                    &mut commands,
+                    "virtual hosts do not support encryption".to_string(),
+                    true,
+                );
+                commands.entity(connection).insert(Handshake::Rejected);
+            }
+            queue.0.clear();
+            continue;
+        }
+
+        let mut inserted = None;
+        let handshake = match handshake_opt {
+            Some(handshake) => handshake.into_inner(),
+            None => {
+                trace!(message = "starting handshake", ?connection);
+                send_queue.send_control(
+                    HANDSHAKE_DELIVERY,
+                    protocol.frame(HELLO_KIND, public_opt, dictionary_opt),
+                );
+                inserted.insert(Handshake::Pending(Vec::new(), now))
+            }
+        };
+
+        let is_pending = matches!(handshake, Handshake::Pending(..));
+        if !is_pending && queue.is_empty() {
+            continue;
+        }
+        // Avoid flagging the queue as changed unless there are frames to remove
+        if matches!(handshake, Handshake::Complete)
+            && !queue
+                .iter()
+                .any(|packet| packet.payload().starts_with(&HANDSHAKE_TAG))
+        {
+            continue;
+        }
+
+        #[cfg(feature = "encryption")]
+        let mut derived = None;
+        #[cfg(feature = "compression")]
+        let mut compression = None;
+
+        for packet in mem::take(&mut queue.0) {
+            let frame = match Frame::parse(packet.payload()) {
+                Some(frame) => frame,
+                None => {
+                    match handshake {
+                        Handshake::Pending(held, _) if held.len() < MAX_HELD => held.push(packet),
+                        Handshake::Pending(..) => {
+                            trace!(message = "dropping packet before handshake", ?connection);
+                        }
+                        Handshake::Complete => queue.0.push_back(packet),
+                        Handshake::Rejected => {}
+                    }
+                    continue;
+                }
+            };
+            if matches!(handshake, Handshake::Rejected) {
+                continue;
+            }
+
+            let (offer, is_hello) = match frame {
+                Frame::Hello(offer) => (offer, true),
+                Frame::Accept(offer) => (offer, false),
+                Frame::Reject(reason) => {
+                    *handshake = Handshake::Rejected;
+                    reject(
+                        &mut commands,
+                        format!("rejected by peer: {}", reason),
+                        false,
+                    );
+                    continue;
+                }
+            };
+
+            let peer = offer.protocol;
+            let result = protocol.negotiate(&peer);
+            // Derive the keys anew should the peer have restarted its side of the handshake
+            #[cfg(feature = "encryption")]
+            let result = result.and_then(|version| match bound_opt {
+                Some(bound) if offer.public_opt != Some(bound.0) => {
+                    Err("public key does not match credentials".to_string())
+                }
+                _ => Ok(version),
+            });
+            #[cfg(feature = "encryption")]
+            let result = result.and_then(|version| match (exchange_opt, offer.public_opt) {
+                (Some(exchange), Some(peer_public))
+                    if !derived
+                        .as_ref()
+                        .or(key_opt)
+                        .is_some_and(|key: &SessionKey| key.is_for(&peer_public)) =>
+                {
+                    let key = exchange
+                        .derive(peer_public)
+                        .ok_or_else(|| "invalid public key".to_string())?;
+                    derived = Some(key);
+                    Ok(version)
+                }
+                _ => Ok(version),
+            });
+
+            match result {
+                Ok(version) => {
+                    #[cfg(feature = "compression")]
+                    {
+                        compression = Some(Compression::negotiate(
+                            &protocol,
+                            dictionary_opt,
+                            &peer,
+                            offer.dictionary_opt,
+                        ));
+                    }
+                    // Also answers peers which restarted their side of the handshake
+                    if is_hello {
+                        send_queue.send_control(
+                            HANDSHAKE_DELIVERY,
+                            protocol.frame(ACCEPT_KIND, public_opt, dictionary_opt),
+                        );
+                    }
+                    if let Handshake::Pending(held, _) = handshake {
+                        trace!(message = "handshake completed", ?connection, version);
+                        queue.0.extend(held.drain(..));
+                        *handshake = Handshake::Complete;
+                        completed_writer.send(HandshakeCompleted {
+                            connection,
+                            version,
+                        });
+                    }
+                }
+                Err(reason) => {
+                    *handshake = Handshake::Rejected;
+                    reject(&mut commands, reason, true);
+                }
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(key) = derived {
+                // Packets sealed by the peer may have arrived before its key
+                if matches!(handshake, Handshake::Complete) {
+                    queue.0 = mem::take(&mut queue.0)
+                        .into_iter()
+                        .filter_map(|packet| key.open_held(packet))
+                        .collect();
+                }
+                commands.entity(connection).insert(key);
+            }
+            if let Some(exchange) = new_exchange {
+                commands.entity(connection).insert(exchange);
+            }
+        }
+        #[cfg(feature = "compression")]
+        if let Some(compression) = compression {
+            commands.entity(connection).insert(compression);
+        }
+        if let Some(handshake) = inserted {
+            commands.entity(connection).insert(handshake);
+        }
+    }
+}
+
+pub(crate) fn expire_handshakes(
+    timeout: Res<HandshakeTimeout>,
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut connection_query: Query<
+        (Entity, &SocketId, &ConnectionAddress, &mut Handshake),
+        With<ConnectionMarker>,
+    >,
+    mut rejected_writer: EventWriter<ConnectionRejected>,
+    mut commands: Commands,
+) {
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, socket_id, addr, mut handshake) in connection_query.iter_mut() {
+        // Avoid flagging the handshake as changed unless it expired
+        let since = match *handshake {
+            Handshake::Pending(_, since) => since,
+            _ => continue,
+        };
+        let since = match since {
+            Some(some) => some,
+            None => {
+                if let Handshake::Pending(_, since) = &mut *handshake {
+                    *since = Some(now);
+                }
+                continue;
+            }
+        };
+        if now.saturating_duration_since(since) < timeout.0 {
+            continue;
+        }
+
+        // Dropping the pending handshake frees the packets held back
+        *handshake = Handshake::Rejected;
+        reject(
+            &mut commands,
+            &mut rejected_writer,
+            (connection, socket_id, addr),
+            "handshake timed out".to_string(),
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bevy::app::Events;
+
+    use super::*;
+    use crate::{BlockedAddresses, SendQueue};
+
+    fn world(protocol: Protocol) -> World {
+        let mut time = Time::default();
+        time.update();
+
+        let mut world = World::new();
+        world.insert_resource(protocol);
+        world.insert_resource(HandshakeTimeout::default());
+        world.insert_resource(NetworkClock::default());
+        world.insert_resource(time);
+        world.insert_resource(Events::<HandshakeCompleted>::default());
+        world.insert_resource(Events::<ConnectionRejected>::default());
+        world
+    }
+
+    /// Spawns a socket and a connection which received `payloads`, returning both.
+    fn spawn_connection(world: &mut World, payloads: Vec<Vec<u8>>) -> (Entity, Entity) {
+        let addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let socket = world.spawn().insert(SendQueue::default()).id();
+        let packets = payloads
+            .into_iter()
+            .map(|payload| Packet::reliable_unordered(addr, payload))
+            .collect();
+        let connection = world
+            .spawn()
+            .insert_bundle((
+                ConnectionMarker,
+                SocketId(socket),
+                ConnectionAddress(addr),
+                ReceiveQueue(packets),
+                ConnectionSendQueue::default(),
+            ))
+            .id();
+        (socket, connection)
+    }
+
+    fn drive(world: &mut World) {
+        SystemStage::single_threaded()
+            .with_system(drive_handshakes)
+            .run(world);
+    }
+
+    fn expire(world: &mut World) {
+        SystemStage::single_threaded()
+            .with_system(expire_handshakes)
+            .run(world);
+    }
+
+    fn rejections(world: &World) -> Vec<String> {
+        let events = world.get_resource::<Events<ConnectionRejected>>().unwrap();
+        events
+            .get_reader()
+            .iter(events)
+            .map(|rejected| rejected.reason.clone())
+            .collect()
+    }
+
+    fn handshake(world: &World, connection: Entity) -> &Handshake {
+        world.get::<Handshake>(connection).unwrap()
+    }
+
+    #[test]
+    fn completes_and_releases_held_packets() {
+        let protocol = Protocol::new(7, 1);
+        let mut world = world(protocol);
+        let hello = protocol.frame(HELLO_KIND, None, None);
+        let (_, connection) = spawn_connection(&mut world, vec![b"early".to_vec(), hello]);
+
+        drive(&mut world);
+
+        assert!(matches!(handshake(&world, connection), Handshake::Complete));
+        let queue = world.get::<ReceiveQueue>(connection).unwrap();
+        let payloads: Vec<_> = queue.iter().map(Packet::payload).collect();
+        assert_eq!(payloads, [b"early"]);
+        assert!(rejections(&world).is_empty());
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut world = world(Protocol::new(7, 1));
+        let hello = Protocol::new(7, 2).frame(HELLO_KIND, None, None);
+        let (socket, connection) = spawn_connection(&mut world, vec![hello, b"early".to_vec()]);
+
+        drive(&mut world);
+
+        assert!(matches!(handshake(&world, connection), Handshake::Rejected));
+        assert!(world.get::<ReceiveQueue>(connection).unwrap().is_empty());
+        assert_eq!(
+            rejections(&world),
+            ["version 1 is older than the peer's minimum version 2"]
+        );
+        // The peer is told why, without being blocked
+        let send_queue = world.get::<SendQueue>(socket).unwrap();
+        assert!(send_queue
+            .iter()
+            .any(|packet| matches!(Frame::parse(packet.payload()), Some(Frame::Reject(_)))));
+        assert!(world.get::<BlockedAddresses>(socket).is_none());
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let mut world = world(Protocol::new(7, 1));
+        let (socket, connection) = spawn_connection(&mut world, vec![b"early".to_vec()]);
+
+        drive(&mut world);
+        assert!(matches!(
+            handshake(&world, connection),
+            Handshake::Pending(held, Some(_)) if held.len() == 1
+        ));
+
+        let mut clock = world.get_resource_mut::<NetworkClock>().unwrap();
+        clock.advance(DEFAULT_HANDSHAKE_TIMEOUT - Duration::from_millis(1));
+        expire(&mut world);
+        assert!(matches!(
+            handshake(&world, connection),
+            Handshake::Pending(..)
+        ));
+
+        let mut clock = world.get_resource_mut::<NetworkClock>().unwrap();
+        clock.advance(Duration::from_millis(1));
+        expire(&mut world);
+        assert!(matches!(handshake(&world, connection), Handshake::Rejected));
+        assert_eq!(rejections(&world), ["handshake timed out"]);
+        assert!(world.get::<BlockedAddresses>(socket).is_none());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn key_other_than_bound() {
+        let protocol = Protocol::new(7, 1).with_encryption();
+        let mut world = world(protocol);
+        let bound = KeyExchange::new().public();
+        let offered = KeyExchange::new().public();
+        let hello = protocol.frame(HELLO_KIND, Some(offered), None);
+        let (_, connection) = spawn_connection(&mut world, vec![hello]);
+        world.entity_mut(connection).insert(BoundKey(bound));
+
+        drive(&mut world);
+
+        assert!(matches!(handshake(&world, connection), Handshake::Rejected));
+        assert!(world.get::<SessionKey>(connection).is_none());
+        assert_eq!(
+            rejections(&world),
+            ["public key does not match credentials"]
+        );
+    }
+}
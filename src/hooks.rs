@@ -0,0 +1,126 @@
+use std::{fmt::Debug, net::SocketAddr};
+
+use bevy::{
+    ecs::system::{CommandQueue, EntityCommands},
+    prelude::*,
+};
+
+use crate::{audit::RecordTransition, peers::RecordKnownPeer, ConnectionState};
+
+/// A change in a connection's [`ConnectionState`], passed to [`ConnectionHooks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionTransition {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The socket entity owning the connection.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// The previous state, or `None` if the connection is being spawned.
+    pub from: Option<ConnectionState>,
+    /// The new state.
+    pub to: ConnectionState,
+}
+
+type Hook = Box<dyn Fn(&ConnectionTransition, &mut EntityCommands) + Send + Sync + 'static>;
+
+/// A resource holding callbacks which run as connections change [`ConnectionState`].
+///
+/// Hooks run inside the network systems making the change, against the [`EntityCommands`] of the
+/// connection, so that anything they insert or remove is applied together with the change.
+/// Register hooks through `ResMut<ConnectionHooks>`.
+#[derive(Default)]
+pub struct ConnectionHooks {
+    hooks: Vec<(Option<ConnectionState>, Hook)>,
+}
+
+impl ConnectionHooks {
+    /// Registers a hook run whenever a connection enters `state`, including when spawned in it.
+    pub fn on_enter<F>(&mut self, state: ConnectionState, f: F) -> &mut Self
+    where
+        F: Fn(&ConnectionTransition, &mut EntityCommands) + Send + Sync + 'static,
+    {
+        self.hooks.push((Some(state), Box::new(f)));
+        self
+    }
+
+    /// Registers a hook run on every transition.
+    pub fn on_transition<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&ConnectionTransition, &mut EntityCommands) + Send + Sync + 'static,
+    {
+        self.hooks.push((None, Box::new(f)));
+        self
+    }
+
+    pub(crate) fn run(&self, transition: &ConnectionTransition, commands: &mut Commands) {
+        let mut entity_commands = commands.entity(transition.connection);
+        for (state_opt, hook) in &self.hooks {
+            if state_opt.is_none_or(|state| state == transition.to) {
+                hook(transition, &mut entity_commands);
+            }
+        }
+        commands.add(RecordTransition(*transition));
+        commands.add(RecordKnownPeer(*transition));
+    }
+}
+
+impl Debug for ConnectionHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionHooks")
+            .field("len", &self.hooks.len())
+            .finish()
+    }
+}
+
+/// Sets a connection's state, running the [`ConnectionHooks`] if it changed.
+pub(crate) fn transition(
+    state: &mut Mut<ConnectionState>,
+    to: ConnectionState,
+    (connection, socket, address): (Entity, Entity, SocketAddr),
+    hooks: &ConnectionHooks,
+    commands: &mut Commands,
+) {
+    let from = **state;
+    if from == to {
+        return;
+    }
+
+    **state = to;
+    let transition = ConnectionTransition {
+        connection,
+        socket,
+        address,
+        from: Some(from),
+        to,
+    };
+    hooks.run(&transition, commands);
+}
+
+/// Sets a connection's state from a [`Command`](bevy::ecs::system::Command), running the
+/// [`ConnectionHooks`] if it changed.
+pub(crate) fn transition_world(
+    world: &mut World,
+    to: ConnectionState,
+    (connection, socket, address): (Entity, Entity, SocketAddr),
+) {
+    let from = match world.get_mut::<ConnectionState>(connection) {
+        Some(mut state) if *state != to => std::mem::replace(&mut *state, to),
+        _ => return,
+    };
+
+    // Hooks expect commands, apply them against the world straight away
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    if let Some(hooks) = world.get_resource::<ConnectionHooks>() {
+        let transition = ConnectionTransition {
+            connection,
+            socket,
+            address,
+            from: Some(from),
+            to,
+        };
+        hooks.run(&transition, &mut commands);
+    }
+    queue.apply(world);
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use laminar::Packet;
+
+use crate::{ConnectionMarker, ConnectionState};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::packet::with_payload;
+
+/// A [`Component`] identifying the virtual host a connection belongs to.
+///
+/// Present on connections of sockets with [`VirtualHosts`]. A connection opened with
+/// [`Connect`](crate::Connect) to a virtual host should be given the [`HostId`] of that host, its
+/// socket serving the same host in its own [`VirtualHosts`] so that replies are routed back.
+///
+/// Payloads sent through the [`ConnectionSendQueue`](crate::ConnectionSendQueue) of a connection
+/// with a [`HostId`] are routed to its host, as are broadcasts and goodbyes.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HostId(pub u16);
+
+impl HostId {
+    const PREFIX_LEN: usize = 2;
+
+    /// Prefixes a [`Packet`] so that the peer routes it to this host.
+    ///
+    /// Packets pushed directly to the [`SendQueue`](crate::SendQueue) of a socket with
+    /// [`VirtualHosts`] must be routed, those sent through connections are routed automatically.
+    pub fn route(self, packet: Packet) -> Packet {
+        with_payload(&packet, self.prefix(packet.payload()))
+    }
+
+    /// Prefixes a payload so that the peer routes it to this host.
+    pub(crate) fn prefix(self, payload: &[u8]) -> Vec<u8> {
+        let mut prefixed = Vec::with_capacity(Self::PREFIX_LEN + payload.len());
+        prefixed.extend_from_slice(&self.0.to_be_bytes());
+        prefixed.extend_from_slice(payload);
+        prefixed
+    }
+
+    /// Strips the prefix from a received [`Packet`], returning `None` if it is too short.
+    pub(crate) fn strip(packet: Packet) -> Option<(Self, Packet)> {
+        let payload = packet.payload();
+        if payload.len() < Self::PREFIX_LEN {
+            return None;
+        }
+
+        let host = HostId(u16::from_be_bytes([payload[0], payload[1]]));
+        let stripped = with_payload(&packet, payload[Self::PREFIX_LEN..].to_vec());
+        Some((host, stripped))
+    }
+}
+
+/// A [`Component`] whose presence on a socket entity shares the socket between several virtual
+/// hosts, for example one per match.
+///
+/// Each received packet is expected to carry a [`HostId`] prefix, see [`HostId::route`].
+/// Connections are then spawned per address and host pair, each carrying a [`HostId`], and
+/// connection limits are enforced per host. Packets without a prefix, or routed to a host which is
+/// not served, are dropped. [`Probe`](crate::Probe)s bypass routing, their echoes being delivered
+/// to every connection of the address.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
+pub struct VirtualHosts {
+    /// The hosts served by the socket.
+    pub hosts: HashSet<HostId>,
+    /// The maximum number of connections for hosts without an entry in `limits`.
+    pub default_limit: Option<usize>,
+    /// The maximum number of connections for specific hosts.
+    pub limits: HashMap<HostId, usize>,
+}
+
+impl VirtualHosts {
+    /// Serves a host.
+    pub fn with_host(mut self, host: HostId) -> Self {
+        self.hosts.insert(host);
+        self
+    }
+
+    /// Returns `true` if the socket serves a host.
+    pub fn serves(&self, host: HostId) -> bool {
+        self.hosts.contains(&host)
+    }
+
+    /// Returns the maximum number of connections permitted for a host, if any.
+    pub fn limit(&self, host: HostId) -> Option<usize> {
+        self.limits.get(&host).copied().or(self.default_limit)
+    }
+}
+
+/// An event emitted when a connection joins a virtual host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionStarted {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The host joined.
+    pub host: HostId,
+}
+
+/// The reason a session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SessionEndReason {
+    /// The connection reached [`ConnectionState::Disconnected`] or [`ConnectionState::TimedOut`].
+    Disconnected,
+    /// The connection was despawned or its [`HostId`] removed.
+    Removed,
+}
+
+/// An event emitted when a connection leaves a virtual host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionEnded {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The host left.
+    pub host: HostId,
+    /// Why the session ended.
+    pub reason: SessionEndReason,
+}
+
+/// Tracks sessions on virtual hosts, a session lasting while its connection is not disconnected.
+#[allow(clippy::type_complexity)]
+pub(crate) fn track_sessions(
+    mut sessions: Local<HashMap<Entity, HostId>>,
+    query: Query<
+        (Entity, &HostId, &ConnectionState),
+        (
+            With<ConnectionMarker>,
+            Or<(Added<HostId>, Changed<ConnectionState>)>,
+        ),
+    >,
+    removed: RemovedComponents<HostId>,
+    mut started_writer: EventWriter<SessionStarted>,
+    mut ended_writer: EventWriter<SessionEnded>,
+) {
+    for (connection, host, state) in query.iter() {
+        let active = !state.is_disconnected();
+        if active && !sessions.contains_key(&connection) {
+            sessions.insert(connection, *host);
+            started_writer.send(SessionStarted {
+                connection,
+                host: *host,
+            });
+        } else if !active {
+            if let Some(host) = sessions.remove(&connection) {
+                ended_writer.send(SessionEnded {
+                    connection,
+                    host,
+                    reason: SessionEndReason::Disconnected,
+                });
+            }
+        }
+    }
+
+    for connection in removed.iter() {
+        if let Some(host) = sessions.remove(&connection) {
+            ended_writer.send(SessionEnded {
+                connection,
+                host,
+                reason: SessionEndReason::Removed,
+            });
+        }
+    }
+}
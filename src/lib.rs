@@ -12,43 +12,394 @@
 //! Both sockets and their connections are represented by entities and distinguished by
 //! [`SocketMarker`] and [`ConnectionMarker`] respectively.
 //!
-//! To send packets one should use [`SendQueue`] on the socket entity. Conversely, to receive
-//! packets one should use [`ReceiveQueue`] on the connection entity.
+//! To send packets one should use [`SendQueue`] on the socket entity, or
+//! [`ConnectionSendQueue`] on the connection entity to have them addressed to its peer. Conversely,
+//! to receive packets one should use [`ReceiveQueue`] on the connection entity. Payloads meant for
+//! every peer of a socket may be sent with its [`BroadcastQueue`]. Traffic may be split into named
+//! [`Channel`]s, each on its own laminar stream, registered with [`AddChannel`]. Packets may be
+//! transformed or dropped on their way to and from the wire by the [`MiddlewareChain`] of their
+//! socket, and a [`NetworkConditions`] may simulate latency, jitter, loss and duplication.
+//! With the `serde` feature, typed messages may be registered using
+//! `App::add_network_message` and received as `MessageReceived` events instead, optionally
+//! stamped with a `TraceId` to be followed across peers' logs, components of
+//! `Replicated` entities mirrored on peers using `App::replicate`, and remote calls registered
+//! using `App::add_rpc`.
+//!
+//! For the common client-server case, [`ClientPlugin`] and [`ServerPlugin`] bind the socket and,
+//! for the client, open the connection to the server at startup. Public servers may require
+//! clients to present [`Credentials`] to an [`Authenticator`] before they are spawned a connection.
+//! With the `serde` feature, servers may tune their clients' networking settings live by pushing
+//! them `ClientSettings`.
+//! With the `encryption` feature, the [`Protocol`] exchanged during the handshake may require
+//! payloads to be encrypted using `Protocol::with_encryption`, and with the `compression` feature
+//! may negotiate zstd compression per connection using `Protocol::with_compression`. Clients may
+//! keep track of the servers they reached in [`KnownPeers`] to choose where to connect to next.
 //!
 //! Connection entities will be spawned automatically by a socket entity when a message has been
-//! received. In addition to [`ReceiveQueue`] and [`ConnectionMarker`] they will include
-//! [`SocketId`] and [`ConnectionAddress`].
+//! received, or may be opened ahead of time using [`Connect`]. In addition to [`ReceiveQueue`],
+//! [`ConnectionSendQueue`] and [`ConnectionMarker`] they will include [`SocketId`],
+//! [`ConnectionAddress`] and [`NetworkStats`].
+//!
+//! Events are processed in the order laminar reports them: within a tick, connection entities are
+//! spawned in the order their peers were first heard from and packets are appended to each
+//! [`ReceiveQueue`] in arrival order, making both deterministic for a given sequence of events.
 //!
 //! Socket entities must be spawned by the user and [`Bundle`]s describing them are yielded from
-//! the [`bind`] and [`bind_with_config`] functions. In addition to [`ReceiveQueue`] and
-//! [`SocketMarker`] they will include [`PollInterval`].
+//! the [`bind`] and [`bind_with_config`] functions, or from [`bind_loopback`] for in-memory sockets
+//! which never touch the network, such as in tests. Other backends may be plugged in by
+//! implementing [`Transport`] and spawning the [`Bundle`] of [`bind_with_transport`]. With the
+//! `websocket` feature, servers may accept browser clients over WebSockets with `bind_websocket`.
+//! Alternatively, [`SocketDescriptor`]s pushed to the [`SocketDescriptors`] resource are spawned
+//! by the plugin. In addition to [`ReceiveQueue`] and [`SocketMarker`] they will include
+//! [`PollInterval`], [`BroadcastQueue`] and [`NetworkStats`].
+//!
+//! The lifecycle of a connection is carried by values rather than by the presence of components:
+//! states such as [`ConnectionState::Suspect`] are enum variants updated in place, and the
+//! components the plugin spawns connections with are kept for their lifetime. Connections
+//! therefore stay in the same archetype from tick to tick, and queries filtering on
+//! [`Changed<ConnectionState>`](Changed) see every transition. The plugin only inserts or removes
+//! components on one-off events, such as a [`SessionTtl`] being set or expiring, never every tick.
+//!
+//! The bytes of the plugin's own frames are versioned by [`WIRE_VERSION`]. Payloads recorded in a
+//! [`WireCapture`] may be kept along an app and verified after upgrading the plugin, to catch
+//! releases which would no longer understand peers built with the previous one.
 
+mod audit;
+mod auth;
+mod channel;
+mod client;
+mod clock;
+#[cfg(feature = "compression")]
+mod compression;
+mod conditions;
+mod connect;
 mod connection;
+mod descriptor;
+mod diagnostics;
+mod disconnect;
+mod echo;
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "serde")]
+mod entity_map;
+mod filter;
+mod group;
+mod handshake;
+mod hooks;
+mod host;
+mod limit;
+mod liveness;
+mod loopback;
+mod mesh;
+#[cfg(feature = "serde")]
+mod message;
+mod middleware;
+mod options;
+mod packet;
+mod param;
+mod peers;
+mod polling;
+mod pool;
+#[cfg(feature = "serde")]
+mod replication;
+#[cfg(feature = "serde")]
+mod rpc;
+mod rtt;
+mod selftest;
+mod server;
+#[cfg(feature = "serde")]
+mod settings;
 mod socket;
+mod stats;
+mod strict;
+mod transport;
+#[cfg(feature = "websocket")]
+mod websocket;
+mod wire;
 
 use std::{
-    collections::{hash_map::Entry, HashMap, VecDeque},
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     hash::Hash,
-    net::{SocketAddr, ToSocketAddrs},
-    time::Duration,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
 };
 
-use bevy::prelude::*;
+use bevy::{diagnostic::Diagnostics, ecs::system::SystemParam, prelude::*, utils::tracing::field};
 
+use audit::record_audit_events;
+pub use audit::{AuditEntry, AuditEvent, AuditLog};
+use auth::{send_credentials, Verdict};
+pub use auth::{AuthenticationFailed, Authenticator, ClientId, ConnectToken, Credentials};
+pub use channel::{AddChannel, Channel, Channels};
+pub use client::{ClientPlugin, ServerConnection};
+pub use clock::NetworkClock;
+#[cfg(feature = "compression")]
+use compression::{compress_payloads, decompress_packets};
+#[cfg(feature = "compression")]
+pub use compression::{Compression, CompressionDictionary};
+pub use conditions::NetworkConditions;
+pub use connect::Connect;
 pub use connection::*;
-use laminar::SocketEvent;
-pub use laminar::{Config, Packet};
+pub use descriptor::*;
+use diagnostics::FlushMetrics;
+pub use diagnostics::NetworkDiagnosticsPlugin;
+pub use disconnect::{CloseConnection, Disconnect};
+pub use echo::*;
+#[cfg(feature = "encryption")]
+use encryption::{BoundKey, Encryption, Outgoing};
+#[cfg(feature = "serde")]
+pub use entity_map::{NetworkEntityMap, NetworkId};
+pub use filter::{AddressFilter, AddressPattern, AddressRejected};
+pub use group::{GroupError, MessageGroup};
+use handshake::{drive_handshakes, expire_handshakes};
+pub use handshake::{HandshakeCompleted, HandshakeTimeout, Protocol};
+pub use hooks::{ConnectionHooks, ConnectionTransition};
+pub use host::*;
+pub use laminar::{Config, Packet, SocketEvent};
+use limit::{Admission, SpawnCounter, SpawnOrder};
+pub use limit::{ConnectionLimit, EvictionPolicy};
+use liveness::{check_liveness, expire_grace_periods, send_heartbeats};
+pub use liveness::{ConnectionSuspected, GracePeriod, Heartbeat, Liveness};
+use loopback::LoopbackSocket;
+use mesh::drive_meshes;
+pub use mesh::{Mesh, MeshReady};
+#[cfg(feature = "serde")]
+pub use message::*;
+pub use middleware::{Middleware, MiddlewareChain};
+pub use options::*;
+pub use packet::Delivery;
+pub use param::*;
+use peers::update_known_peers;
+pub use peers::{KnownPeer, KnownPeers};
+use polling::{start_polling_threads, update_bridge_stats};
+pub use polling::{EventBridge, EventBridgeStats, OverflowPolicy, PollingMode};
+pub use pool::ConnectionPool;
+use pool::ConnectionSpawner;
+#[cfg(feature = "serde")]
+pub use replication::*;
+#[cfg(feature = "serde")]
+pub use rpc::{AddRpc, CallId, Rpc, RpcRequest, RpcResponse, RpcTimedOut};
+use rtt::measure_rtt;
+pub use rtt::Rtt;
+use selftest::{run_self_tests, start_self_tests};
+pub use selftest::{RunSelfTest, SelfTestCompleted, SelfTestError};
+pub use server::{Clients, ServerPlugin, ServerSocket};
+#[cfg(feature = "serde")]
+use settings::apply_client_settings;
+#[cfg(feature = "serde")]
+pub use settings::{
+    AcceptSettings, ClientSettings, ClientSettingsApplied, InterpolationDelay,
+    MAX_PUSHED_POLL_INTERVAL, MIN_PUSHED_POLL_INTERVAL,
+};
 pub use socket::*;
+pub use stats::NetworkStats;
+use strict::check_misuse;
+pub use transport::Transport;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketTransport;
+pub use wire::{FrameKind, WireCapture, WireMismatch, WIRE_VERSION};
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn flush_send(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    policy: Res<ConnectedPolicy>,
+    hooks: Res<ConnectionHooks>,
+    index: Res<ConnectionIndex>,
+    mut socket_query: Query<
+        (
+            Entity,
+            &mut Socket,
+            &mut SendQueue,
+            Option<&UnreachableAddresses>,
+            Option<&mut PacketSizeHistogram>,
+            Option<&mut NetworkStats>,
+            Option<&mut MiddlewareChain>,
+            Option<&mut NetworkConditions>,
+        ),
+        Without<ConnectionMarker>,
+    >,
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &mut ConnectionState,
+            Option<&mut NetworkStats>,
+        ),
+        With<ConnectionMarker>,
+    >,
+    mut error_writer: EventWriter<SendError>,
+    mut metrics: ResMut<FlushMetrics>,
+    mut diagnostics_opt: Option<ResMut<Diagnostics>>,
+    #[cfg(feature = "encryption")] encryption: Encryption,
+    mut commands: Commands,
+) {
+    let now = clock.now(&time);
+
+    // Aggregate the flush into a single span rather than tracing every packet
+    let span = debug_span!(
+        "flush",
+        packets = field::Empty,
+        bytes = field::Empty,
+        peers = field::Empty,
+        max_packets_per_peer = field::Empty,
+        errors = field::Empty,
+        serialize_us = field::Empty,
+        laminar_send_us = field::Empty,
+    );
+    let _entered = span.enter();
+
+    for (
+        socket_id,
+        mut socket,
+        mut queue,
+        unreachable_opt,
+        mut histogram_opt,
+        mut stats_opt,
+        mut middleware_opt,
+        mut conditions_opt,
+    ) in socket_query.iter_mut()
+    {
+        let mut sent_to = HashMap::new();
+        #[cfg(feature = "encryption")]
+        let mut held = Vec::new();
+
+        let mut outgoing = Vec::new();
+        for (packet, is_control) in queue.drain() {
+            // Only encryption tells control frames apart
+            #[cfg(not(feature = "encryption"))]
+            let _ = is_control;
+            let packet_addr = packet.addr();
+            if unreachable_opt.is_some_and(|unreachable| unreachable.is_unreachable(&packet_addr)) {
+                trace!(message = "purging packet to unreachable address", address = %packet_addr);
+                continue;
+            }
+
+            #[cfg(feature = "encryption")]
+            let packet = {
+                let is_disconnected = index
+                    .get(socket_id, packet_addr)
+                    .last()
+                    .and_then(|connection| connection_query.get(*connection).ok())
+                    .is_some_and(|(_, _, _, state, _)| state.is_disconnected());
+                match encryption.seal(socket_id, packet, is_control, is_disconnected) {
+                    Outgoing::Send(packet) => packet,
+                    Outgoing::Hold(packet) => {
+                        held.push(packet);
+                        continue;
+                    }
+                    Outgoing::Drop => continue,
+                }
+            };
+
+            let packet = match middleware_opt.as_mut() {
+                Some(middleware) => match middleware.send(packet) {
+                    Some(packet) => packet,
+                    None => {
+                        trace!(message = "packet dropped by middleware", address = %packet_addr);
+                        continue;
+                    }
+                },
+                None => packet,
+            };
+            outgoing.push(packet);
+        }
+
+        if let Some(conditions) = conditions_opt.as_mut() {
+            outgoing = conditions.send(outgoing, now.unwrap_or_else(Instant::now));
+        }
+
+        for packet in outgoing {
+            let packet_addr = packet.addr();
+            let packet_len = packet.payload().len();
+            let mut connection_stats_opt = index
+                .get(socket_id, packet_addr)
+                .last()
+                .and_then(|connection| connection_query.get_mut(*connection).ok())
+                .and_then(|(_, _, _, _, stats_opt)| stats_opt);
+
+            let start = Instant::now();
+            let result = socket.send(packet);
+            metrics.laminar_send_time += start.elapsed();
 
-fn flush_send(mut query: Query<(&mut Socket, &mut SendQueue)>) {
-    for (mut socket, mut queue) in query.iter_mut() {
-        for packet in queue.0.drain(..) {
-            if let Err(error) = socket.0.send(packet) {
+            if let Err(error) = result {
                 error!(message = "failed to send", %error);
+                metrics.errors += 1;
+                if let Some(stats) = stats_opt.as_mut() {
+                    stats.record_send_error();
+                }
+                if let Some(stats) = connection_stats_opt.as_mut() {
+                    stats.record_send_error();
+                }
+                error_writer.send(SendError {
+                    socket: socket_id,
+                    address: packet_addr,
+                    len: packet_len,
+                    error,
+                });
+            } else {
+                *sent_to.entry(normalize_address(packet_addr)).or_insert(0) += 1;
+                metrics.packets += 1;
+                metrics.bytes += packet_len as u64;
+                if let Some(histogram) = histogram_opt.as_mut() {
+                    histogram.record_sent(packet_len);
+                }
+                if let Some(stats) = stats_opt.as_mut() {
+                    stats.record_sent(packet_len, now);
+                }
+                if let Some(stats) = connection_stats_opt.as_mut() {
+                    stats.record_sent(packet_len, now);
+                }
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        for packet in held {
+            queue.send(packet);
+        }
+
+        metrics.peers += sent_to.len() as u64;
+        metrics.max_packets_per_peer = sent_to
+            .values()
+            .copied()
+            .fold(metrics.max_packets_per_peer, u64::max);
+
+        if *policy != ConnectedPolicy::Bidirectional || sent_to.is_empty() {
+            continue;
+        }
+
+        // Connection entities only exist once something has been received, so sending to a
+        // pending connection completes the exchange
+        for (connection, id, addr, mut state, _) in connection_query.iter_mut() {
+            if id.0 == socket_id
+                && *state == ConnectionState::Pending
+                && sent_to.contains_key(&addr.normalized())
+            {
+                trace!(message = "promoting connection", address = %addr.0);
+                hooks::transition(
+                    &mut state,
+                    ConnectionState::Connected,
+                    (connection, socket_id, addr.0),
+                    &hooks,
+                    &mut commands,
+                );
             }
         }
     }
+
+    span.record("packets", metrics.packets);
+    span.record("bytes", metrics.bytes);
+    span.record("peers", metrics.peers);
+    span.record("max_packets_per_peer", metrics.max_packets_per_peer);
+    span.record("errors", metrics.errors);
+    span.record("serialize_us", metrics.serialize_time.as_micros() as u64);
+    span.record(
+        "laminar_send_us",
+        metrics.laminar_send_time.as_micros() as u64,
+    );
+    metrics.finish(diagnostics_opt.as_deref_mut());
 }
 
 /// Represents the current state of a connection.
@@ -58,13 +409,73 @@ pub enum ConnectionState {
     Connected,
     /// Connection has received a message.
     Pending,
+    /// Connection was opened locally with [`Connect::connect`] and has not received a message.
+    Connecting,
+    /// Connection has not received a message for longer than its [`Liveness`] permits, but may
+    /// yet recover.
+    Suspect,
     /// Connection has been disconnected.
     Disconnected,
+    /// Connection was dropped by laminar after the peer went unheard for longer than the idle
+    /// connection timeout, or too many packets were left unacknowledged.
+    TimedOut,
+}
+
+impl ConnectionState {
+    /// Returns `true` if the connection is [`Disconnected`](Self::Disconnected) or
+    /// [`TimedOut`](Self::TimedOut).
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Self::Disconnected | Self::TimedOut)
+    }
+}
+
+/// Determines when a connection is promoted to [`ConnectionState::Connected`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectedPolicy {
+    /// Follow laminar exactly: a connection becomes connected when laminar emits its connect
+    /// event. Laminar only does so while polling, after it has itself processed both a send and a
+    /// receive for the peer, so a connection may remain [`ConnectionState::Pending`] for a number
+    /// of ticks while packets are already being exchanged.
+    #[default]
+    Laminar,
+    /// Promote a pending connection as soon as a packet has been flushed to it, that is, once
+    /// traffic has flowed both ways.
+    Bidirectional,
 }
 
+#[derive(Default)]
 struct Action {
     state: Option<ConnectionState>,
     packets: VecDeque<Packet>,
+    /// Laminar has dropped the address from its connection table.
+    dropped: bool,
+}
+
+/// [`Action`]s keyed by address, iterated in the order each address was first seen.
+#[derive(Default)]
+struct Actions {
+    indices: HashMap<SocketAddr, usize>,
+    actions: Vec<(SocketAddr, Action)>,
+}
+
+impl Actions {
+    fn entry(&mut self, address: SocketAddr) -> &mut Action {
+        let actions = &mut self.actions;
+        let index = *self.indices.entry(address).or_insert_with(|| {
+            actions.push((address, Action::default()));
+            actions.len() - 1
+        });
+        &mut self.actions[index].1
+    }
+}
+
+impl IntoIterator for Actions {
+    type Item = (SocketAddr, Action);
+    type IntoIter = std::vec::IntoIter<(SocketAddr, Action)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.actions.into_iter()
+    }
 }
 
 #[derive(Bundle)]
@@ -73,11 +484,66 @@ struct ConnectionBundle {
     socket_id: SocketId,
     address: ConnectionAddress,
     queue: ReceiveQueue,
+    send_queue: ConnectionSendQueue,
     state: ConnectionState,
+    order: SpawnOrder,
+    stats: NetworkStats,
 }
 
+impl ConnectionBundle {
+    fn new(
+        socket_id: Entity,
+        address: SocketAddr,
+        packets: VecDeque<Packet>,
+        state: ConnectionState,
+        order: SpawnOrder,
+    ) -> Self {
+        Self {
+            marker: ConnectionMarker,
+            socket_id: SocketId(socket_id),
+            address: ConnectionAddress(address),
+            queue: ReceiveQueue(packets),
+            send_queue: ConnectionSendQueue::default(),
+            state,
+            order,
+            stats: NetworkStats::default(),
+        }
+    }
+}
+
+/// The events emitted by [`drain_recv`].
+#[derive(SystemParam)]
+struct RecvEvents<'w, 's> {
+    new: EventWriter<'w, 's, NewConnection>,
+    disconnected: EventWriter<'w, 's, DisconnectedTraffic>,
+    unreachable: EventWriter<'w, 's, UnreachableAddress>,
+    suspected: EventWriter<'w, 's, ConnectionSuspected>,
+    filtered: EventWriter<'w, 's, AddressRejected>,
+    rejected: EventWriter<'w, 's, ConnectionRejected>,
+    failed_auth: EventWriter<'w, 's, AuthenticationFailed>,
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn drain_recv(
-    mut socket_query: Query<(Entity, &mut Socket, Option<&ConnectionBuilder>), With<SocketMarker>>,
+    mut socket_query: Query<
+        (
+            Entity,
+            &mut Socket,
+            &mut SendQueue,
+            Option<&ConnectionBuilder>,
+            Option<&VirtualHosts>,
+            Option<&DisconnectedPolicy>,
+            Option<&mut UnreachableAddresses>,
+            Option<&mut PacketSizeHistogram>,
+            Option<&BlockedAddresses>,
+            Option<&AddressFilter>,
+            Option<&mut NetworkStats>,
+            Option<&mut Authenticator>,
+            Option<&mut MiddlewareChain>,
+            Option<&mut NetworkConditions>,
+        ),
+        (With<SocketMarker>, Without<ConnectionMarker>),
+    >,
     mut connection_query: Query<
         (
             Entity,
@@ -85,96 +551,528 @@ fn drain_recv(
             &ConnectionAddress,
             &mut ReceiveQueue,
             &mut ConnectionState,
+            Option<&HostId>,
+            &SpawnOrder,
         ),
         With<ConnectionMarker>,
     >,
-
+    mut stats_query: Query<&mut NetworkStats, With<ConnectionMarker>>,
+    grace_query: Query<&GracePeriod>,
+    probe_listeners: ProbeListeners,
+    index: Res<ConnectionIndex>,
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    limit_opt: Option<Res<ConnectionLimit>>,
+    hooks: Res<ConnectionHooks>,
+    connected_policy: Res<ConnectedPolicy>,
+    mut spawn_counter: ResMut<SpawnCounter>,
+    mut spawner: ConnectionSpawner,
+    mut events: RecvEvents,
+    #[cfg(feature = "encryption")] encryption: Encryption,
     mut commands: Commands,
 ) {
-    for (socket_id, mut socket, builder_opt) in socket_query.iter_mut() {
-        let mut actions: HashMap<SocketAddr, Action> = HashMap::new();
+    let now = clock.now(&time);
+    #[cfg(feature = "encryption")]
+    let is_bound = encryption.is_encrypted();
+    #[cfg(not(feature = "encryption"))]
+    let is_bound = false;
+    let mut admission = Admission::new(
+        limit_opt.map(|limit| *limit),
+        connection_query.iter().count(),
+    );
+
+    for (
+        socket_id,
+        mut socket,
+        mut send_queue,
+        builder_opt,
+        hosts_opt,
+        policy_opt,
+        mut unreachable_opt,
+        mut histogram_opt,
+        blocked_opt,
+        filter_opt,
+        mut stats_opt,
+        mut authenticator_opt,
+        mut middleware_opt,
+        mut conditions_opt,
+    ) in socket_query.iter_mut()
+    {
+        let mut actions = Actions::default();
+        let mut rejected = Vec::new();
+        let is_blocked =
+            |address: &SocketAddr| blocked_opt.is_some_and(|blocked| blocked.is_blocked(address));
+        let mut is_rejected = |address: &SocketAddr| {
+            if !filter_opt.is_some_and(|filter| filter.rejects(address)) {
+                return false;
+            }
+            if !rejected
+                .iter()
+                .any(|other| ConnectionAddress(*other).matches(address))
+            {
+                rejected.push(*address);
+            }
+            true
+        };
+
+        // Packets delayed by the network conditions are received ahead of new events
+        let conditions_now = now.unwrap_or_else(Instant::now);
+        let mut released: VecDeque<Packet> = conditions_opt
+            .as_mut()
+            .map(|conditions| conditions.release_received(conditions_now).into())
+            .unwrap_or_default();
 
-        while let Some(event) = socket.0.recv() {
+        while let Some((event, is_fresh)) = released
+            .pop_front()
+            .map(|packet| (SocketEvent::Packet(packet), false))
+            .or_else(|| socket.recv().map(|event| (event, true)))
+        {
             match event {
                 SocketEvent::Connect(connect_address) => {
                     trace!(message = "connect event", address = %connect_address);
 
-                    actions
-                        .entry(connect_address)
-                        .and_modify(|action| action.state = Some(ConnectionState::Connected))
-                        .or_insert(Action {
-                            state: Some(ConnectionState::Connected),
-                            packets: VecDeque::new(),
-                        });
+                    if is_blocked(&connect_address) || is_rejected(&connect_address) {
+                        continue;
+                    }
+                    actions.entry(connect_address).state = Some(ConnectionState::Connected);
                 }
                 SocketEvent::Disconnect(disconnect_address) => {
                     trace!(message = "disconnect event", address = %disconnect_address);
 
-                    actions
-                        .entry(disconnect_address)
-                        .and_modify(|action| action.state = Some(ConnectionState::Disconnected))
-                        .or_insert(Action {
-                            state: Some(ConnectionState::Disconnected),
-                            packets: VecDeque::new(),
-                        });
+                    // Laminar follows a timeout with a disconnect, keep the more specific state
+                    let action = actions.entry(disconnect_address);
+                    if !action.dropped {
+                        action.state = Some(ConnectionState::Disconnected);
+                    }
                 }
                 SocketEvent::Packet(packet) => {
+                    if let (Some(conditions), true) = (conditions_opt.as_mut(), is_fresh) {
+                        released.extend(conditions.recv(packet, conditions_now));
+                        continue;
+                    }
                     let packet_addr = packet.addr();
 
                     trace!(message = "packet event", address = %packet_addr);
 
-                    match actions.entry(packet_addr) {
-                        Entry::Occupied(mut action) => {
-                            action.get_mut().packets.push_back(packet);
+                    if is_blocked(&packet_addr) {
+                        trace!(message = "ignoring blocked address", address = %packet_addr);
+                        continue;
+                    }
+                    if is_rejected(&packet_addr) {
+                        trace!(message = "ignoring rejected address", address = %packet_addr);
+                        continue;
+                    }
+
+                    if let Some(unreachable) = unreachable_opt.as_mut() {
+                        unreachable.clear(&packet_addr);
+                    }
+                    if let Some(histogram) = histogram_opt.as_mut() {
+                        histogram.record_received(packet.payload().len());
+                    }
+                    if let Some(stats) = stats_opt.as_mut() {
+                        stats.record_received(packet.payload().len(), now);
+                    }
+
+                    let packet = match middleware_opt.as_mut() {
+                        Some(middleware) => match middleware.recv(packet) {
+                            Some(packet) => packet,
+                            None => {
+                                trace!(message = "packet dropped by middleware", address = %packet_addr);
+                                continue;
+                            }
+                        },
+                        None => packet,
+                    };
+
+                    #[cfg(feature = "encryption")]
+                    let packet = match encryption.open(socket_id, packet) {
+                        Some(packet) => packet,
+                        None => continue,
+                    };
+
+                    let connections = index.get(socket_id, packet_addr);
+                    let probe_opt = Probe::from_request(&packet)
+                        .filter(|_| probe_listeners.intercepts(socket_id, connections));
+                    if let Some(probe) = probe_opt {
+                        // Probes count as traffic from the peer, despite never being delivered
+                        if let Some(mut stats) = connections
+                            .last()
+                            .and_then(|connection| stats_query.get_mut(*connection).ok())
+                        {
+                            stats.record_received(packet.payload().len(), now);
                         }
-                        Entry::Vacant(empty) => {
-                            empty.insert(Action {
-                                state: None,
-                                packets: [packet].into(),
-                            });
+
+                        if probe_listeners.echoes(socket_id, connections) {
+                            trace!(message = "echoing probe", address = %packet_addr, id = probe.id);
+                            send_queue.send_control(probe.echo(packet_addr));
                         }
+                        continue;
                     }
+
+                    let action = actions.entry(packet_addr);
+                    // Laminar tracks the peer afresh after dropping it
+                    if action.dropped {
+                        action.dropped = false;
+                        action.state = Some(ConnectionState::Pending);
+                    }
+                    action.packets.push_back(packet);
                 }
                 SocketEvent::Timeout(timeout_address) => {
                     trace!(message = "timeout event", address = %timeout_address);
+
+                    // Laminar emits a timeout whenever it drops a connection but only follows it
+                    // with a disconnect if the connection was established, mirror the drop so
+                    // that pending connections do not linger
+                    let action = actions.entry(timeout_address);
+                    action.state = Some(ConnectionState::TimedOut);
+                    action.dropped = true;
                 }
             }
         }
 
+        for address in rejected {
+            debug!(message = "rejected address", %address);
+            events.filtered.send(AddressRejected {
+                socket: socket_id,
+                address,
+            });
+        }
+
+        if let (Some(authenticator), Some(now)) = (authenticator_opt.as_mut(), now) {
+            for address in authenticator.expire(now) {
+                debug!(message = "authentication timed out", %address);
+                events.failed_auth.send(AuthenticationFailed {
+                    socket: socket_id,
+                    address,
+                    reason: "timed out before authenticating".to_string(),
+                });
+            }
+        }
+
+        let policy = policy_opt.copied().unwrap_or_default();
+        let mut host_counts: HashMap<HostId, usize> = HashMap::new();
+
         for (connection_addr, action) in actions.into_iter() {
-            let result = connection_query
-                .iter_mut()
-                .find(|(_, id, addr, _, _)| id.0 == socket_id && addr.0 == connection_addr);
-
-            if let Some((_, _, _, mut queue, mut state)) = result {
-                queue.0.extend(action.packets);
-                if let Some(new_state) = action.state {
-                    *state = new_state;
+            if let Some(unreachable) = unreachable_opt.as_mut() {
+                let known = index
+                    .get(socket_id, connection_addr)
+                    .iter()
+                    .any(|connection| connection_query.get(*connection).is_ok());
+                if action.dropped && !known && unreachable.record_failure(connection_addr) {
+                    debug!(message = "address is unreachable", address = %connection_addr);
+                    events.unreachable.send(UnreachableAddress {
+                        socket: socket_id,
+                        address: connection_addr,
+                    });
+                }
+            }
+
+            // Group packets by the connection they are destined for, laminar events concern the
+            // address so every virtual host behind it is included
+            let mut routes: Vec<(Option<HostId>, VecDeque<Packet>)> = Vec::new();
+            if hosts_opt.is_some() {
+                for connection in index.get(socket_id, connection_addr) {
+                    if let Ok((_, _, _, _, _, Some(host), _)) = connection_query.get(*connection) {
+                        if !routes.iter().any(|(route, _)| *route == Some(*host)) {
+                            routes.push((Some(*host), VecDeque::new()));
+                        }
+                    }
+                }
+
+                for packet in action.packets {
+                    // Probes bypass routing, so their echoes concern every host of the address
+                    if Probe::from_echo(&packet).is_some() {
+                        for (_, packets) in routes.iter_mut() {
+                            packets.push_back(packet.clone());
+                        }
+                        continue;
+                    }
+                    match HostId::strip(packet) {
+                        Some((host, packet))
+                            if hosts_opt.is_some_and(|hosts| hosts.serves(host)) =>
+                        {
+                            if let Some((_, packets)) =
+                                routes.iter_mut().find(|(route, _)| *route == Some(host))
+                            {
+                                packets.push_back(packet);
+                            } else {
+                                routes.push((Some(host), [packet].into()));
+                            }
+                        }
+                        Some((host, _)) => {
+                            trace!(message = "dropping packet to unserved host", address = %connection_addr, host = host.0);
+                        }
+                        None => {
+                            trace!(message = "dropping unrouted packet", address = %connection_addr);
+                        }
+                    }
                 }
             } else {
+                routes.push((None, action.packets));
+            }
+
+            for (host_opt, mut packets) in routes {
+                // Prefer a live connection, falling back to a disconnected one
+                let mut existing = None;
+                for connection in index.get(socket_id, connection_addr) {
+                    if let Ok((_, _, _, _, state, item_host_opt, _)) =
+                        connection_query.get(*connection)
+                    {
+                        if item_host_opt.copied() == host_opt {
+                            existing = Some(*connection);
+                            if !state.is_disconnected() {
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                if let Some((connection, _, _, mut queue, mut state, _, _)) =
+                    existing.and_then(|connection| connection_query.get_mut(connection).ok())
+                {
+                    let mut spawn_new = false;
+                    if let Some(mut new_state) = action.state {
+                        // Give peers dropped by laminar a chance to return, see GracePeriod
+                        let grace = new_state == ConnectionState::TimedOut
+                            && !state.is_disconnected()
+                            && grace_query
+                                .get(connection)
+                                .or_else(|_| grace_query.get(socket_id))
+                                .is_ok();
+                        if grace {
+                            new_state = ConnectionState::Suspect;
+                            if *state != ConnectionState::Suspect {
+                                events.suspected.send(ConnectionSuspected {
+                                    connection,
+                                    socket: socket_id,
+                                    address: connection_addr,
+                                });
+                            }
+                        }
+                        hooks::transition(
+                            &mut state,
+                            new_state,
+                            (connection, socket_id, connection_addr),
+                            &hooks,
+                            &mut commands,
+                        );
+                    } else if *state == ConnectionState::Suspect && !packets.is_empty() {
+                        trace!(message = "suspect peer returned", address = %connection_addr);
+                        hooks::transition(
+                            &mut state,
+                            ConnectionState::Connected,
+                            (connection, socket_id, connection_addr),
+                            &hooks,
+                            &mut commands,
+                        );
+                    } else if *state == ConnectionState::Connecting && !packets.is_empty() {
+                        // The peer has answered an outbound connection, which was sent to first
+                        let new_state = match *connected_policy {
+                            ConnectedPolicy::Laminar => ConnectionState::Pending,
+                            ConnectedPolicy::Bidirectional => ConnectionState::Connected,
+                        };
+                        hooks::transition(
+                            &mut state,
+                            new_state,
+                            (connection, socket_id, connection_addr),
+                            &hooks,
+                            &mut commands,
+                        );
+                    } else if state.is_disconnected() && !packets.is_empty() {
+                        trace!(message = "traffic after disconnect", address = %connection_addr, ?policy);
+
+                        events.disconnected.send(DisconnectedTraffic {
+                            connection,
+                            packets: packets.len(),
+                            policy,
+                        });
+
+                        match policy {
+                            DisconnectedPolicy::ResurrectToPending => {
+                                hooks::transition(
+                                    &mut state,
+                                    ConnectionState::Pending,
+                                    (connection, socket_id, connection_addr),
+                                    &hooks,
+                                    &mut commands,
+                                );
+                            }
+                            DisconnectedPolicy::Drop => continue,
+                            DisconnectedPolicy::SpawnNew => spawn_new = true,
+                        }
+                    }
+
+                    if !spawn_new {
+                        if let Ok(mut stats) = stats_query.get_mut(connection) {
+                            for packet in packets.iter() {
+                                stats.record_received(packet.payload().len(), now);
+                            }
+                        }
+                        queue.0.extend(packets.into_iter().flat_map(group::split));
+                        continue;
+                    }
+                }
+
+                if action.dropped {
+                    // Nothing to reconcile, laminar dropped a peer that was only ever sent to
+                    trace!(message = "ignoring dropped peer", address = %connection_addr);
+                    continue;
+                }
+
+                let mut state_opt = action.state;
+                let mut client_id_opt = None;
+                let mut bound_opt = None;
+                if let Some(authenticator) = authenticator_opt.as_mut() {
+                    match authenticator.admit(
+                        connection_addr,
+                        host_opt,
+                        packets,
+                        state_opt,
+                        now,
+                        is_bound,
+                    ) {
+                        Verdict::Pending => continue,
+                        Verdict::Accepted {
+                            packets: held,
+                            state,
+                            client_id,
+                            key_opt,
+                        } => {
+                            packets = held;
+                            state_opt = state;
+                            client_id_opt = client_id;
+                            bound_opt = key_opt;
+                        }
+                        Verdict::Failed(reason) => {
+                            debug!(message = "authentication failed", address = %connection_addr, %reason);
+                            events.failed_auth.send(AuthenticationFailed {
+                                socket: socket_id,
+                                address: connection_addr,
+                                reason,
+                            });
+                            continue;
+                        }
+                    }
+                }
+
+                let decision = builder_opt.map(|builder| builder.decide_on(connection_addr));
+                if let Some(ConnectionDecision::Reject(reason)) = decision {
+                    debug!(message = "connection rejected", address = %connection_addr, %reason);
+                    events.rejected.send(ConnectionRejected {
+                        socket: socket_id,
+                        address: connection_addr,
+                        reason,
+                    });
+                    continue;
+                }
+
+                if let (Some(hosts), Some(host)) = (hosts_opt, host_opt) {
+                    let count = host_counts.entry(host).or_insert_with(|| {
+                        connection_query
+                            .iter()
+                            .filter(|(_, id, _, _, _, item_host_opt, _)| {
+                                id.0 == socket_id && *item_host_opt == Some(&host)
+                            })
+                            .count()
+                    });
+                    if hosts.limit(host).is_some_and(|limit| *count >= limit) {
+                        trace!(message = "host is full", address = %connection_addr, host = host.0);
+                        continue;
+                    }
+                    *count += 1;
+                }
+
+                let connections = connection_query
+                    .iter()
+                    .map(|(entity, _, _, _, state, _, order)| (entity, state, order));
+                if !admission.admit(connections, &mut commands) {
+                    debug!(message = "connection limit reached", address = %connection_addr);
+                    continue;
+                }
+
                 trace!(message = "spawning connection", address = %connection_addr);
 
-                let mut entity_commands = commands.spawn_bundle(ConnectionBundle {
-                    marker: ConnectionMarker,
-                    socket_id: SocketId(socket_id),
-                    address: ConnectionAddress(connection_addr),
-                    queue: ReceiveQueue(action.packets),
-                    state: action.state.unwrap_or(ConnectionState::Pending),
-                });
+                let state = state_opt.unwrap_or(ConnectionState::Pending);
+                let mut stats = NetworkStats::default();
+                for packet in packets.iter() {
+                    stats.record_received(packet.payload().len(), now);
+                }
+                let mut bundle = ConnectionBundle::new(
+                    socket_id,
+                    connection_addr,
+                    packets.into_iter().flat_map(group::split).collect(),
+                    state,
+                    spawn_counter.next(),
+                );
+                bundle.stats = stats;
+                let mut entity_commands = spawner.spawn_bundle(&mut commands, bundle);
+                if let Some(host) = host_opt {
+                    entity_commands.insert(host);
+                }
+                if let Some(client_id) = client_id_opt {
+                    entity_commands.insert(client_id);
+                }
+                #[cfg(feature = "encryption")]
+                if let Some(key) = bound_opt {
+                    entity_commands.insert(BoundKey(key));
+                }
+                // Only encryption binds credentials to a key
+                #[cfg(not(feature = "encryption"))]
+                let _ = bound_opt;
                 if let Some(builder) = builder_opt {
-                    builder.0(connection_addr, &mut entity_commands)
+                    builder.build(connection_addr, &mut entity_commands)
                 }
+
+                let connection = entity_commands.id();
+                hooks.run(
+                    &ConnectionTransition {
+                        connection,
+                        socket: socket_id,
+                        address: connection_addr,
+                        from: None,
+                        to: state,
+                    },
+                    &mut commands,
+                );
+
+                events.new.send(NewConnection {
+                    connection,
+                    socket: socket_id,
+                    address: connection_addr,
+                    state,
+                });
             }
         }
     }
 }
 
-/// Binds to a UDP socket, with provided [`Config`] and `poll_interval`, returning a [`Bundle`].
+/// Creates a socket over any [`Transport`], with the [`Config`] it was created with and provided
+/// `poll_interval`, returning a [`Bundle`].
 ///
-/// The `poll_interval` sets the elapsed time before polls.
+/// The `config` is only read to validate the [`PollInterval`] against its timeouts, the transport
+/// being configured when created. The returned [`Bundle`] must be spawned in order to use the
+/// socket. It will include [`PollInterval`], [`SocketMarker`], and [`SendQueue`].
+#[must_use = "The returned Bundle must be spawned to use the socket"]
+pub fn bind_with_transport<T>(transport: T, poll_interval: Duration, config: Config) -> impl Bundle
+where
+    T: Transport,
+{
+    SocketBundle {
+        marker: SocketMarker,
+        socket: Socket::new(transport),
+        config: SocketConfig(config),
+        last_poll: LastPoll(None),
+        poll_interval: PollInterval(poll_interval),
+        send_queue: SendQueue::default(),
+        broadcast_queue: BroadcastQueue::default(),
+        stats: NetworkStats::default(),
+    }
+}
+
+/// Binds to a UDP socket, with provided [`Config`] and `poll_interval`, returning a [`Bundle`].
 ///
-/// The returned [`Bundle`] must be spawned in order to use the socket. It will include
-/// [`PollInterval`], [`SocketMarker`], and [`SendQueue`].
+/// The `poll_interval` sets the elapsed time before polls. See [`bind_with_transport`] for more
+/// details.
 #[must_use = "The returned Bundle must be spawned to use the socket"]
 pub fn bind_with_config<A>(
     addresses: A,
@@ -184,15 +1082,26 @@ pub fn bind_with_config<A>(
 where
     A: ToSocketAddrs,
 {
-    let socket = laminar::Socket::bind_with_config(addresses, config)?;
+    let socket = laminar::Socket::bind_with_config(addresses, config.clone())?;
+    Ok(bind_with_transport(socket, poll_interval, config))
+}
 
-    Ok(SocketBundle {
-        marker: SocketMarker,
-        socket: Socket(socket),
-        last_poll: LastPoll(None),
-        poll_interval: PollInterval(poll_interval),
-        send_queue: SendQueue::default(),
-    })
+/// Binds to a UDP socket, with default [`Config`] overridden by [`ConnectionOptions`] and provided
+/// `poll_interval`, returning a [`Bundle`].
+///
+/// The options should be checked with [`ConnectionOptions::validate`] beforehand, otherwise a
+/// warning is logged once the socket is spawned. See [`bind_with_config`] for more details.
+pub fn bind_with_options<A>(
+    addresses: A,
+    poll_interval: Duration,
+    options: ConnectionOptions,
+) -> Result<impl Bundle, laminar::ErrorKind>
+where
+    A: ToSocketAddrs,
+{
+    let mut config = Config::default();
+    options.apply(&mut config);
+    bind_with_config(addresses, poll_interval, config)
 }
 
 /// Binds to a UDP socket, with default [`Config`] and provided `poll_interval`, returning a
@@ -206,14 +1115,92 @@ where
     bind_with_config(addresses, poll_interval, Config::default())
 }
 
+/// Binds to an in-memory loopback socket, with provided [`Config`] and `poll_interval`, returning
+/// a [`Bundle`].
+///
+/// Loopback sockets exchange packets over channels with the other loopback sockets of the
+/// process, whether spawned in the same [`App`] or another, and never touch the network, so that
+/// tests may run in parallel without claiming ports. They are otherwise used as sockets returned
+/// by [`bind_with_config`], connecting to each other by address. Loopback addresses are distinct
+/// from those of UDP sockets: binding to port 0 picks a port free among loopback sockets, and an
+/// unspecified address binds to localhost. The address is released once the socket is dropped.
+#[must_use = "The returned Bundle must be spawned to use the socket"]
+pub fn bind_loopback_with_config<A>(
+    addresses: A,
+    poll_interval: Duration,
+    config: Config,
+) -> Result<impl Bundle, laminar::ErrorKind>
+where
+    A: ToSocketAddrs,
+{
+    let manager = LoopbackSocket::bind_manager(addresses, config.clone())?;
+    Ok(bind_with_transport(manager, poll_interval, config))
+}
+
+/// Binds to an in-memory loopback socket, with default [`Config`] and provided `poll_interval`,
+/// returning a [`Bundle`].
+///
+/// See [`bind_loopback_with_config`] for more details.
+pub fn bind_loopback<A>(
+    addresses: A,
+    poll_interval: Duration,
+) -> Result<impl Bundle, laminar::ErrorKind>
+where
+    A: ToSocketAddrs,
+{
+    bind_loopback_with_config(addresses, poll_interval, Config::default())
+}
+
+/// Listens for WebSocket connections, such as those of browser clients, with provided
+/// `poll_interval`, returning a [`Bundle`].
+///
+/// See [`WebSocketTransport`] and [`bind_with_transport`] for more details.
+#[cfg(feature = "websocket")]
+pub fn bind_websocket<A>(
+    addresses: A,
+    poll_interval: Duration,
+) -> Result<impl Bundle, laminar::ErrorKind>
+where
+    A: ToSocketAddrs,
+{
+    let transport = WebSocketTransport::bind(addresses)?;
+    Ok(bind_with_transport(
+        transport,
+        poll_interval,
+        Config::default(),
+    ))
+}
+
+/// Checks that a UDP socket can be bound to `addresses` without keeping it, so that user-entered
+/// settings may be validated before a session starts.
+///
+/// The socket is released straight away, so the address may still be taken by the time it is
+/// bound for real. Binding to port 0 always succeeds on a valid local address.
+pub fn validate_bind<A>(addresses: A) -> Result<(), NetworkError>
+where
+    A: ToSocketAddrs,
+{
+    UdpSocket::bind(addresses)?;
+    Ok(())
+}
+
 /// A [`Plugin`] encapsulating the networking systems.
 pub struct NetworkPlugin {
     system_set_f: Box<dyn Fn() -> SystemSet + Send + Sync + 'static>,
+    connected_policy: ConnectedPolicy,
+    polling_mode: PollingMode,
+    protocol: Option<Protocol>,
+    strict: bool,
 }
 
 impl Debug for NetworkPlugin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("NetworkPlugin").finish_non_exhaustive()
+        f.debug_struct("NetworkPlugin")
+            .field("connected_policy", &self.connected_policy)
+            .field("polling_mode", &self.polling_mode)
+            .field("protocol", &self.protocol)
+            .field("strict", &self.strict)
+            .finish_non_exhaustive()
     }
 }
 
@@ -222,9 +1209,20 @@ impl NetworkPlugin {
     pub fn always() -> Self {
         Self {
             system_set_f: Box::new(SystemSet::new),
+            connected_policy: ConnectedPolicy::default(),
+            polling_mode: PollingMode::default(),
+            protocol: None,
+            strict: false,
         }
     }
 
+    /// The plugin will always run, polling sockets on dedicated threads.
+    ///
+    /// See [`PollingMode::Threaded`].
+    pub fn threaded() -> Self {
+        Self::always().with_polling_mode(PollingMode::Threaded)
+    }
+
     /// The plugin will run only in a specified state.
     pub fn on_state<State>(state: State) -> Self
     where
@@ -233,14 +1231,58 @@ impl NetworkPlugin {
     {
         Self {
             system_set_f: Box::new(move || SystemSet::on_update(state.clone())),
+            connected_policy: ConnectedPolicy::default(),
+            polling_mode: PollingMode::default(),
+            protocol: None,
+            strict: false,
         }
     }
+
+    /// Sets the [`ConnectedPolicy`], defaulting to [`ConnectedPolicy::Laminar`].
+    pub fn with_connected_policy(mut self, policy: ConnectedPolicy) -> Self {
+        self.connected_policy = policy;
+        self
+    }
+
+    /// Sets the [`PollingMode`], defaulting to [`PollingMode::Frame`].
+    pub fn with_polling_mode(mut self, mode: PollingMode) -> Self {
+        self.polling_mode = mode;
+        self
+    }
+
+    /// Requires peers to speak a compatible [`Protocol`], by default there is no handshake.
+    ///
+    /// Every connection then opens with a handshake exchanging the protocol with the peer. Until
+    /// it completes, with a [`HandshakeCompleted`] event, packets from the peer are held back
+    /// rather than delivered to the [`ReceiveQueue`]. Incompatible peers are sent the reason,
+    /// disconnected with [`Disconnect`] without being blocked, and a [`ConnectionRejected`] event
+    /// is emitted, as are peers which do not complete the handshake within the
+    /// [`HandshakeTimeout`]. Payloads sent before the handshake completes are not held back.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Turns misuse which would otherwise fail silently into panics in debug builds, and errors
+    /// in release builds. Disabled by default.
+    ///
+    /// Checked once per tick, before payloads are forwarded, this catches payloads queued on a
+    /// [`ConnectionState::Disconnected`] or [`ConnectionState::TimedOut`] connection or on one
+    /// whose socket was despawned, packets sent to addresses the socket's [`AddressFilter`] will
+    /// not hear back from, a [`ReceiveQueue`] or [`ConnectionSendQueue`] inserted on a socket
+    /// entity, and a [`SendQueue`] inserted on a connection entity.
+    pub fn with_strict_mode(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
 }
 
 /// Labels enumerating the different network systems.
 ///
-/// The order is `Poll` < `Recv` < `Send`, which means that anything sent will be performed next
-/// tick.
+/// `Poll` < `Recv` run in a stage between [`NetworkStage::PrePoll`] and [`NetworkStage::PostRecv`],
+/// before [`CoreStage::Update`]. `Send` runs in a stage between [`NetworkStage::PreSend`] and
+/// [`NetworkStage::PostSend`], after [`CoreStage::Update`], which means that anything sent during
+/// the update will be performed the same tick.
 #[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
 pub enum NetworkSystemLabels {
     /// Labels the system polling the underlying socket.
@@ -257,22 +1299,230 @@ impl SystemLabel for NetworkSystemLabels {
     }
 }
 
+/// Stages surrounding the network systems, providing extension points for other plugins.
+///
+/// The order is `PrePoll` < `PostRecv` < [`CoreStage::Update`] < `PreSend` < `PostSend`. Sockets
+/// are polled and received packets drained into connections between `PrePoll` and `PostRecv`,
+/// while queued packets are flushed between `PreSend` and `PostSend`. Commands are applied at the
+/// end of each stage, so connections spawned while receiving are visible in `PostRecv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkStage {
+    /// Runs before sockets are polled.
+    PrePoll,
+    /// Runs after received packets have been drained into connections.
+    PostRecv,
+    /// Runs before queued packets are flushed.
+    PreSend,
+    /// Runs after queued packets have been flushed.
+    PostSend,
+}
+
+impl StageLabel for NetworkStage {
+    fn dyn_clone(&self) -> Box<dyn StageLabel> {
+        Box::new(*self)
+    }
+}
+
+/// Stages containing the network systems themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InternalStage {
+    Recv,
+    Handshake,
+    Send,
+}
+
+impl StageLabel for InternalStage {
+    fn dyn_clone(&self) -> Box<dyn StageLabel> {
+        Box::new(*self)
+    }
+}
+
+/// Labels ordering the network systems among themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InternalLabel {
+    Forward,
+    Handshake,
+    #[cfg(feature = "serde")]
+    Messages,
+    #[cfg(feature = "serde")]
+    Rpc,
+}
+
+impl SystemLabel for InternalLabel {
+    fn dyn_clone(&self) -> Box<dyn SystemLabel> {
+        Box::new(*self)
+    }
+}
+
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut App) {
         let polling_set = (self.system_set_f)()
             .label(NetworkSystemLabels::Poll)
+            .with_system(index_connections)
+            .with_system(spawn_described_sockets)
+            .with_system(validate_poll_interval)
+            .with_system(start_polling_threads)
+            .with_system(update_bridge_stats)
             .with_system(socket_poll);
         let recv_set = (self.system_set_f)()
             .label(NetworkSystemLabels::Recv)
             .after(NetworkSystemLabels::Poll)
             .with_system(drain_recv);
+        let forward_set = (self.system_set_f)()
+            .label(InternalLabel::Forward)
+            .before(NetworkSystemLabels::Send)
+            .with_system(forward_connection_sends)
+            .with_system(forward_broadcasts)
+            .with_system(index_connections);
         let send_set = (self.system_set_f)()
             .label(NetworkSystemLabels::Send)
-            .after(NetworkSystemLabels::Recv)
             .with_system(flush_send);
+        let lifecycle_set = (self.system_set_f)()
+            .after(NetworkSystemLabels::Recv)
+            .with_system(track_sessions)
+            .with_system(expire_sessions)
+            .with_system(reap_connections)
+            .with_system(drive_meshes)
+            .with_system(measure_rtt)
+            .with_system(update_known_peers.after(measure_rtt))
+            .with_system(check_liveness)
+            .with_system(expire_grace_periods)
+            .with_system(send_heartbeats)
+            .with_system(record_audit_events)
+            .with_system(start_self_tests)
+            .with_system(run_self_tests);
 
-        app.add_system_set(polling_set)
-            .add_system_set(send_set)
-            .add_system_set(recv_set);
+        app.add_stage_before(
+            CoreStage::Update,
+            NetworkStage::PrePoll,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            NetworkStage::PrePoll,
+            InternalStage::Recv,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            InternalStage::Recv,
+            NetworkStage::PostRecv,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            CoreStage::Update,
+            NetworkStage::PreSend,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            NetworkStage::PreSend,
+            InternalStage::Send,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            InternalStage::Send,
+            NetworkStage::PostSend,
+            SystemStage::parallel(),
+        );
+
+        app.insert_resource(self.connected_policy)
+            .insert_resource(self.polling_mode)
+            .init_resource::<ConnectionHooks>()
+            .init_resource::<ConnectionIndex>()
+            .init_resource::<NetworkClock>()
+            .init_resource::<SpawnCounter>()
+            .init_resource::<FlushMetrics>()
+            .add_event::<NewConnection>()
+            .add_event::<DisconnectedTraffic>()
+            .add_event::<UnreachableAddress>()
+            .add_event::<SendError>()
+            .add_event::<SocketClosed>()
+            .add_event::<DescriptorBindError>()
+            .add_event::<SessionExpired>()
+            .add_event::<ConnectionReaped>()
+            .add_event::<MeshReady>()
+            .add_event::<ConnectionSuspected>()
+            .add_event::<AddressRejected>()
+            .add_event::<ConnectionRejected>()
+            .add_event::<AuthenticationFailed>()
+            .add_event::<SessionStarted>()
+            .add_event::<SessionEnded>()
+            .add_event::<RunSelfTest>()
+            .add_event::<SelfTestCompleted>()
+            .add_system_set_to_stage(InternalStage::Recv, polling_set)
+            .add_system_set_to_stage(InternalStage::Recv, recv_set)
+            .add_system_set_to_stage(InternalStage::Recv, lifecycle_set)
+            .add_system_set_to_stage(InternalStage::Send, forward_set)
+            .add_system_set_to_stage(InternalStage::Send, send_set)
+            .add_system_set_to_stage(
+                InternalStage::Send,
+                (self.system_set_f)()
+                    .before(InternalLabel::Forward)
+                    .with_system(send_credentials),
+            );
+
+        #[cfg(feature = "serde")]
+        app.init_resource::<MessageBandwidth>()
+            .add_event::<ClientSettingsApplied>()
+            .add_system_set_to_stage(
+                NetworkStage::PostRecv,
+                (self.system_set_f)().with_system(apply_client_settings),
+            )
+            .add_system_set_to_stage(
+                InternalStage::Send,
+                (self.system_set_f)()
+                    .label(InternalLabel::Messages)
+                    .before(InternalLabel::Forward)
+                    .with_system(message::prepare_sent_messages),
+            );
+
+        if self.strict {
+            app.add_system_set_to_stage(
+                InternalStage::Send,
+                (self.system_set_f)()
+                    .before(InternalLabel::Forward)
+                    .with_system(check_misuse),
+            );
+        }
+
+        // Connections spawned while receiving only exist once the stage ends, so handshakes are
+        // driven in a stage of their own, before anything reads their packets
+        if let Some(protocol) = self.protocol {
+            app.insert_resource(protocol)
+                .init_resource::<HandshakeTimeout>()
+                .add_event::<HandshakeCompleted>()
+                .add_stage_after(
+                    InternalStage::Recv,
+                    InternalStage::Handshake,
+                    SystemStage::parallel(),
+                )
+                .add_system_set_to_stage(
+                    InternalStage::Handshake,
+                    (self.system_set_f)()
+                        .label(InternalLabel::Handshake)
+                        .with_system(drive_handshakes),
+                )
+                .add_system_set_to_stage(
+                    InternalStage::Handshake,
+                    (self.system_set_f)()
+                        .after(InternalLabel::Handshake)
+                        .with_system(expire_handshakes),
+                );
+
+            // Payloads are compressed before being sealed, and decompressed once opened
+            #[cfg(feature = "compression")]
+            if protocol.is_compressed() {
+                let compress_set = (self.system_set_f)()
+                    .before(InternalLabel::Forward)
+                    .with_system(compress_payloads);
+                #[cfg(feature = "serde")]
+                let compress_set = compress_set.after(InternalLabel::Messages);
+                app.add_system_set_to_stage(InternalStage::Send, compress_set)
+                    .add_system_set_to_stage(
+                        InternalStage::Handshake,
+                        (self.system_set_f)()
+                            .after(InternalLabel::Handshake)
+                            .with_system(decompress_packets),
+                    );
+            }
+        }
     }
 }
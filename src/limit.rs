@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+
+use crate::{pool::DespawnConnection, ConnectionState};
+
+/// Determines what happens when a new connection would exceed the [`ConnectionLimit`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EvictionPolicy {
+    /// Refuse the new connection, dropping its packets.
+    #[default]
+    RefuseNew,
+    /// Despawn the oldest connection in [`ConnectionState::Disconnected`] or
+    /// [`ConnectionState::TimedOut`] to make room, refusing the new connection if there is none.
+    EvictOldestDisconnected,
+}
+
+/// A resource capping the total number of connection entities across all sockets.
+///
+/// This is a safety valve against bugs or attacks exhausting memory through connection entities,
+/// it is absent by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionLimit {
+    /// The maximum number of connection entities.
+    pub max: usize,
+    /// What happens when `max` is reached.
+    pub policy: EvictionPolicy,
+}
+
+impl ConnectionLimit {
+    /// Creates a new [`ConnectionLimit`] with [`EvictionPolicy::RefuseNew`].
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            policy: EvictionPolicy::RefuseNew,
+        }
+    }
+
+    /// Sets the [`EvictionPolicy`].
+    pub fn with_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// A [`Component`] recording the order in which connections were spawned.
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct SpawnOrder(pub(crate) u64);
+
+/// A resource counting connection spawns, used to assign [`SpawnOrder`].
+#[derive(Debug, Default)]
+pub(crate) struct SpawnCounter(u64);
+
+impl SpawnCounter {
+    pub(crate) fn next(&mut self) -> SpawnOrder {
+        self.0 += 1;
+        SpawnOrder(self.0)
+    }
+}
+
+/// Applies the [`ConnectionLimit`] to connections spawned within a single system run.
+#[derive(Debug)]
+pub(crate) struct Admission {
+    limit: Option<ConnectionLimit>,
+    total: usize,
+    evicted: Vec<Entity>,
+}
+
+impl Admission {
+    pub(crate) fn new(limit: Option<ConnectionLimit>, total: usize) -> Self {
+        Self {
+            limit,
+            total,
+            evicted: Vec::new(),
+        }
+    }
+
+    /// Returns whether a new connection may be spawned, despawning an evicted connection to make
+    /// room if required.
+    pub(crate) fn admit<'a>(
+        &mut self,
+        connections: impl Iterator<Item = (Entity, &'a ConnectionState, &'a SpawnOrder)>,
+        commands: &mut Commands,
+    ) -> bool {
+        let limit = if let Some(limit) = self.limit {
+            limit
+        } else {
+            self.total += 1;
+            return true;
+        };
+
+        if self.total < limit.max {
+            self.total += 1;
+            return true;
+        }
+
+        if limit.policy == EvictionPolicy::RefuseNew {
+            return false;
+        }
+
+        let evicted = &self.evicted;
+        let oldest = connections
+            .filter(|(entity, state, _)| state.is_disconnected() && !evicted.contains(entity))
+            .min_by_key(|(_, _, order)| **order);
+
+        if let Some((entity, _, _)) = oldest {
+            trace!(message = "evicting connection", ?entity);
+            commands.add(DespawnConnection(entity));
+            self.evicted.push(entity);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    hooks, ConnectionAddress, ConnectionHooks, ConnectionMarker, ConnectionState, NetworkClock,
+    NetworkStats, Probe, SendQueue, SocketId, SocketMarker,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_SUSPECT_AFTER: u32 = 4;
+const DEFAULT_TIMEOUT_AFTER: u32 = 12;
+
+/// A [`Component`] which, when present on a connection entity, or on a socket entity for all of
+/// its connections, detects unresponsive peers faster than laminar's idle connection timeout.
+///
+/// Peers are expected to send something, such as a [`Probe`](crate::Probe), at least once per
+/// interval. Once `suspect_after` intervals pass without receiving anything, a connected or pending
+/// connection moves to [`ConnectionState::Suspect`] and a [`ConnectionSuspected`] event is emitted.
+/// Should traffic resume within that many intervals it returns to [`ConnectionState::Connected`],
+/// otherwise it moves to [`ConnectionState::TimedOut`] after `timeout_after` intervals. A component
+/// on the connection takes precedence over the socket's.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct Liveness {
+    interval: Duration,
+    suspect_after: u32,
+    timeout_after: u32,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL)
+    }
+}
+
+impl Liveness {
+    /// Creates a new [`Liveness`] expecting traffic every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            suspect_after: DEFAULT_SUSPECT_AFTER,
+            timeout_after: DEFAULT_TIMEOUT_AFTER,
+        }
+    }
+
+    /// Sets the number of missed intervals after which a peer is suspected.
+    pub fn with_suspect_after(mut self, missed: u32) -> Self {
+        self.suspect_after = missed;
+        self
+    }
+
+    /// Sets the number of missed intervals after which a peer is timed out.
+    pub fn with_timeout_after(mut self, missed: u32) -> Self {
+        self.timeout_after = missed;
+        self
+    }
+
+    /// Returns the duration without traffic after which a peer is suspected.
+    pub fn suspect_timeout(&self) -> Duration {
+        self.interval * self.suspect_after
+    }
+
+    /// Returns the duration without traffic after which a peer is timed out.
+    pub fn timeout(&self) -> Duration {
+        self.interval * self.timeout_after
+    }
+}
+
+/// A [`Component`] which, when present on a connection entity, or on a socket entity for all of
+/// its connections, gives peers a grace period before they are considered gone.
+///
+/// When laminar drops the peer of a connection which is not disconnected, the connection moves to
+/// [`ConnectionState::Suspect`] rather than [`ConnectionState::TimedOut`], and a
+/// [`ConnectionSuspected`] event is emitted. This allows, for instance, a player's avatar to be
+/// frozen rather than despawned. Should packets resume the connection returns to
+/// [`ConnectionState::Connected`], or [`ConnectionState::Pending`] if laminar has yet to track the
+/// peer again, otherwise it moves to [`ConnectionState::TimedOut`] once it has been suspect for the
+/// grace period, whichever the reason it became suspect.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct GracePeriod(pub Duration);
+
+/// A [`Component`] which, when present on a connection entity, keeps the connection alive and
+/// times it out independently of laminar.
+///
+/// Whenever nothing has been sent to the peer for an interval a [`Probe`] is sent, which peers
+/// opted into probes, such as by a [`Heartbeat`] of their own, count as traffic without it reaching
+/// their [`ReceiveQueue`](crate::ReceiveQueue). Once nothing has been received from the peer for
+/// the timeout, the connection moves to [`ConnectionState::TimedOut`]. Connections which have yet
+/// to receive anything are only kept alive.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct Heartbeat {
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Heartbeat {
+    /// Creates a new [`Heartbeat`] sending every `interval` while idle and timing out after
+    /// `timeout` without receiving anything.
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self { interval, timeout }
+    }
+
+    /// Returns the interval between keepalives.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the duration without receiving anything after which the connection times out.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// An event emitted when a connection moves to [`ConnectionState::Suspect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionSuspected {
+    /// The suspected connection entity.
+    pub connection: Entity,
+    /// The socket entity owning the connection.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn check_liveness(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    hooks: Res<ConnectionHooks>,
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &NetworkStats,
+            &mut ConnectionState,
+            Option<&Liveness>,
+        ),
+        With<ConnectionMarker>,
+    >,
+    socket_query: Query<&Liveness, With<SocketMarker>>,
+    mut suspected_writer: EventWriter<ConnectionSuspected>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, socket_id, addr, stats, mut state, liveness_opt) in connection_query.iter_mut()
+    {
+        let liveness = match liveness_opt.or_else(|| socket_query.get(socket_id.0).ok()) {
+            Some(liveness) => liveness,
+            None => continue,
+        };
+        let last_received = match stats.last_received {
+            Some(last_received) => last_received,
+            None => continue,
+        };
+
+        let silence = now.saturating_duration_since(last_received);
+        let new_state = match *state {
+            ConnectionState::Connected | ConnectionState::Pending
+                if silence >= liveness.suspect_timeout() =>
+            {
+                ConnectionState::Suspect
+            }
+            ConnectionState::Suspect if silence >= liveness.timeout() => ConnectionState::TimedOut,
+            ConnectionState::Suspect if silence < liveness.suspect_timeout() => {
+                ConnectionState::Connected
+            }
+            _ => continue,
+        };
+
+        trace!(message = "liveness changed", ?connection, from = ?*state, to = ?new_state);
+
+        hooks::transition(
+            &mut state,
+            new_state,
+            (connection, socket_id.0, addr.0),
+            &hooks,
+            &mut commands,
+        );
+        if new_state == ConnectionState::Suspect {
+            suspected_writer.send(ConnectionSuspected {
+                connection,
+                socket: socket_id.0,
+                address: addr.0,
+            });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn expire_grace_periods(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    hooks: Res<ConnectionHooks>,
+    mut suspected: Local<HashMap<Entity, Instant>>,
+    mut connection_query: Query<
+        (Entity, &SocketId, &ConnectionAddress, &mut ConnectionState),
+        With<ConnectionMarker>,
+    >,
+    grace_query: Query<&GracePeriod>,
+    removed: RemovedComponents<ConnectionMarker>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, _, _, state) in connection_query.iter_mut() {
+        if !state.is_changed() {
+            continue;
+        }
+
+        if *state == ConnectionState::Suspect {
+            suspected.entry(connection).or_insert(now);
+        } else {
+            suspected.remove(&connection);
+        }
+    }
+
+    for connection in removed.iter() {
+        suspected.remove(&connection);
+    }
+
+    suspected.retain(|&connection, since| {
+        let (_, socket_id, addr, mut state) = match connection_query.get_mut(connection) {
+            Ok(item) => item,
+            Err(_) => return false,
+        };
+
+        let grace = match grace_query
+            .get(connection)
+            .or_else(|_| grace_query.get(socket_id.0))
+        {
+            Ok(grace) => grace.0,
+            Err(_) => return true,
+        };
+        if *since + grace > now {
+            return true;
+        }
+
+        trace!(message = "grace period elapsed", ?connection);
+
+        hooks::transition(
+            &mut state,
+            ConnectionState::TimedOut,
+            (connection, socket_id.0, addr.0),
+            &hooks,
+            &mut commands,
+        );
+        false
+    });
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn send_heartbeats(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    hooks: Res<ConnectionHooks>,
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &NetworkStats,
+            &mut ConnectionState,
+            &Heartbeat,
+        ),
+        With<ConnectionMarker>,
+    >,
+    mut socket_query: Query<&mut SendQueue, With<SocketMarker>>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (connection, socket_id, addr, stats, mut state, heartbeat) in connection_query.iter_mut() {
+        if state.is_disconnected() {
+            continue;
+        }
+
+        let timed_out = stats
+            .last_received
+            .is_some_and(|last_received| last_received + heartbeat.timeout <= now);
+        if timed_out {
+            trace!(message = "heartbeat timed out", ?connection);
+
+            hooks::transition(
+                &mut state,
+                ConnectionState::TimedOut,
+                (connection, socket_id.0, addr.0),
+                &hooks,
+                &mut commands,
+            );
+            continue;
+        }
+
+        let idle = stats
+            .last_sent
+            .is_none_or(|last_sent| last_sent + heartbeat.interval <= now);
+        if idle {
+            if let Ok(mut send_queue) = socket_query.get_mut(socket_id.0) {
+                send_queue.send_probe(addr.0, Probe { id: 0 });
+            }
+        }
+    }
+}
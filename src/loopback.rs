@@ -0,0 +1,122 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    sync::{Mutex, MutexGuard},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use laminar::{Config, ConnectionManager, DatagramSocket, VirtualConnection};
+
+/// A datagram in flight between loopback sockets, along with its sender's address.
+type Datagram = (SocketAddr, Vec<u8>);
+
+/// A laminar socket whose datagrams are exchanged in memory, see
+/// [`bind_loopback`](crate::bind_loopback).
+pub(crate) type LoopbackManager = ConnectionManager<LoopbackSocket, VirtualConnection>;
+
+/// The loopback sockets of the process, by address.
+static SOCKETS: Mutex<BTreeMap<SocketAddr, Sender<Datagram>>> = Mutex::new(BTreeMap::new());
+
+/// The first port handed out to loopback sockets bound to port 0.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+fn sockets() -> MutexGuard<'static, BTreeMap<SocketAddr, Sender<Datagram>>> {
+    // The map is left consistent should a holder panic
+    SOCKETS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A datagram socket exchanging datagrams over channels with the other loopback sockets of the
+/// process, never touching the operating system's network stack.
+#[derive(Debug)]
+pub(crate) struct LoopbackSocket {
+    address: SocketAddr,
+    receiver: Receiver<Datagram>,
+}
+
+impl LoopbackSocket {
+    /// Registers a loopback socket at the first of `addresses`, or at a free port should it be 0.
+    fn bind<A>(addresses: A) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let mut address = addresses
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind to"))?;
+        // There are no interfaces, sockets bound to all of them are only reachable on localhost
+        match address {
+            SocketAddr::V4(ref mut v4) if v4.ip().is_unspecified() => {
+                v4.set_ip(Ipv4Addr::LOCALHOST)
+            }
+            SocketAddr::V6(ref mut v6) if v6.ip().is_unspecified() => {
+                v6.set_ip(Ipv6Addr::LOCALHOST)
+            }
+            _ => {}
+        }
+
+        let mut sockets = sockets();
+        if address.port() == 0 {
+            let port = (FIRST_EPHEMERAL_PORT..=u16::MAX)
+                .find(|port| !sockets.contains_key(&SocketAddr::new(address.ip(), *port)))
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::AddrInUse, "no free loopback port left")
+                })?;
+            address.set_port(port);
+        } else if sockets.contains_key(&address) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("loopback address {} already in use", address),
+            ));
+        }
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        sockets.insert(address, sender);
+        Ok(Self { address, receiver })
+    }
+
+    /// Binds a loopback socket and wraps it in laminar's connection handling.
+    pub(crate) fn bind_manager<A>(addresses: A, config: Config) -> io::Result<LoopbackManager>
+    where
+        A: ToSocketAddrs,
+    {
+        Ok(ConnectionManager::new(Self::bind(addresses)?, config))
+    }
+}
+
+impl DatagramSocket for LoopbackSocket {
+    fn send_packet(&mut self, addr: &SocketAddr, payload: &[u8]) -> io::Result<usize> {
+        // As with UDP, datagrams to an address nobody is bound to are silently lost
+        if let Some(sender) = sockets().get(addr) {
+            let _ = sender.send((self.address, payload.to_vec()));
+        }
+        Ok(payload.len())
+    }
+
+    fn receive_packet<'a>(&mut self, buffer: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+        let (from, payload) = self
+            .receiver
+            .try_recv()
+            .map_err(|_| io::Error::from(io::ErrorKind::WouldBlock))?;
+        // Truncate datagrams larger than the buffer, as UDP does
+        let len = payload.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&payload[..len]);
+        Ok((&buffer[..len], from))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+
+    fn is_blocking_mode(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for LoopbackSocket {
+    fn drop(&mut self) {
+        sockets().remove(&self.address);
+    }
+}
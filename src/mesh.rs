@@ -0,0 +1,143 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    Connect, ConnectionAddress, ConnectionMarker, ConnectionState, NetworkClock, Probe, SendQueue,
+    Socket, SocketId,
+};
+
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A [`Component`] which, when present on a socket entity, connects to every peer in a list, for
+/// instance one provided by a lobby.
+///
+/// Connections to missing peers are opened with [`Connect`] and every peer is sent a [`Probe`] at
+/// an interval. Links reach [`ConnectionState::Connected`] once traffic flows both ways, which
+/// requires every peer to run a [`Mesh`] or otherwise send to the others, and the probes keep
+/// them from timing out while idle. Probes are never delivered to a
+/// [`ReceiveQueue`](crate::ReceiveQueue). The socket's own address is skipped, and a
+/// [`MeshReady`] event is emitted each time all links become connected.
+#[derive(Debug, Clone, Component, PartialEq, Eq)]
+pub struct Mesh {
+    peers: Vec<SocketAddr>,
+    probe_interval: Duration,
+    links: HashMap<SocketAddr, Entity>,
+    last_probe: Option<Instant>,
+    ready: bool,
+}
+
+impl Mesh {
+    /// Creates a new [`Mesh`] connecting to `peers`.
+    pub fn new(peers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            peers: peers.into_iter().collect(),
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            links: HashMap::new(),
+            last_probe: None,
+            ready: false,
+        }
+    }
+
+    /// Sets the interval between probes sent to each peer.
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Returns the peers.
+    pub fn peers(&self) -> &[SocketAddr] {
+        &self.peers
+    }
+
+    /// Returns the connection entity linking to a peer, if any.
+    pub fn link(&self, peer: &SocketAddr) -> Option<Entity> {
+        self.links.get(peer).copied()
+    }
+
+    /// Returns `true` if every link is connected.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+}
+
+/// An event emitted when every link of a [`Mesh`] becomes connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshReady {
+    /// The socket entity.
+    pub socket: Entity,
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn drive_meshes(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut mesh_query: Query<(Entity, &Socket, &mut Mesh, &mut SendQueue)>,
+    connection_query: Query<
+        (Entity, &SocketId, &ConnectionAddress, &ConnectionState),
+        With<ConnectionMarker>,
+    >,
+    mut ready_writer: EventWriter<MeshReady>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (socket_id, socket, mut mesh, mut send_queue) in mesh_query.iter_mut() {
+        let local_addr = socket.local_addr();
+        let mesh = &mut *mesh;
+
+        let probe = mesh
+            .last_probe
+            .is_none_or(|last_probe| last_probe + mesh.probe_interval <= now);
+        if probe {
+            mesh.last_probe = Some(now);
+        }
+
+        let mut ready = true;
+        for &peer in &mesh.peers {
+            if Some(peer) == local_addr {
+                continue;
+            }
+
+            // Prefer the linked connection, falling back to any live connection to the peer
+            let linked = mesh
+                .links
+                .get(&peer)
+                .and_then(|link| connection_query.get(*link).ok());
+            let existing = linked.or_else(|| {
+                connection_query.iter().find(|(_, id, addr, state)| {
+                    id.0 == socket_id && addr.matches(&peer) && !state.is_disconnected()
+                })
+            });
+
+            let state = if let Some((connection, _, _, state)) = existing {
+                mesh.links.insert(peer, connection);
+                *state
+            } else {
+                trace!(message = "opening mesh link", address = %peer);
+                let connection = commands.connect(socket_id, peer);
+                mesh.links.insert(peer, connection);
+                ConnectionState::Connecting
+            };
+
+            ready &= state == ConnectionState::Connected;
+            if probe {
+                send_queue.send_probe(peer, Probe { id: 0 });
+            }
+        }
+
+        if ready && !mesh.ready {
+            ready_writer.send(MeshReady { socket: socket_id });
+        }
+        mesh.ready = ready;
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    any::type_name,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    convert::TryInto,
+    fmt,
+    hash::{BuildHasher, Hasher},
+};
+
+use bevy::{prelude::*, utils::tracing::Span};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    connection::QueuedPayload, wire::TRACE_TAG, ConnectionMarker, ConnectionSendQueue, Delivery,
+    NetworkStage, ReceiveQueue, SocketId, SocketMarker,
+};
+
+/// The length of the [`NetworkMessage::ID`] prefix of an encoded message.
+const ID_LEN: usize = 2;
+
+/// The length of the envelope of a traced message: tag and [`TraceId`].
+const TRACE_LEN: usize = 4 + 8;
+
+/// A type sent between peers as a typed message, see [`AddNetworkMessage`].
+///
+/// Messages are encoded with bincode, prefixed by their [`ID`](NetworkMessage::ID) in big-endian.
+pub trait NetworkMessage: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Identifies the message type on the wire. It must be unique among the message types
+    /// registered with an [`App`] and agreed upon by peers.
+    const ID: u16;
+}
+
+/// Encodes a message, prefixed by its [`NetworkMessage::ID`].
+pub fn encode_message<M>(message: &M) -> Result<Vec<u8>, bincode::Error>
+where
+    M: NetworkMessage,
+{
+    let mut payload = M::ID.to_be_bytes().to_vec();
+    payload.extend(bincode::serialize(message)?);
+    Ok(payload)
+}
+
+/// Encodes a message, prefixed by its [`NetworkMessage::ID`] and wrapped in an envelope carrying
+/// `trace_id`.
+pub fn encode_traced_message<M>(message: &M, trace_id: TraceId) -> Result<Vec<u8>, bincode::Error>
+where
+    M: NetworkMessage,
+{
+    let mut payload = Vec::with_capacity(TRACE_LEN);
+    payload.extend_from_slice(&TRACE_TAG);
+    payload.extend_from_slice(&trace_id.0.to_be_bytes());
+    payload.extend(encode_message(message)?);
+    Ok(payload)
+}
+
+/// Decodes a message of type `M`, returning `None` if the payload carries another message type.
+///
+/// Traced messages are decoded as well, discarding their [`TraceId`].
+pub fn decode_message<M>(payload: &[u8]) -> Option<Result<M, bincode::Error>>
+where
+    M: NetworkMessage,
+{
+    let (_, payload) = split_trace(payload);
+    if payload.len() < ID_LEN || payload[..ID_LEN] != M::ID.to_be_bytes() {
+        return None;
+    }
+
+    Some(bincode::deserialize(&payload[ID_LEN..]))
+}
+
+/// Splits the [`TraceId`] of a traced message from the encoded message.
+fn split_trace(payload: &[u8]) -> (Option<TraceId>, &[u8]) {
+    match payload.strip_prefix(&TRACE_TAG) {
+        Some(rest) if rest.len() >= TRACE_LEN - TRACE_TAG.len() => {
+            let (id, rest) = rest.split_at(TRACE_LEN - TRACE_TAG.len());
+            let id = u64::from_be_bytes(id.try_into().unwrap());
+            (Some(TraceId(id)), rest)
+        }
+        _ => (None, payload),
+    }
+}
+
+/// Parses the envelope of a traced message and builds it again over the same message, see
+/// [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    let (trace_id, message) = split_trace(payload);
+    let mut reencoded = TRACE_TAG.to_vec();
+    reencoded.extend_from_slice(&trace_id?.0.to_be_bytes());
+    reencoded.extend_from_slice(message);
+    Some(reencoded)
+}
+
+/// Identifies a user action across peers, stamped on the messages it causes with
+/// [`ConnectionSendQueue::send_traced_message`].
+///
+/// The id is logged when the message is sent and received, and carried by the
+/// [`MessageReceived`] event, so that the action may be followed from the sender's logs, through
+/// the wire, to the receiver's logs. It is displayed in hexadecimal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TraceId(pub u64);
+
+impl TraceId {
+    /// Creates a new random [`TraceId`].
+    pub fn random() -> Self {
+        Self(RandomState::new().build_hasher().finish())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl ConnectionSendQueue {
+    /// Encodes a message and sends it to the peer with the given [`Delivery`].
+    pub fn send_message<M>(&mut self, delivery: Delivery, message: &M) -> Result<(), bincode::Error>
+    where
+        M: NetworkMessage,
+    {
+        self.payloads.push(QueuedPayload {
+            message: Some((M::ID, type_name::<M>())),
+            ..QueuedPayload::new(delivery, encode_message(message)?)
+        });
+        Ok(())
+    }
+
+    /// Encodes a message stamped with `trace_id` and sends it to the peer with the given
+    /// [`Delivery`], see [`TraceId`].
+    pub fn send_traced_message<M>(
+        &mut self,
+        delivery: Delivery,
+        message: &M,
+        trace_id: TraceId,
+    ) -> Result<(), bincode::Error>
+    where
+        M: NetworkMessage,
+    {
+        debug!(message = "sending traced message", %trace_id, id = M::ID, name = type_name::<M>());
+        self.payloads.push(QueuedPayload {
+            message: Some((M::ID, type_name::<M>())),
+            ..QueuedPayload::new(delivery, encode_traced_message(message, trace_id)?)
+        });
+        Ok(())
+    }
+}
+
+/// A [`Component`] which, when present on a connection or socket entity, mutes message types.
+///
+/// Muted messages sent with [`ConnectionSendQueue::send_message`] are dropped before reaching the
+/// socket, without being accounted in [`MessageBandwidth`]. A mask on the socket applies to all of
+/// its connections, in addition to their own. Payloads sent with [`ConnectionSendQueue::send`] are
+/// never muted.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMask {
+    muted: HashSet<u16>,
+}
+
+impl ChannelMask {
+    /// Mutes message type `M`.
+    pub fn with_muted<M>(mut self) -> Self
+    where
+        M: NetworkMessage,
+    {
+        self.mute::<M>();
+        self
+    }
+
+    /// Mutes message type `M`.
+    pub fn mute<M>(&mut self)
+    where
+        M: NetworkMessage,
+    {
+        self.muted.insert(M::ID);
+    }
+
+    /// Unmutes message type `M`.
+    pub fn unmute<M>(&mut self)
+    where
+        M: NetworkMessage,
+    {
+        self.muted.remove(&M::ID);
+    }
+
+    /// Returns `true` if message type `M` is muted.
+    pub fn is_muted<M>(&self) -> bool
+    where
+        M: NetworkMessage,
+    {
+        self.muted.contains(&M::ID)
+    }
+}
+
+/// The number of messages and bytes of a single message type sent and received.
+///
+/// Byte counts include the [`NetworkMessage::ID`] prefix but not laminar's headers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageTraffic {
+    /// The number of messages sent.
+    pub sent: u64,
+    /// The number of bytes sent.
+    pub sent_bytes: u64,
+    /// The number of messages received.
+    pub received: u64,
+    /// The number of bytes received.
+    pub received_bytes: u64,
+}
+
+/// The [`MessageTraffic`] of each registered message type.
+///
+/// Used as a resource, aggregating the traffic of every connection, and as a [`Component`] which,
+/// when present on a connection entity, accounts for that connection alone.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
+pub struct MessageBandwidth {
+    traffic: HashMap<u16, (&'static str, MessageTraffic)>,
+}
+
+impl MessageBandwidth {
+    /// Returns the traffic of message type `M`, if any has been accounted.
+    pub fn get<M>(&self) -> Option<&MessageTraffic>
+    where
+        M: NetworkMessage,
+    {
+        self.traffic.get(&M::ID).map(|(_, traffic)| traffic)
+    }
+
+    /// Iterates over the [`NetworkMessage::ID`], type name and traffic of each message type.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &'static str, &MessageTraffic)> {
+        self.traffic
+            .iter()
+            .map(|(id, (name, traffic))| (*id, *name, traffic))
+    }
+
+    /// Resets all counts.
+    pub fn reset(&mut self) {
+        self.traffic.clear();
+    }
+
+    fn entry(&mut self, id: u16, name: &'static str) -> &mut MessageTraffic {
+        &mut self
+            .traffic
+            .entry(id)
+            .or_insert_with(|| (name, MessageTraffic::default()))
+            .1
+    }
+
+    pub(crate) fn record_sent(&mut self, id: u16, name: &'static str, len: usize) {
+        let traffic = self.entry(id, name);
+        traffic.sent += 1;
+        traffic.sent_bytes += len as u64;
+    }
+
+    pub(crate) fn record_received(&mut self, id: u16, name: &'static str, len: usize) {
+        let traffic = self.entry(id, name);
+        traffic.received += 1;
+        traffic.received_bytes += len as u64;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn prepare_sent_messages(
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &mut ConnectionSendQueue,
+            Option<&ChannelMask>,
+            Option<&mut MessageBandwidth>,
+        ),
+        Changed<ConnectionSendQueue>,
+    >,
+    mask_query: Query<&ChannelMask, With<SocketMarker>>,
+    mut bandwidth: ResMut<MessageBandwidth>,
+) {
+    for (connection, socket_id, mut queue, mask_opt, mut connection_bandwidth) in
+        connection_query.iter_mut()
+    {
+        if queue.is_empty() {
+            continue;
+        }
+
+        let socket_mask_opt = mask_query.get(socket_id.0).ok();
+        let is_muted = |id: &u16| {
+            mask_opt
+                .into_iter()
+                .chain(socket_mask_opt)
+                .any(|mask| mask.muted.contains(id))
+        };
+
+        queue.payloads.retain(|queued| {
+            let (id, name) = match queued.message {
+                Some(message) => message,
+                None => return true,
+            };
+            if is_muted(&id) {
+                trace!(message = "dropping muted message", id, ?connection);
+                return false;
+            }
+
+            let len = queued.payload.len();
+            bandwidth.record_sent(id, name, len);
+            if let Some(connection_bandwidth) = connection_bandwidth.as_mut() {
+                connection_bandwidth.record_sent(id, name, len);
+            }
+            true
+        });
+    }
+}
+
+/// An event emitted when a message of type `M` is received by a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageReceived<M> {
+    /// The connection entity which received the message.
+    pub connection: Entity,
+    /// The decoded message.
+    pub message: M,
+    /// The trace id the message was stamped with, if any.
+    pub trace_id: Option<TraceId>,
+}
+
+impl<M> MessageReceived<M> {
+    /// Returns a span carrying the trace id of the message, to be entered while handling it so
+    /// that the logs it causes are tagged along, or a disabled span if it was not traced.
+    pub fn span(&self) -> Span {
+        match self.trace_id {
+            Some(trace_id) => {
+                info_span!("traced_message", %trace_id, connection = ?self.connection)
+            }
+            None => Span::none(),
+        }
+    }
+}
+
+/// An extension trait for [`App`] registering typed messages.
+pub trait AddNetworkMessage {
+    /// Registers `M`, emitting a [`MessageReceived<M>`] for each message of that type received.
+    ///
+    /// Packets carrying `M` are removed from the [`ReceiveQueue`] in [`NetworkStage::PostRecv`],
+    /// other packets are left in place. Packets which fail to decode are logged and dropped.
+    ///
+    /// Traffic of `M` is accounted in the [`MessageBandwidth`] resource and component.
+    fn add_network_message<M>(&mut self) -> &mut Self
+    where
+        M: NetworkMessage;
+}
+
+impl AddNetworkMessage for App {
+    fn add_network_message<M>(&mut self) -> &mut Self
+    where
+        M: NetworkMessage,
+    {
+        self.init_resource::<MessageBandwidth>()
+            .add_event::<MessageReceived<M>>()
+            .add_system_to_stage(NetworkStage::PostRecv, receive_messages::<M>)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn receive_messages<M>(
+    mut connection_query: Query<
+        (Entity, &mut ReceiveQueue, Option<&mut MessageBandwidth>),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+    mut bandwidth: ResMut<MessageBandwidth>,
+    mut message_writer: EventWriter<MessageReceived<M>>,
+) where
+    M: NetworkMessage,
+{
+    for (connection, mut queue, mut connection_bandwidth) in connection_query.iter_mut() {
+        queue
+            .0
+            .retain(|packet| match decode_message::<M>(packet.payload()) {
+                None => true,
+                Some(Ok(message)) => {
+                    let len = packet.payload().len();
+                    bandwidth.record_received(M::ID, type_name::<M>(), len);
+                    if let Some(connection_bandwidth) = connection_bandwidth.as_mut() {
+                        connection_bandwidth.record_received(M::ID, type_name::<M>(), len);
+                    }
+                    let (trace_id, _) = split_trace(packet.payload());
+                    if let Some(trace_id) = trace_id {
+                        debug!(message = "received traced message", %trace_id, id = M::ID, ?connection);
+                    }
+                    message_writer.send(MessageReceived {
+                        connection,
+                        message,
+                        trace_id,
+                    });
+                    false
+                }
+                Some(Err(error)) => {
+                    warn!(message = "failed to decode message", id = M::ID, ?connection, %error);
+                    false
+                }
+            });
+    }
+}
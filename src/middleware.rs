@@ -0,0 +1,91 @@
+use std::any::type_name;
+
+use bevy::prelude::*;
+use laminar::Packet;
+
+/// A layer of a [`MiddlewareChain`], transforming or dropping the packets of a socket as they
+/// are sent and received.
+///
+/// Both methods pass packets through unchanged by default, so that layers need only implement
+/// the direction they act upon. Returning `None` drops the packet.
+pub trait Middleware: Send + Sync + 'static {
+    /// Transforms a packet about to be sent.
+    fn send(&mut self, packet: Packet) -> Option<Packet> {
+        Some(packet)
+    }
+
+    /// Transforms a packet just received.
+    fn recv(&mut self, packet: Packet) -> Option<Packet> {
+        Some(packet)
+    }
+}
+
+/// A [`Component`] which, when present on a socket entity, runs its packets through an ordered
+/// chain of [`Middleware`], such as to compress, encrypt, measure or log them.
+///
+/// Layers wrap each other: sent packets run through the layers in the order they were added,
+/// and received packets in the reverse order, so that the first layer added sees packets closest
+/// to the application and the last closest to the wire. The chain sits right against the wire,
+/// after payloads were sealed by the `encryption` feature and before they are opened, and packets
+/// are counted by [`NetworkStats`](crate::NetworkStats) as they are on the wire. Received packets
+/// are run through the chain once the [`BlockedAddresses`](crate::BlockedAddresses) and
+/// [`AddressFilter`](crate::AddressFilter) of the socket let them through.
+#[derive(Default, Component)]
+pub struct MiddlewareChain {
+    layers: Vec<(&'static str, Box<dyn Middleware>)>,
+}
+
+impl std::fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.layers.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl MiddlewareChain {
+    /// Creates a new, empty [`MiddlewareChain`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer, closer to the wire than those already added.
+    pub fn with<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.push(middleware);
+        self
+    }
+
+    /// Adds a layer, closer to the wire than those already added.
+    pub fn push<M>(&mut self, middleware: M)
+    where
+        M: Middleware,
+    {
+        self.layers.push((type_name::<M>(), Box::new(middleware)));
+    }
+
+    /// Returns the number of layers.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns `true` if there are no layers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn send(&mut self, packet: Packet) -> Option<Packet> {
+        self.layers
+            .iter_mut()
+            .try_fold(packet, |packet, (_, layer)| layer.send(packet))
+    }
+
+    pub(crate) fn recv(&mut self, packet: Packet) -> Option<Packet> {
+        self.layers
+            .iter_mut()
+            .rev()
+            .try_fold(packet, |packet, (_, layer)| layer.recv(packet))
+    }
+}
@@ -0,0 +1,198 @@
+use std::{error::Error, fmt, io, time::Duration};
+
+use laminar::Config;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The connection keep-alive settings of a socket, applied over a laminar [`Config`].
+///
+/// These are the [`Config`] fields which interact with [`PollInterval`](crate::PollInterval):
+/// connections are only serviced when the socket is polled, so polling less often than the
+/// heartbeat interval or idle timeout causes late heartbeats or spurious timeouts.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionOptions {
+    /// The interval at which heartbeats are sent to idle peers, or `None` to disable them.
+    pub heartbeat_interval: Option<Duration>,
+    /// The time without hearing from a peer before it times out.
+    pub idle_connection_timeout: Duration,
+    /// The maximum number of unestablished connections laminar tracks.
+    pub max_unestablished_connections: u16,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self::from(&Config::default())
+    }
+}
+
+impl From<&Config> for ConnectionOptions {
+    fn from(config: &Config) -> Self {
+        Self {
+            heartbeat_interval: config.heartbeat_interval,
+            idle_connection_timeout: config.idle_connection_timeout,
+            max_unestablished_connections: config.max_unestablished_connections,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    /// Sends heartbeats to peers which have not been sent anything for `interval`.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// Disables heartbeats.
+    pub fn without_heartbeat(mut self) -> Self {
+        self.heartbeat_interval = None;
+        self
+    }
+
+    /// Sets the time without hearing from a peer before it times out.
+    pub fn with_idle_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_connection_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of unestablished connections laminar tracks.
+    pub fn with_max_unestablished_connections(mut self, max: u16) -> Self {
+        self.max_unestablished_connections = max;
+        self
+    }
+
+    /// Checks the options are consistent with each other and with a socket's `poll_interval`.
+    pub fn validate(&self, poll_interval: Duration) -> Result<(), OptionsError> {
+        if poll_interval >= self.idle_connection_timeout {
+            return Err(OptionsError::PollExceedsIdleTimeout {
+                poll_interval,
+                idle_connection_timeout: self.idle_connection_timeout,
+            });
+        }
+
+        if let Some(heartbeat_interval) = self.heartbeat_interval {
+            if heartbeat_interval >= self.idle_connection_timeout {
+                return Err(OptionsError::HeartbeatExceedsIdleTimeout {
+                    heartbeat_interval,
+                    idle_connection_timeout: self.idle_connection_timeout,
+                });
+            }
+
+            if poll_interval > heartbeat_interval {
+                return Err(OptionsError::PollExceedsHeartbeat {
+                    poll_interval,
+                    heartbeat_interval,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the options into a laminar [`Config`].
+    pub fn apply(&self, config: &mut Config) {
+        config.heartbeat_interval = self.heartbeat_interval;
+        config.idle_connection_timeout = self.idle_connection_timeout;
+        config.max_unestablished_connections = self.max_unestablished_connections;
+    }
+}
+
+/// An inconsistency found by [`ConnectionOptions::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptionsError {
+    /// Peers would time out between polls.
+    PollExceedsIdleTimeout {
+        /// The socket's poll interval.
+        poll_interval: Duration,
+        /// The idle connection timeout.
+        idle_connection_timeout: Duration,
+    },
+    /// Heartbeats would be sent late.
+    PollExceedsHeartbeat {
+        /// The socket's poll interval.
+        poll_interval: Duration,
+        /// The heartbeat interval.
+        heartbeat_interval: Duration,
+    },
+    /// Heartbeats would not keep idle peers alive.
+    HeartbeatExceedsIdleTimeout {
+        /// The heartbeat interval.
+        heartbeat_interval: Duration,
+        /// The idle connection timeout.
+        idle_connection_timeout: Duration,
+    },
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PollExceedsIdleTimeout {
+                poll_interval,
+                idle_connection_timeout,
+            } => write!(
+                f,
+                "poll interval {:?} exceeds idle connection timeout {:?}, peers will time out between polls",
+                poll_interval, idle_connection_timeout
+            ),
+            Self::PollExceedsHeartbeat {
+                poll_interval,
+                heartbeat_interval,
+            } => write!(
+                f,
+                "poll interval {:?} exceeds heartbeat interval {:?}, heartbeats will be sent late",
+                poll_interval, heartbeat_interval
+            ),
+            Self::HeartbeatExceedsIdleTimeout {
+                heartbeat_interval,
+                idle_connection_timeout,
+            } => write!(
+                f,
+                "heartbeat interval {:?} exceeds idle connection timeout {:?}, idle peers will time out",
+                heartbeat_interval, idle_connection_timeout
+            ),
+        }
+    }
+}
+
+impl Error for OptionsError {}
+
+/// An error found by [`validate_bind`](crate::validate_bind) or
+/// [`SocketDescriptor::validate`](crate::SocketDescriptor::validate).
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The address could not be bound, for instance because it is in use or not local.
+    Bind(io::Error),
+    /// The connection options are inconsistent.
+    Options(OptionsError),
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(error: io::Error) -> Self {
+        Self::Bind(error)
+    }
+}
+
+impl From<OptionsError> for NetworkError {
+    fn from(error: OptionsError) -> Self {
+        Self::Options(error)
+    }
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bind(error) => write!(f, "failed to bind: {}", error),
+            Self::Options(error) => write!(f, "invalid connection options: {}", error),
+        }
+    }
+}
+
+impl Error for NetworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Bind(error) => Some(error),
+            Self::Options(error) => Some(error),
+        }
+    }
+}
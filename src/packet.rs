@@ -0,0 +1,63 @@
+use std::net::SocketAddr;
+
+use laminar::{DeliveryGuarantee, OrderingGuarantee, Packet};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The delivery and ordering guarantees of a [`Packet`], mirroring its constructors.
+///
+/// Stream ids of `None` use laminar's default stream.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delivery {
+    /// See [`Packet::unreliable`].
+    Unreliable,
+    /// See [`Packet::unreliable_sequenced`].
+    UnreliableSequenced(Option<u8>),
+    /// See [`Packet::reliable_unordered`].
+    ReliableUnordered,
+    /// See [`Packet::reliable_sequenced`].
+    ReliableSequenced(Option<u8>),
+    /// See [`Packet::reliable_ordered`].
+    ReliableOrdered(Option<u8>),
+}
+
+impl Delivery {
+    /// Creates a [`Packet`] to `addr` carrying `payload` with these guarantees.
+    pub fn packet(self, addr: SocketAddr, payload: Vec<u8>) -> Packet {
+        match self {
+            Self::Unreliable => Packet::unreliable(addr, payload),
+            Self::UnreliableSequenced(stream_id) => {
+                Packet::unreliable_sequenced(addr, payload, stream_id)
+            }
+            Self::ReliableUnordered => Packet::reliable_unordered(addr, payload),
+            Self::ReliableSequenced(stream_id) => {
+                Packet::reliable_sequenced(addr, payload, stream_id)
+            }
+            Self::ReliableOrdered(stream_id) => Packet::reliable_ordered(addr, payload, stream_id),
+        }
+    }
+}
+
+impl From<&Packet> for Delivery {
+    fn from(packet: &Packet) -> Self {
+        match (packet.delivery_guarantee(), packet.order_guarantee()) {
+            (DeliveryGuarantee::Unreliable, OrderingGuarantee::None) => Self::Unreliable,
+            (DeliveryGuarantee::Unreliable, OrderingGuarantee::Sequenced(stream_id)) => {
+                Self::UnreliableSequenced(stream_id)
+            }
+            (DeliveryGuarantee::Reliable, OrderingGuarantee::None) => Self::ReliableUnordered,
+            (DeliveryGuarantee::Reliable, OrderingGuarantee::Sequenced(stream_id)) => {
+                Self::ReliableSequenced(stream_id)
+            }
+            // Laminar has no unreliable ordered delivery, ordering implies reliability
+            (_, OrderingGuarantee::Ordered(stream_id)) => Self::ReliableOrdered(stream_id),
+        }
+    }
+}
+
+/// Creates a [`Packet`] with the same address and guarantees as `packet`, carrying `payload`.
+pub(crate) fn with_payload(packet: &Packet, payload: Vec<u8>) -> Packet {
+    Delivery::from(packet).packet(packet.addr(), payload)
+}
@@ -0,0 +1,154 @@
+use std::{fmt::Debug, net::SocketAddr};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    ConnectionAddress, ConnectionMarker, ConnectionState, ReceiveQueue, SendQueue, SocketId,
+    SocketMarker,
+};
+
+/// The components yielded for each connection by [`Connections`].
+pub type ConnectionItem<'a> = (
+    Entity,
+    &'a SocketId,
+    &'a ConnectionAddress,
+    &'a ConnectionState,
+);
+
+/// A [`SystemParam`] for looking up connections by socket, address, or state.
+#[derive(SystemParam)]
+pub struct Connections<'w, 's> {
+    query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static SocketId,
+            &'static ConnectionAddress,
+            &'static ConnectionState,
+        ),
+        With<ConnectionMarker>,
+    >,
+}
+
+impl Connections<'_, '_> {
+    /// Returns an iterator over all connections.
+    pub fn iter(&self) -> impl Iterator<Item = ConnectionItem<'_>> {
+        self.query.iter()
+    }
+
+    /// Returns the connection with the given entity, if any.
+    pub fn get(&self, connection: Entity) -> Option<ConnectionItem<'_>> {
+        self.query.get(connection).ok()
+    }
+
+    /// Returns an iterator over the connections of the given socket.
+    pub fn of_socket(&self, socket: Entity) -> impl Iterator<Item = ConnectionItem<'_>> {
+        self.iter().filter(move |(_, id, _, _)| id.0 == socket)
+    }
+
+    /// Returns an iterator over the connections with the given peer address, across all sockets.
+    ///
+    /// Addresses are compared after normalization, see [`ConnectionAddress::matches`].
+    pub fn by_addr(&self, address: SocketAddr) -> impl Iterator<Item = ConnectionItem<'_>> {
+        self.iter()
+            .filter(move |(_, _, addr, _)| addr.matches(&address))
+    }
+
+    /// Returns an iterator over the connections in [`ConnectionState::Connected`].
+    pub fn connected(&self) -> impl Iterator<Item = ConnectionItem<'_>> {
+        self.iter()
+            .filter(|(_, _, _, state)| **state == ConnectionState::Connected)
+    }
+}
+
+impl Debug for Connections<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connections").finish_non_exhaustive()
+    }
+}
+
+/// A connection together with its [`ReceiveQueue`] and the [`SendQueue`] of its socket, yielded by
+/// [`Network`].
+#[derive(Debug)]
+pub struct ConnectionMut<'a> {
+    /// The connection entity.
+    pub connection: Entity,
+    /// The socket entity owning the connection.
+    pub socket: Entity,
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// The connection's state.
+    pub state: ConnectionState,
+    /// The connection's [`ReceiveQueue`].
+    pub receive: Mut<'a, ReceiveQueue>,
+    /// The socket's [`SendQueue`].
+    pub send: Mut<'a, SendQueue>,
+}
+
+/// A [`SystemParam`] combining sockets with their connections, allowing a connection's
+/// [`ReceiveQueue`] and its socket's [`SendQueue`] to be borrowed together.
+#[derive(SystemParam)]
+pub struct Network<'w, 's> {
+    sockets: Query<'w, 's, &'static mut SendQueue, With<SocketMarker>>,
+    connections: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static SocketId,
+            &'static ConnectionAddress,
+            &'static ConnectionState,
+            &'static mut ReceiveQueue,
+        ),
+        With<ConnectionMarker>,
+    >,
+}
+
+impl Network<'_, '_> {
+    /// Returns the [`SendQueue`] of the given socket, if any.
+    pub fn send_queue(&mut self, socket: Entity) -> Option<Mut<'_, SendQueue>> {
+        self.sockets.get_mut(socket).ok()
+    }
+
+    /// Returns the given connection with its queues, if any.
+    pub fn get_mut(&mut self, connection: Entity) -> Option<ConnectionMut<'_>> {
+        let (connection, socket_id, addr, state, receive) =
+            self.connections.get_mut(connection).ok()?;
+        let send = self.sockets.get_mut(socket_id.0).ok()?;
+        Some(ConnectionMut {
+            connection,
+            socket: socket_id.0,
+            address: addr.0,
+            state: *state,
+            receive,
+            send,
+        })
+    }
+
+    /// Runs `f` against every connection with its queues.
+    pub fn for_each_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(ConnectionMut<'_>),
+    {
+        let sockets = &mut self.sockets;
+        for (connection, socket_id, addr, state, receive) in self.connections.iter_mut() {
+            if let Ok(send) = sockets.get_mut(socket_id.0) {
+                f(ConnectionMut {
+                    connection,
+                    socket: socket_id.0,
+                    address: addr.0,
+                    state: *state,
+                    receive,
+                    send,
+                });
+            }
+        }
+    }
+}
+
+impl Debug for Network<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Network").finish_non_exhaustive()
+    }
+}
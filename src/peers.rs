@@ -0,0 +1,252 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+#[cfg(feature = "serde")]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::{
+    normalize_address, ConnectionAddress, ConnectionMarker, ConnectionState, ConnectionTransition,
+    Credentials, Rtt,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What is known of a peer previously connected to, see [`KnownPeers`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KnownPeer {
+    /// The peer's address.
+    pub address: SocketAddr,
+    /// The number of times the peer answered a connection.
+    pub successes: u32,
+    /// The number of times in a row the peer failed to answer a connection.
+    pub consecutive_failures: u32,
+    /// When the peer last answered a connection.
+    pub last_success: Option<SystemTime>,
+    /// The latest smoothed round trip time to the peer, see [`Rtt`].
+    pub rtt: Option<Duration>,
+    /// A session token to present when connecting to the peer, see [`KnownPeer::credentials`].
+    pub session_token: Option<Vec<u8>>,
+}
+
+impl KnownPeer {
+    fn new(address: SocketAddr) -> Self {
+        Self {
+            address,
+            successes: 0,
+            consecutive_failures: 0,
+            last_success: None,
+            rtt: None,
+            session_token: None,
+        }
+    }
+
+    /// Returns the session token as pre-shared [`Credentials`], if there is one.
+    pub fn credentials(&self) -> Option<Credentials> {
+        self.session_token.clone().map(Credentials::pre_shared)
+    }
+}
+
+/// A resource which, when present, records the peers outbound connections reached, such as
+/// servers, to be ranked when choosing where to connect to next.
+///
+/// A peer is recorded as soon as it answers a connection opened with [`Connect`](crate::Connect),
+/// and counts a failure should such a connection time out or disconnect before it does. The
+/// round trip time of connections with an [`Rtt`] is recorded as it is measured. With the
+/// `serde` feature, the peers may be saved to and loaded from disk, see `KnownPeers::load` and
+/// `KnownPeers::persist_to`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KnownPeers {
+    peers: HashMap<SocketAddr, KnownPeer>,
+    #[cfg(feature = "serde")]
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dirty: bool,
+}
+
+impl KnownPeers {
+    /// Returns what is known of a peer.
+    pub fn get(&self, address: SocketAddr) -> Option<&KnownPeer> {
+        self.peers.get(&normalize_address(address))
+    }
+
+    /// Returns an iterator over the known peers, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &KnownPeer> {
+        self.peers.values()
+    }
+
+    /// Returns the number of known peers.
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Returns `true` if no peer is known.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the known peers from most to least preferred: those with the fewest consecutive
+    /// failures first, then the lowest round trip time, then the most recently reached.
+    pub fn ranked(&self) -> Vec<&KnownPeer> {
+        let mut ranked: Vec<_> = self.peers.values().collect();
+        ranked.sort_by_key(|peer| {
+            (
+                peer.consecutive_failures,
+                peer.rtt.is_none(),
+                peer.rtt,
+                Reverse(peer.last_success),
+                peer.address,
+            )
+        });
+        ranked
+    }
+
+    /// Returns the most preferred peer among `candidates`, falling back to the first candidate
+    /// should none of them be known, see [`KnownPeers::ranked`].
+    pub fn choose(&self, candidates: &[SocketAddr]) -> Option<SocketAddr> {
+        self.ranked()
+            .into_iter()
+            .map(|peer| peer.address)
+            .find(|address| {
+                candidates
+                    .iter()
+                    .any(|candidate| normalize_address(*candidate) == *address)
+            })
+            .or_else(|| candidates.first().copied())
+    }
+
+    /// Records that a peer answered a connection.
+    pub fn record_success(&mut self, address: SocketAddr) {
+        let peer = self.entry(address);
+        peer.successes += 1;
+        peer.consecutive_failures = 0;
+        peer.last_success = Some(SystemTime::now());
+        self.dirty = true;
+    }
+
+    /// Records that a peer failed to answer a connection.
+    pub fn record_failure(&mut self, address: SocketAddr) {
+        self.entry(address).consecutive_failures += 1;
+        self.dirty = true;
+    }
+
+    /// Records the round trip time to a peer, if it is known.
+    pub fn record_rtt(&mut self, address: SocketAddr, rtt: Duration) {
+        if let Some(peer) = self.peers.get_mut(&normalize_address(address)) {
+            peer.rtt = Some(rtt);
+        }
+    }
+
+    /// Sets the session token to present when connecting to a peer, or clears it with `None`.
+    pub fn set_session_token(&mut self, address: SocketAddr, token: Option<Vec<u8>>) {
+        self.entry(address).session_token = token;
+        self.dirty = true;
+    }
+
+    /// Forgets a peer, returning what was known of it.
+    pub fn forget(&mut self, address: SocketAddr) -> Option<KnownPeer> {
+        let removed = self.peers.remove(&normalize_address(address));
+        self.dirty |= removed.is_some();
+        removed
+    }
+
+    fn entry(&mut self, address: SocketAddr) -> &mut KnownPeer {
+        let address = normalize_address(address);
+        self.peers
+            .entry(address)
+            .or_insert_with(|| KnownPeer::new(address))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl KnownPeers {
+    /// Loads the peers saved at `path`, or none should the file not exist.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(error),
+        };
+        bincode::deserialize(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Saves the peers to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = bincode::serialize(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(path, bytes)
+    }
+
+    /// Saves the peers to `path` whenever a peer is reached, fails or has its session token
+    /// changed. Round trip times are saved along, but do not trigger a save.
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+/// Records a transition of an outbound connection in the [`KnownPeers`].
+pub(crate) struct RecordKnownPeer(pub(crate) ConnectionTransition);
+
+impl Command for RecordKnownPeer {
+    fn write(self, world: &mut World) {
+        let transition = self.0;
+        if transition.from != Some(ConnectionState::Connecting) {
+            return;
+        }
+        let mut peers = match world.get_resource_mut::<KnownPeers>() {
+            Some(some) => some,
+            None => return,
+        };
+
+        if transition.to.is_disconnected() {
+            peers.record_failure(transition.address);
+        } else {
+            peers.record_success(transition.address);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_known_peers(
+    peers_opt: Option<ResMut<KnownPeers>>,
+    rtt_query: Query<(&ConnectionAddress, &Rtt), (With<ConnectionMarker>, Changed<Rtt>)>,
+) {
+    let mut peers = match peers_opt {
+        Some(some) => some,
+        None => return,
+    };
+
+    for (addr, rtt) in rtt_query.iter() {
+        if let Some(smoothed) = rtt.smoothed() {
+            if peers
+                .get(addr.0)
+                .is_some_and(|peer| peer.rtt != Some(smoothed))
+            {
+                peers.record_rtt(addr.0, smoothed);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    if peers.dirty {
+        peers.dirty = false;
+        if let Some(path) = peers.path.as_ref() {
+            if let Err(error) = peers.save(path) {
+                warn!(message = "failed to save known peers", path = %path.display(), %error);
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use laminar::{ErrorKind, Packet, SocketEvent};
+
+use crate::{PollInterval, Socket, Transport};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Determines where sockets are polled, see [`NetworkPlugin`](crate::NetworkPlugin).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PollingMode {
+    /// Sockets are polled by a system within the frame, every [`PollInterval`].
+    #[default]
+    Frame,
+    /// Each socket is moved to a dedicated thread once spawned and polled there every
+    /// [`PollInterval`], as read when the socket is spawned. Packets and events are exchanged
+    /// over channels, so a burst of traffic cannot stall the frame. Events are bridged without
+    /// bound unless the socket has an [`EventBridge`].
+    ///
+    /// Polling threads use the system clock rather than the [`NetworkClock`](crate::NetworkClock).
+    /// Sockets whose [`Transport`] provides no [`Transport::channels`] are still polled within
+    /// the frame.
+    Threaded,
+}
+
+/// What a bounded [`EventBridge`] does with events once full.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the newest events, keeping those already waiting.
+    #[default]
+    DropNewest,
+    /// Drop the oldest waiting events to make room for the newest.
+    DropOldest,
+    /// Stop polling the socket until there is room, leaving packets in the operating system's
+    /// receive buffer. Sends are held back too, as laminar sends while polling.
+    Backpressure,
+}
+
+/// A [`Component`] which, when present on a socket entity as it is spawned, configures the
+/// channel bridging its events from the polling thread to the frame, see
+/// [`PollingMode::Threaded`].
+///
+/// Bridging is unbounded by default, which only moves a backlog off-thread should the frame fall
+/// behind. A bounded bridge caps the events waiting, applying its [`OverflowPolicy`] once full.
+/// Sockets bridged by a thread are given [`EventBridgeStats`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct EventBridge {
+    capacity: Option<usize>,
+    policy: OverflowPolicy,
+}
+
+impl EventBridge {
+    /// Creates an unbounded [`EventBridge`].
+    pub fn unbounded() -> Self {
+        Self::default()
+    }
+
+    /// Creates an [`EventBridge`] holding at most `capacity` events, at least one, dropping the
+    /// newest once full.
+    pub fn bounded(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity.max(1)),
+            policy: OverflowPolicy::default(),
+        }
+    }
+
+    /// Sets the [`OverflowPolicy`], which only applies to bounded bridges.
+    pub fn with_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the maximum number of events waiting, if bounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Returns the [`OverflowPolicy`].
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+}
+
+/// A [`Component`] counting the traffic over the [`EventBridge`] of a socket polled by a thread.
+///
+/// Updated every frame before events are received.
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct EventBridgeStats {
+    /// The number of events waiting to be received.
+    pub queued: usize,
+    /// The largest number of events seen waiting.
+    pub peak_queued: usize,
+    /// The number of events dropped by the [`OverflowPolicy`].
+    pub dropped: u64,
+    /// The number of polls skipped under [`OverflowPolicy::Backpressure`].
+    pub stalled_polls: u64,
+}
+
+#[derive(Debug, Default)]
+struct BridgeCounters {
+    peak_queued: AtomicUsize,
+    dropped: AtomicU64,
+    stalled_polls: AtomicU64,
+}
+
+/// Moves events from laminar onto a bounded channel on the polling thread.
+struct Bridge {
+    laminar_receiver: Receiver<SocketEvent>,
+    sender: Sender<SocketEvent>,
+    receiver: Receiver<SocketEvent>,
+    policy: OverflowPolicy,
+    held: Option<SocketEvent>,
+    counters: Arc<BridgeCounters>,
+}
+
+impl Bridge {
+    /// Forwards the events received from laminar, returning `false` if some were held back.
+    fn forward(&mut self) -> bool {
+        loop {
+            let event = match self
+                .held
+                .take()
+                .or_else(|| self.laminar_receiver.try_recv().ok())
+            {
+                Some(some) => some,
+                None => return true,
+            };
+
+            match self.sender.try_send(event) {
+                Ok(()) => {
+                    self.counters
+                        .peak_queued
+                        .fetch_max(self.sender.len(), Ordering::Relaxed);
+                }
+                Err(TrySendError::Full(event)) => match self.policy {
+                    OverflowPolicy::DropNewest => {
+                        self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    OverflowPolicy::DropOldest => {
+                        if self.receiver.try_recv().is_ok() {
+                            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        self.held = Some(event);
+                    }
+                    OverflowPolicy::Backpressure => {
+                        self.held = Some(event);
+                        return false;
+                    }
+                },
+                Err(TrySendError::Disconnected(_)) => return true,
+            }
+        }
+    }
+}
+
+/// A thread polling the [`Transport`] of a socket, stopped and joined on drop.
+#[derive(Debug)]
+pub(crate) struct PollingThread {
+    packet_sender: Sender<Packet>,
+    event_receiver: Receiver<SocketEvent>,
+    local_addr: Option<SocketAddr>,
+    counters: Arc<BridgeCounters>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PollingThread {
+    pub(crate) fn spawn(
+        mut transport: Box<dyn Transport>,
+        (packet_sender, laminar_receiver): (Sender<Packet>, Receiver<SocketEvent>),
+        poll_interval: Duration,
+        bridge: EventBridge,
+    ) -> Self {
+        let local_addr = transport.local_addr();
+        let counters = Arc::new(BridgeCounters::default());
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        // Unbounded bridging needs no forwarding, receive from laminar directly
+        let (event_receiver, mut bridge_opt) = match bridge.capacity {
+            Some(capacity) => {
+                let (sender, receiver) = crossbeam_channel::bounded(capacity);
+                let bridge = Bridge {
+                    laminar_receiver,
+                    sender,
+                    receiver: receiver.clone(),
+                    policy: bridge.policy,
+                    held: None,
+                    counters: counters.clone(),
+                };
+                (receiver, Some(bridge))
+            }
+            None => (laminar_receiver, None),
+        };
+
+        let thread_shutdown = shutdown.clone();
+        let thread_counters = counters.clone();
+        let handle = thread::spawn(move || loop {
+            // Poll once more after shutdown is requested, flushing the last sends
+            let stop = thread_shutdown.load(Ordering::Acquire);
+            let room = bridge_opt.as_mut().is_none_or(Bridge::forward);
+            if room || stop {
+                transport.poll(Instant::now());
+                if let Some(bridge) = bridge_opt.as_mut() {
+                    bridge.forward();
+                }
+            } else {
+                thread_counters
+                    .stalled_polls
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            if stop {
+                break;
+            }
+            thread::sleep(poll_interval);
+        });
+
+        Self {
+            packet_sender,
+            event_receiver,
+            local_addr,
+            counters,
+            shutdown,
+            handle: Some(handle),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> EventBridgeStats {
+        let queued = self.event_receiver.len();
+        EventBridgeStats {
+            queued,
+            peak_queued: self
+                .counters
+                .peak_queued
+                .fetch_max(queued, Ordering::Relaxed)
+                .max(queued),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            stalled_polls: self.counters.stalled_polls.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn send(&self, packet: Packet) -> laminar::Result<()> {
+        self.packet_sender.send(packet).map_err(|_| {
+            ErrorKind::IOError(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "polling thread stopped",
+            ))
+        })
+    }
+
+    pub(crate) fn recv(&self) -> Option<SocketEvent> {
+        self.event_receiver.try_recv().ok()
+    }
+
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
+
+impl Drop for PollingThread {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error!(message = "polling thread panicked");
+            }
+        }
+    }
+}
+
+pub(crate) fn start_polling_threads(
+    mode: Res<PollingMode>,
+    mut query: Query<(Entity, &mut Socket, &PollInterval, Option<&EventBridge>), Added<Socket>>,
+    mut commands: Commands,
+) {
+    if *mode != PollingMode::Threaded {
+        return;
+    }
+
+    for (socket_id, mut socket, poll_interval, bridge_opt) in query.iter_mut() {
+        if socket.start_thread(poll_interval.0, bridge_opt.copied().unwrap_or_default()) {
+            commands
+                .entity(socket_id)
+                .insert(EventBridgeStats::default());
+        }
+    }
+}
+
+pub(crate) fn update_bridge_stats(mut query: Query<(&Socket, &mut EventBridgeStats)>) {
+    for (socket, mut stats) in query.iter_mut() {
+        if let Some(new_stats) = socket.bridge_stats() {
+            // Avoid flagging the stats as changed while idle
+            if *stats != new_stats {
+                *stats = new_stats;
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::{
+    ecs::system::{Command, CommandQueue, EntityCommands, SystemParam},
+    prelude::*,
+};
+
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+#[cfg(feature = "encryption")]
+use crate::encryption::{BoundKey, KeyExchange, SessionKey};
+use crate::{
+    handshake::Handshake, limit::SpawnOrder, ClientId, ConnectionMarker, ConnectionSendQueue,
+    HostId, NetworkStats, ReceiveQueue, SessionDeadline,
+};
+#[cfg(feature = "serde")]
+use crate::{AcceptSettings, ChannelMask, InterpolationDelay};
+
+type ResetFn = dyn Fn(&mut EntityCommands) + Send + Sync + 'static;
+
+/// A resource which, when present, keeps despawned connection entities aside to be reused by
+/// the next connections sockets spawn, rather than spawning fresh entities.
+///
+/// Under heavy churn, such as from scanners or reconnect storms, this spares spawning and
+/// despawning entities along with their components. Connections reaped by a
+/// [`DespawnPolicy`](crate::DespawnPolicy), evicted by a [`ConnectionLimit`](crate::ConnectionLimit),
+/// despawned by [`Disconnect::despawn`](crate::Disconnect::despawn) or closed along with their
+/// socket are released to the pool while it has room. Released entities lose their
+/// [`ConnectionMarker`], and with it their place in every connection query, as well as the
+/// components the plugin inserted. Components inserted by other means are kept unless removed by
+/// [`ConnectionPool::with_reset`]. Connections opened with [`Connect`](crate::Connect) are always
+/// spawned fresh.
+///
+/// Unlike a despawned entity, a reused entity is the same [`Entity`] as the connection it
+/// replaces, so any held onto after a connection is despawned may come to refer to another peer.
+pub struct ConnectionPool {
+    capacity: usize,
+    reset: Option<Box<ResetFn>>,
+    entities: Vec<Entity>,
+}
+
+impl std::fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("capacity", &self.capacity)
+            .field("entities", &self.entities)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConnectionPool {
+    /// Creates a new [`ConnectionPool`] keeping at most `capacity` entities aside.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            reset: None,
+            entities: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Runs `reset` on each entity released to the pool, to remove the components inserted by
+    /// the application, for example by a [`ConnectionBuilder`](crate::ConnectionBuilder).
+    pub fn with_reset<F>(mut self, reset: F) -> Self
+    where
+        F: Fn(&mut EntityCommands) + Send + Sync + 'static,
+    {
+        self.reset = Some(Box::new(reset));
+        self
+    }
+
+    /// Returns the maximum number of entities kept aside.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entities waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Returns `true` if no entity is waiting to be reused.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A marker [`Component`] for entities waiting in the [`ConnectionPool`].
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub(crate) struct PooledConnection;
+
+/// Despawns a connection, or releases it to the [`ConnectionPool`] should there be room.
+pub(crate) fn despawn_connection(world: &mut World, connection: Entity) {
+    match world.get_entity(connection) {
+        Some(entity) if !entity.contains::<PooledConnection>() => {}
+        _ => return,
+    }
+
+    let released = match world.get_resource_mut::<ConnectionPool>() {
+        Some(mut pool) if pool.entities.len() < pool.capacity => {
+            pool.entities.push(connection);
+            true
+        }
+        _ => false,
+    };
+    if !released {
+        world.despawn(connection);
+        return;
+    }
+
+    trace!(message = "releasing connection to pool", ?connection);
+
+    world.entity_mut(connection).remove_bundle_intersection::<(
+        ConnectionMarker,
+        SessionDeadline,
+        HostId,
+        Handshake,
+        ClientId,
+        SpawnOrder,
+        NetworkStats,
+    )>();
+    #[cfg(feature = "serde")]
+    world
+        .entity_mut(connection)
+        .remove_bundle_intersection::<(AcceptSettings, InterpolationDelay, ChannelMask)>();
+    #[cfg(feature = "encryption")]
+    world
+        .entity_mut(connection)
+        .remove_bundle_intersection::<(KeyExchange, SessionKey, BoundKey)>();
+    #[cfg(feature = "compression")]
+    world.entity_mut(connection).remove::<Compression>();
+    world.entity_mut(connection).insert_bundle((
+        PooledConnection,
+        ReceiveQueue::default(),
+        ConnectionSendQueue::default(),
+    ));
+
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    if let Some(reset) = world
+        .get_resource::<ConnectionPool>()
+        .and_then(|pool| pool.reset.as_ref())
+    {
+        reset(&mut commands.entity(connection));
+    }
+    queue.apply(world);
+}
+
+/// A [`Command`] despawning a connection, see [`despawn_connection`].
+pub(crate) struct DespawnConnection(pub(crate) Entity);
+
+impl Command for DespawnConnection {
+    fn write(self, world: &mut World) {
+        despawn_connection(world, self.0);
+    }
+}
+
+/// Spawns connections, reusing entities from the [`ConnectionPool`] when present.
+#[derive(SystemParam)]
+pub(crate) struct ConnectionSpawner<'w, 's> {
+    pool_opt: Option<ResMut<'w, ConnectionPool>>,
+    pooled_query: Query<'w, 's, (), With<PooledConnection>>,
+}
+
+impl ConnectionSpawner<'_, '_> {
+    pub(crate) fn spawn_bundle<'w, 's, 'a, B>(
+        &mut self,
+        commands: &'a mut Commands<'w, 's>,
+        bundle: B,
+    ) -> EntityCommands<'w, 's, 'a>
+    where
+        B: Bundle,
+    {
+        // Entities may have been despawned while waiting
+        let pooled_query = &self.pooled_query;
+        let reused = self.pool_opt.as_mut().and_then(|pool| {
+            std::iter::from_fn(|| pool.entities.pop())
+                .find(|entity| pooled_query.get(*entity).is_ok())
+        });
+
+        match reused {
+            Some(entity) => {
+                trace!(message = "reusing pooled connection", ?entity);
+                let mut entity_commands = commands.entity(entity);
+                entity_commands
+                    .remove::<PooledConnection>()
+                    .insert_bundle(bundle);
+                entity_commands
+            }
+            None => commands.spawn_bundle(bundle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    #[test]
+    fn reused_connection_loses_client_id() {
+        let mut world = World::new();
+        world.insert_resource(ConnectionPool::new(1));
+        let authenticated = world
+            .spawn()
+            .insert_bundle((ConnectionMarker, ClientId(7), NetworkStats::default()))
+            .id();
+
+        despawn_connection(&mut world, authenticated);
+        let pooled = world
+            .get_resource::<ConnectionPool>()
+            .map(ConnectionPool::len);
+        assert_eq!(pooled, Some(1));
+
+        // A peer accepted without an `Authenticator` is spawned with no `ClientId`
+        let mut state = SystemState::<(Commands, ConnectionSpawner)>::new(&mut world);
+        let (mut commands, mut spawner) = state.get_mut(&mut world);
+        let unauthenticated = spawner
+            .spawn_bundle(&mut commands, (ConnectionMarker,))
+            .id();
+        state.apply(&mut world);
+
+        assert_eq!(unauthenticated, authenticated);
+        let entity = world.entity(unauthenticated);
+        assert!(entity.contains::<ConnectionMarker>());
+        assert!(!entity.contains::<PooledConnection>());
+        assert!(!entity.contains::<ClientId>());
+        assert!(!entity.contains::<NetworkStats>());
+    }
+}
@@ -0,0 +1,551 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashSet,
+};
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    entity_map::{assign_network_ids, release_network_ids},
+    wire::REPLICATION_TAG,
+    Config, ConnectionMarker, ConnectionSendQueue, ConnectionState, Delivery, NetworkEntityMap,
+    NetworkId, NetworkStage, ReceiveQueue, SocketConfig, SocketId, SocketMarker,
+};
+
+/// Room left in each payload below laminar's maximum packet size, for the tag and operation count
+/// and for the layers wrapping payloads, such as [`HostId`](crate::HostId)s and encryption.
+const HEADER_LEN: usize = 128;
+
+/// Replication is sent reliably on the default stream, so that spawns precede updates.
+const REPLICATION_DELIVERY: Delivery = Delivery::ReliableOrdered(None);
+
+/// A [`Component`] marking an entity to be replicated to peers, see [`AddReplication`].
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct Replicated;
+
+/// A [`Component`] which, when present on a connection entity, or on a socket entity for all of
+/// its connections, replicates [`Replicated`] entities to the peer.
+///
+/// Once a connection becomes a target, which requires it not to be disconnected, every replicated
+/// entity and its registered components are sent. Afterwards only spawns, changed or removed
+/// components and despawns are.
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct ReplicationTarget;
+
+/// A [`Component`] which, when present on a connection entity, or on a socket entity for all of
+/// its connections, applies replication sent by the peer to mirrored [`Replica`] entities.
+///
+/// Replication from other connections is dropped, so peers cannot spawn entities unless allowed.
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct ReplicationSource;
+
+/// A [`Component`] present on entities mirroring a [`Replicated`] entity of a peer.
+///
+/// Replicas are resolved with [`NetworkEntityMap::get_remote`], and despawned alongside their
+/// peer's entity, or once their connection entity is despawned.
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct Replica {
+    /// The connection entity replicating the entity.
+    pub connection: Entity,
+    /// The [`NetworkId`] the peer assigned its entity.
+    pub remote: NetworkId,
+}
+
+/// An event emitted when a replicated component is too large to be sent to a target, which
+/// leaves the peer's [`Replica`] without it.
+///
+/// Replication is split into payloads fitting the maximum packet size of the target's socket, see
+/// [`Config`], so a single component must fit one payload along with some framing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReplicationTooLarge {
+    /// The target connection entity.
+    pub connection: Entity,
+    /// The replicated entity.
+    pub entity: Entity,
+    /// The type name of the component.
+    pub component: &'static str,
+    /// The approximate encoded length of the component, in bytes.
+    pub len: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum ReplicationOp {
+    Spawn(NetworkId),
+    Insert(NetworkId, u16, Vec<u8>),
+    Remove(NetworkId, u16),
+    Despawn(NetworkId),
+}
+
+impl ReplicationOp {
+    /// Approximates the encoded length of the operation.
+    fn len(&self) -> usize {
+        match self {
+            Self::Insert(_, _, payload) => 24 + payload.len(),
+            _ => 16,
+        }
+    }
+}
+
+type InsertFn = fn(&mut EntityCommands, &[u8]) -> Result<(), bincode::Error>;
+type RemoveFn = fn(&mut EntityCommands);
+
+#[derive(Debug)]
+struct ReplicatedComponent {
+    type_id: TypeId,
+    name: &'static str,
+    insert: InsertFn,
+    remove: RemoveFn,
+}
+
+/// The components registered with [`AddReplication`], indexed by registration order.
+#[derive(Debug, Default)]
+pub(crate) struct ReplicationRegistry {
+    components: Vec<ReplicatedComponent>,
+}
+
+impl ReplicationRegistry {
+    fn index_of<C>(&self) -> Option<u16>
+    where
+        C: Component,
+    {
+        self.components
+            .iter()
+            .position(|component| component.type_id == TypeId::of::<C>())
+            .map(|index| index as u16)
+    }
+}
+
+#[derive(Debug)]
+struct ComponentUpdate {
+    id: NetworkId,
+    index: u16,
+    payload: Vec<u8>,
+    /// Whether the update is sent to every target, rather than only to fresh ones.
+    changed: bool,
+}
+
+/// The replication gathered during a tick, before it is sent.
+#[derive(Debug, Default)]
+pub(crate) struct ReplicationBuffer {
+    /// Each target connection, and whether it has yet to receive the full state.
+    targets: Vec<(Entity, bool)>,
+    updates: Vec<ComponentUpdate>,
+    removals: Vec<(NetworkId, u16)>,
+}
+
+impl ReplicationBuffer {
+    fn has_fresh_targets(&self) -> bool {
+        self.targets.iter().any(|(_, fresh)| *fresh)
+    }
+}
+
+/// Labels ordering the replication systems among themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ReplicationLabel {
+    Track,
+    Collect,
+    Send,
+}
+
+impl SystemLabel for ReplicationLabel {
+    fn dyn_clone(&self) -> Box<dyn SystemLabel> {
+        Box::new(*self)
+    }
+}
+
+/// An extension trait for [`App`] registering replicated components.
+pub trait AddReplication {
+    /// Registers `C` to be replicated on [`Replicated`] entities.
+    ///
+    /// Components are identified on the wire by registration order, so peers must register the
+    /// same components in the same order. Replication is gathered in [`NetworkStage::PreSend`],
+    /// sent to each [`ReplicationTarget`] and applied by each [`ReplicationSource`] in
+    /// [`NetworkStage::PostRecv`]. Components which fail to encode or decode are logged and
+    /// skipped, as are components too large to send, which emit a [`ReplicationTooLarge`] event.
+    fn replicate<C>(&mut self) -> &mut Self
+    where
+        C: Component + Serialize + DeserializeOwned;
+}
+
+impl AddReplication for App {
+    fn replicate<C>(&mut self) -> &mut Self
+    where
+        C: Component + Serialize + DeserializeOwned,
+    {
+        if self.world.get_resource::<ReplicationRegistry>().is_none() {
+            self.init_resource::<ReplicationRegistry>()
+                .init_resource::<ReplicationBuffer>()
+                .add_event::<ReplicationTooLarge>()
+                .init_resource::<NetworkEntityMap>()
+                .add_system_to_stage(
+                    NetworkStage::PreSend,
+                    assign_network_ids.before(ReplicationLabel::Track),
+                )
+                .add_system_to_stage(
+                    NetworkStage::PreSend,
+                    track_replication_targets.label(ReplicationLabel::Track),
+                )
+                .add_system_to_stage(
+                    NetworkStage::PreSend,
+                    send_replication
+                        .label(ReplicationLabel::Send)
+                        .after(ReplicationLabel::Collect),
+                )
+                .add_system_to_stage(
+                    NetworkStage::PreSend,
+                    release_network_ids.after(ReplicationLabel::Send),
+                )
+                .add_system_to_stage(NetworkStage::PostRecv, apply_replication);
+        }
+
+        let mut registry = self
+            .world
+            .get_resource_or_insert_with(ReplicationRegistry::default);
+        if registry.index_of::<C>().is_some() {
+            return self;
+        }
+        registry.components.push(ReplicatedComponent {
+            type_id: TypeId::of::<C>(),
+            name: type_name::<C>(),
+            insert: insert_component::<C>,
+            remove: remove_component::<C>,
+        });
+
+        self.add_system_to_stage(
+            NetworkStage::PreSend,
+            collect_replicated::<C>
+                .label(ReplicationLabel::Collect)
+                .after(ReplicationLabel::Track),
+        )
+    }
+}
+
+fn insert_component<C>(commands: &mut EntityCommands, payload: &[u8]) -> Result<(), bincode::Error>
+where
+    C: Component + DeserializeOwned,
+{
+    commands.insert(bincode::deserialize::<C>(payload)?);
+    Ok(())
+}
+
+fn remove_component<C>(commands: &mut EntityCommands)
+where
+    C: Component,
+{
+    commands.remove::<C>();
+}
+
+#[allow(clippy::type_complexity)]
+fn track_replication_targets(
+    mut known: Local<HashSet<Entity>>,
+    mut buffer: ResMut<ReplicationBuffer>,
+    connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionState,
+            Option<&ReplicationTarget>,
+        ),
+        With<ConnectionMarker>,
+    >,
+    target_query: Query<&ReplicationTarget, With<SocketMarker>>,
+) {
+    buffer.targets.clear();
+    buffer.updates.clear();
+    buffer.removals.clear();
+
+    let targets: HashSet<Entity> = connection_query
+        .iter()
+        .filter(|(_, socket_id, state, target_opt)| {
+            !state.is_disconnected()
+                && (target_opt.is_some() || target_query.get(socket_id.0).is_ok())
+        })
+        .map(|(connection, _, _, _)| connection)
+        .collect();
+
+    buffer.targets = targets
+        .iter()
+        .map(|connection| (*connection, !known.contains(connection)))
+        .collect();
+    *known = targets;
+}
+
+#[allow(clippy::type_complexity)]
+fn collect_replicated<C>(
+    registry: Res<ReplicationRegistry>,
+    map: Res<NetworkEntityMap>,
+    mut buffer: ResMut<ReplicationBuffer>,
+    component_query: Query<
+        (Entity, &C, ChangeTrackers<C>, ChangeTrackers<Replicated>),
+        With<Replicated>,
+    >,
+    replicated_query: Query<(), With<Replicated>>,
+    removed: RemovedComponents<C>,
+) where
+    C: Component + Serialize,
+{
+    if buffer.targets.is_empty() {
+        return;
+    }
+    let index = match registry.index_of::<C>() {
+        Some(index) => index,
+        None => return,
+    };
+    let fresh = buffer.has_fresh_targets();
+
+    for (entity, component, component_tracker, replicated_tracker) in component_query.iter() {
+        let changed = component_tracker.is_changed() || replicated_tracker.is_added();
+        let id = match map.id_of(entity) {
+            Some(id) if changed || fresh => id,
+            _ => continue,
+        };
+
+        match bincode::serialize(component) {
+            Ok(payload) => buffer.updates.push(ComponentUpdate {
+                id,
+                index,
+                payload,
+                changed,
+            }),
+            Err(error) => {
+                warn!(message = "failed to encode component", component = type_name::<C>(), ?entity, %error);
+            }
+        }
+    }
+
+    for entity in removed.iter() {
+        if let (Ok(_), Some(id)) = (replicated_query.get(entity), map.id_of(entity)) {
+            buffer.removals.push((id, index));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_replication(
+    registry: Res<ReplicationRegistry>,
+    buffer: Res<ReplicationBuffer>,
+    map: Res<NetworkEntityMap>,
+    replicated_query: Query<(Entity, ChangeTrackers<Replicated>)>,
+    removed: RemovedComponents<Replicated>,
+    mut queue_query: Query<(&mut ConnectionSendQueue, &SocketId)>,
+    config_query: Query<&SocketConfig>,
+    mut too_large_writer: EventWriter<ReplicationTooLarge>,
+) {
+    if buffer.targets.is_empty() {
+        return;
+    }
+
+    let despawned: Vec<NetworkId> = removed
+        .iter()
+        .filter(|entity| replicated_query.get(*entity).is_err())
+        .filter_map(|entity| map.id_of(entity))
+        .collect();
+
+    for (connection, fresh) in buffer.targets.iter().copied() {
+        let (mut queue, socket_id) = match queue_query.get_mut(connection) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        let max_packet_size = config_query
+            .get(socket_id.0)
+            .map(|config| config.0.max_packet_size)
+            .unwrap_or_else(|_| Config::default().max_packet_size);
+        let max_len = max_packet_size.saturating_sub(HEADER_LEN);
+
+        let mut ops = Vec::new();
+        for (entity, tracker) in replicated_query.iter() {
+            if let (true, Some(id)) = (fresh || tracker.is_added(), map.id_of(entity)) {
+                ops.push(ReplicationOp::Spawn(id));
+            }
+        }
+        for update in buffer.updates.iter() {
+            if fresh || update.changed {
+                ops.push(ReplicationOp::Insert(
+                    update.id,
+                    update.index,
+                    update.payload.clone(),
+                ));
+            }
+        }
+        if !fresh {
+            for (id, index) in buffer.removals.iter() {
+                ops.push(ReplicationOp::Remove(*id, *index));
+            }
+            for id in despawned.iter() {
+                ops.push(ReplicationOp::Despawn(*id));
+            }
+        }
+        if ops.is_empty() {
+            continue;
+        }
+
+        trace!(message = "replicating", ?connection, ops = ops.len(), fresh);
+
+        let mut chunk = Vec::new();
+        let mut chunk_len = 0;
+        for op in ops {
+            let op_len = op.len();
+            if op_len > max_len {
+                // Only insertions carry a payload which can outgrow a packet
+                if let ReplicationOp::Insert(id, index, _) = op {
+                    let component = registry
+                        .components
+                        .get(usize::from(index))
+                        .map_or("unknown", |component| component.name);
+                    warn!(
+                        message = "skipping component too large to replicate",
+                        ?connection,
+                        component,
+                        len = op_len
+                    );
+                    if let Some(entity) = map.get(id) {
+                        too_large_writer.send(ReplicationTooLarge {
+                            connection,
+                            entity,
+                            component,
+                            len: op_len,
+                        });
+                    }
+                }
+                continue;
+            }
+            // Send the chunk before it outgrows a payload
+            if chunk_len + op_len > max_len {
+                queue.send(REPLICATION_DELIVERY, encode(&chunk));
+                chunk.clear();
+                chunk_len = 0;
+            }
+            chunk_len += op_len;
+            chunk.push(op);
+        }
+        if !chunk.is_empty() {
+            queue.send(REPLICATION_DELIVERY, encode(&chunk));
+        }
+    }
+}
+
+pub(crate) fn encode(ops: &[ReplicationOp]) -> Vec<u8> {
+    let mut payload = REPLICATION_TAG.to_vec();
+    payload.extend(bincode::serialize(ops).expect("replication operations always encode"));
+    payload
+}
+
+fn decode(payload: &[u8]) -> Option<Result<Vec<ReplicationOp>, bincode::Error>> {
+    let rest = payload.strip_prefix(&REPLICATION_TAG)?;
+    Some(bincode::deserialize(rest))
+}
+
+/// Decodes replication operations and encodes them again, see [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    decode(payload)?.ok().map(|ops| encode(&ops))
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_replication(
+    registry: Res<ReplicationRegistry>,
+    mut map: ResMut<NetworkEntityMap>,
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &mut ReceiveQueue,
+            Option<&ReplicationSource>,
+        ),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+    source_query: Query<&ReplicationSource, With<SocketMarker>>,
+    removed: RemovedComponents<ConnectionMarker>,
+    mut commands: Commands,
+) {
+    for connection in removed.iter() {
+        for replica in map.drain_connection(connection) {
+            commands.entity(replica).despawn();
+        }
+    }
+
+    for (connection, socket_id, mut queue, source_opt) in connection_query.iter_mut() {
+        // Avoid flagging the queue as changed unless there is replication to remove
+        if !queue
+            .iter()
+            .any(|packet| packet.payload().starts_with(&REPLICATION_TAG))
+        {
+            continue;
+        }
+        let is_source = source_opt.is_some() || source_query.get(socket_id.0).is_ok();
+
+        let mut ops = Vec::new();
+        queue.0.retain(|packet| match decode(packet.payload()) {
+            None => true,
+            Some(Ok(decoded)) => {
+                if is_source {
+                    ops.extend(decoded);
+                } else {
+                    debug!(
+                        message = "dropping replication from non-source",
+                        ?connection
+                    );
+                }
+                false
+            }
+            Some(Err(error)) => {
+                warn!(message = "failed to decode replication", ?connection, %error);
+                false
+            }
+        });
+
+        for op in ops {
+            match op {
+                ReplicationOp::Spawn(remote) => {
+                    if map.get_remote(connection, remote).is_none() {
+                        let replica = commands.spawn().insert(Replica { connection, remote }).id();
+                        map.insert_remote(connection, remote, replica);
+                    }
+                }
+                ReplicationOp::Insert(remote, index, payload) => {
+                    let (replica, component) =
+                        match lookup(&map, &registry, connection, remote, index) {
+                            Some(found) => found,
+                            None => continue,
+                        };
+                    if let Err(error) = (component.insert)(&mut commands.entity(replica), &payload)
+                    {
+                        warn!(message = "failed to decode component", component = component.name, ?connection, %error);
+                    }
+                }
+                ReplicationOp::Remove(remote, index) => {
+                    if let Some((replica, component)) =
+                        lookup(&map, &registry, connection, remote, index)
+                    {
+                        (component.remove)(&mut commands.entity(replica));
+                    }
+                }
+                ReplicationOp::Despawn(remote) => {
+                    if let Some(replica) = map.remove_remote(connection, remote) {
+                        commands.entity(replica).despawn();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Finds the replica of a remote entity and the registered component at `index`.
+fn lookup<'a>(
+    map: &NetworkEntityMap,
+    registry: &'a ReplicationRegistry,
+    connection: Entity,
+    remote: NetworkId,
+    index: u16,
+) -> Option<(Entity, &'a ReplicatedComponent)> {
+    let replica = map.get_remote(connection, remote)?;
+    match registry.components.get(index as usize) {
+        Some(component) => Some((replica, component)),
+        None => {
+            warn!(message = "unknown replicated component", index, ?connection);
+            None
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    decode_message, encode_message, wire::RPC_TAG, ConnectionMarker, ConnectionSendQueue, Delivery,
+    InternalLabel, InternalStage, NetworkClock, NetworkMessage, NetworkStage, ReceiveQueue,
+};
+
+const HEADER_LEN: usize = RPC_TAG.len() + 1 + 4;
+
+pub(crate) const REQUEST_KIND: u8 = 0;
+pub(crate) const RESPONSE_KIND: u8 = 1;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Calls are sent reliably, their responses being matched by [`CallId`] rather than by order.
+const RPC_DELIVERY: Delivery = Delivery::ReliableUnordered;
+
+/// Identifies a call made with [`Rpc::send`], correlating it with its response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallId(pub(crate) u32);
+
+impl CallId {
+    /// Returns the raw id.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+struct PendingCall {
+    connection: Entity,
+    request_id: u16,
+    timeout: Duration,
+    deadline: Option<Instant>,
+}
+
+/// A resource making remote calls to peers, and responding to theirs, see [`AddRpc`].
+///
+/// Calls and responses are queued here and handed to the connections' [`ConnectionSendQueue`]s
+/// before packets are flushed. A call which has not been responded to once its timeout elapses,
+/// counted from when it is flushed, emits an [`RpcTimedOut`] event, and a late response is dropped.
+#[derive(Debug)]
+pub struct Rpc {
+    next_call: u32,
+    outbox: Vec<(Entity, Vec<u8>)>,
+    pending: HashMap<CallId, PendingCall>,
+}
+
+impl Default for Rpc {
+    fn default() -> Self {
+        Self {
+            next_call: 1,
+            outbox: Vec::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl Rpc {
+    /// Calls the peer of `connection`, timing out after 5 seconds.
+    pub fn send<Req>(&mut self, connection: Entity, request: &Req) -> Result<CallId, bincode::Error>
+    where
+        Req: NetworkMessage,
+    {
+        self.send_with_timeout(connection, request, DEFAULT_TIMEOUT)
+    }
+
+    /// Calls the peer of `connection`, timing out after `timeout`.
+    pub fn send_with_timeout<Req>(
+        &mut self,
+        connection: Entity,
+        request: &Req,
+        timeout: Duration,
+    ) -> Result<CallId, bincode::Error>
+    where
+        Req: NetworkMessage,
+    {
+        let call = CallId(self.next_call);
+        let frame = encode_frame(REQUEST_KIND, call, request)?;
+        self.next_call = self.next_call.wrapping_add(1).max(1);
+
+        self.outbox.push((connection, frame));
+        self.pending.insert(
+            call,
+            PendingCall {
+                connection,
+                request_id: Req::ID,
+                timeout,
+                deadline: None,
+            },
+        );
+        Ok(call)
+    }
+
+    /// Responds to the call `call` received on `connection`, see [`RpcRequest`].
+    pub fn respond<Resp>(
+        &mut self,
+        connection: Entity,
+        call: CallId,
+        response: &Resp,
+    ) -> Result<(), bincode::Error>
+    where
+        Resp: NetworkMessage,
+    {
+        let frame = encode_frame(RESPONSE_KIND, call, response)?;
+        self.outbox.push((connection, frame));
+        Ok(())
+    }
+
+    /// Returns `true` if `call` is awaiting its response.
+    pub fn is_pending(&self, call: CallId) -> bool {
+        self.pending.contains_key(&call)
+    }
+
+    /// Stops awaiting the response to `call`, returning `true` if it was pending.
+    pub fn cancel(&mut self, call: CallId) -> bool {
+        self.pending.remove(&call).is_some()
+    }
+}
+
+pub(crate) fn encode_frame<M>(
+    kind: u8,
+    call: CallId,
+    message: &M,
+) -> Result<Vec<u8>, bincode::Error>
+where
+    M: NetworkMessage,
+{
+    let mut frame = Vec::with_capacity(HEADER_LEN);
+    frame.extend_from_slice(&RPC_TAG);
+    frame.push(kind);
+    frame.extend_from_slice(&call.0.to_be_bytes());
+    frame.extend(encode_message(message)?);
+    Ok(frame)
+}
+
+/// Parses the kind, call and encoded message of a frame.
+fn parse_frame(payload: &[u8]) -> Option<(u8, CallId, &[u8])> {
+    if payload.len() < HEADER_LEN || payload[..RPC_TAG.len()] != RPC_TAG {
+        return None;
+    }
+    let kind = payload[RPC_TAG.len()];
+    let call = u32::from_be_bytes([payload[5], payload[6], payload[7], payload[8]]);
+    Some((kind, CallId(call), &payload[HEADER_LEN..]))
+}
+
+/// Parses the header of a frame and builds it again over the same message, see
+/// [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    let (kind, call, message) = parse_frame(payload)?;
+    if kind != REQUEST_KIND && kind != RESPONSE_KIND {
+        return None;
+    }
+    let mut frame = Vec::with_capacity(payload.len());
+    frame.extend_from_slice(&RPC_TAG);
+    frame.push(kind);
+    frame.extend_from_slice(&call.0.to_be_bytes());
+    frame.extend_from_slice(message);
+    Some(frame)
+}
+
+/// An event emitted when a peer calls with a request of type `Req`.
+///
+/// Respond with [`Rpc::respond`], passing the connection and call.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RpcRequest<Req> {
+    /// The connection entity which received the call.
+    pub connection: Entity,
+    /// The call, to be passed to [`Rpc::respond`].
+    pub call: CallId,
+    /// The decoded request.
+    pub request: Req,
+}
+
+/// An event emitted when a peer responds to a call made with [`Rpc::send`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RpcResponse<Resp> {
+    /// The connection entity the call was made on.
+    pub connection: Entity,
+    /// The call responded to.
+    pub call: CallId,
+    /// The decoded response.
+    pub response: Resp,
+}
+
+/// An event emitted when a call made with [`Rpc::send`] is not responded to in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RpcTimedOut {
+    /// The connection entity the call was made on.
+    pub connection: Entity,
+    /// The call which timed out.
+    pub call: CallId,
+}
+
+/// An extension trait for [`App`] registering remote calls.
+pub trait AddRpc {
+    /// Registers calls with a request of type `Req` responded to with a `Resp`.
+    ///
+    /// Requests are received as [`RpcRequest<Req>`] events and responses as [`RpcResponse<Resp>`]
+    /// events, both in [`NetworkStage::PostRecv`]. The message ids of `Req` and `Resp` are used on
+    /// the wire, as with [`AddNetworkMessage`](crate::AddNetworkMessage), and calls do not reach
+    /// the `MessageReceived` events of those types. Frames which fail to decode, and frames of
+    /// calls of unregistered types, are logged and dropped.
+    fn add_rpc<Req, Resp>(&mut self) -> &mut Self
+    where
+        Req: NetworkMessage,
+        Resp: NetworkMessage;
+}
+
+impl AddRpc for App {
+    fn add_rpc<Req, Resp>(&mut self) -> &mut Self
+    where
+        Req: NetworkMessage,
+        Resp: NetworkMessage,
+    {
+        if self.world.get_resource::<Rpc>().is_none() {
+            self.init_resource::<Rpc>()
+                .add_event::<RpcTimedOut>()
+                .add_system_to_stage(
+                    InternalStage::Send,
+                    drive_rpc_calls.before(InternalLabel::Forward),
+                )
+                .add_system_to_stage(
+                    NetworkStage::PostRecv,
+                    drop_unclaimed_rpc.after(InternalLabel::Rpc),
+                );
+        }
+
+        self.add_event::<RpcRequest<Req>>()
+            .add_event::<RpcResponse<Resp>>()
+            .add_system_to_stage(
+                NetworkStage::PostRecv,
+                receive_rpc::<Req, Resp>.label(InternalLabel::Rpc),
+            )
+    }
+}
+
+fn drive_rpc_calls(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut rpc: ResMut<Rpc>,
+    mut queue_query: Query<&mut ConnectionSendQueue>,
+    mut timed_out_writer: EventWriter<RpcTimedOut>,
+) {
+    let rpc = &mut *rpc;
+    for (connection, frame) in rpc.outbox.drain(..) {
+        match queue_query.get_mut(connection) {
+            Ok(mut queue) => queue.send(RPC_DELIVERY, frame),
+            Err(_) => debug!(message = "dropping call to missing connection", ?connection),
+        }
+    }
+
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    rpc.pending.retain(|call, pending| {
+        let deadline = *pending.deadline.get_or_insert(now + pending.timeout);
+        if deadline > now {
+            return true;
+        }
+
+        trace!(message = "call timed out", ?call, connection = ?pending.connection);
+        timed_out_writer.send(RpcTimedOut {
+            connection: pending.connection,
+            call: *call,
+        });
+        false
+    });
+}
+
+#[allow(clippy::type_complexity)]
+fn receive_rpc<Req, Resp>(
+    mut rpc: ResMut<Rpc>,
+    mut connection_query: Query<
+        (Entity, &mut ReceiveQueue),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+    mut request_writer: EventWriter<RpcRequest<Req>>,
+    mut response_writer: EventWriter<RpcResponse<Resp>>,
+) where
+    Req: NetworkMessage,
+    Resp: NetworkMessage,
+{
+    for (connection, mut queue) in connection_query.iter_mut() {
+        // Avoid flagging the queue as changed unless there are frames to remove
+        if !queue
+            .iter()
+            .any(|packet| parse_frame(packet.payload()).is_some())
+        {
+            continue;
+        }
+
+        queue.0.retain(|packet| {
+            let (kind, call, message) = match parse_frame(packet.payload()) {
+                Some(frame) => frame,
+                None => return true,
+            };
+
+            match kind {
+                REQUEST_KIND => match decode_message::<Req>(message) {
+                    None => true,
+                    Some(Ok(request)) => {
+                        request_writer.send(RpcRequest {
+                            connection,
+                            call,
+                            request,
+                        });
+                        false
+                    }
+                    Some(Err(error)) => {
+                        warn!(message = "failed to decode request", id = Req::ID, ?connection, %error);
+                        false
+                    }
+                },
+                RESPONSE_KIND => {
+                    match rpc.pending.get(&call) {
+                        Some(pending) if pending.connection != connection => {
+                            warn!(message = "response on the wrong connection", ?call, ?connection);
+                            return false;
+                        }
+                        // Awaited by the registration of another request type
+                        Some(pending) if pending.request_id != Req::ID => return true,
+                        Some(_) => {}
+                        None => {
+                            debug!(message = "dropping late response", ?call, ?connection);
+                            return false;
+                        }
+                    }
+                    match decode_message::<Resp>(message) {
+                        None => true,
+                        Some(Ok(response)) => {
+                            rpc.pending.remove(&call);
+                            response_writer.send(RpcResponse {
+                                connection,
+                                call,
+                                response,
+                            });
+                            false
+                        }
+                        Some(Err(error)) => {
+                            warn!(message = "failed to decode response", id = Resp::ID, ?connection, %error);
+                            rpc.pending.remove(&call);
+                            false
+                        }
+                    }
+                }
+                _ => {
+                    warn!(message = "unknown call frame", kind, ?connection);
+                    false
+                }
+            }
+        });
+    }
+}
+
+/// Drops the frames left by every [`receive_rpc`], which no registered type claimed.
+#[allow(clippy::type_complexity)]
+fn drop_unclaimed_rpc(
+    mut connection_query: Query<
+        (Entity, &mut ReceiveQueue),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+) {
+    for (connection, mut queue) in connection_query.iter_mut() {
+        // Avoid flagging the queue as changed unless there are frames to remove
+        if !queue
+            .iter()
+            .any(|packet| parse_frame(packet.payload()).is_some())
+        {
+            continue;
+        }
+
+        queue
+            .0
+            .retain(|packet| match parse_frame(packet.payload()) {
+                Some((kind, call, _)) => {
+                    debug!(
+                        message = "dropping call of unregistered type",
+                        kind,
+                        ?call,
+                        ?connection
+                    );
+                    false
+                }
+                None => true,
+            });
+    }
+}
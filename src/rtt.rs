@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    ConnectionAddress, ConnectionMarker, ConnectionState, NetworkClock, Probe, ReceiveQueue,
+    SendQueue, SocketId, SocketMarker,
+};
+
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of unanswered probes remembered, older probes are considered lost.
+const MAX_IN_FLIGHT: usize = 16;
+
+/// The weight of each new sample in the smoothed estimate, as in RFC 6298.
+const SMOOTHING: f64 = 0.125;
+
+/// A [`Component`] which, when present on a connection entity, measures the round trip time to
+/// the peer.
+///
+/// A [`Probe`] is sent at an interval while the connection is not disconnected, and matched
+/// against its echo, which requires the peer to have a
+/// [`DiagnosticEcho`](crate::DiagnosticEcho). Echoes are removed from the [`ReceiveQueue`].
+#[derive(Debug, Clone, Component, PartialEq, Eq)]
+pub struct Rtt {
+    probe_interval: Duration,
+    latest: Option<Duration>,
+    smoothed: Option<Duration>,
+    last_probe: Option<Instant>,
+    next_id: u32,
+    in_flight: VecDeque<(u32, Instant)>,
+}
+
+impl Default for Rtt {
+    fn default() -> Self {
+        Self {
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            latest: None,
+            smoothed: None,
+            last_probe: None,
+            next_id: 1,
+            in_flight: VecDeque::new(),
+        }
+    }
+}
+
+impl Rtt {
+    /// Sets the interval between probes.
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Returns the smoothed round trip time, or `None` until a probe has been echoed.
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+
+    /// Returns the round trip time of the latest echoed probe.
+    pub fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    fn record(&mut self, sample: Duration) {
+        self.latest = Some(sample);
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => smoothed.mul_f64(1.0 - SMOOTHING) + sample.mul_f64(SMOOTHING),
+            None => sample,
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn measure_rtt(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    mut connection_query: Query<
+        (
+            &SocketId,
+            &ConnectionAddress,
+            &ConnectionState,
+            &mut Rtt,
+            &mut ReceiveQueue,
+        ),
+        With<ConnectionMarker>,
+    >,
+    mut socket_query: Query<&mut SendQueue, With<SocketMarker>>,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (socket_id, addr, state, mut rtt, mut queue) in connection_query.iter_mut() {
+        // Avoid flagging the queue as changed unless there are echoes to remove
+        if queue
+            .iter()
+            .any(|packet| Probe::from_echo(packet).is_some())
+        {
+            queue.0.retain(|packet| {
+                let probe = if let Some(probe) = Probe::from_echo(packet) {
+                    probe
+                } else {
+                    return true;
+                };
+
+                if let Some(index) = rtt.in_flight.iter().position(|(id, _)| *id == probe.id) {
+                    let (_, sent) = rtt.in_flight[index];
+                    rtt.in_flight.drain(..=index);
+                    rtt.record(now.saturating_duration_since(sent));
+                }
+                false
+            });
+        }
+
+        let probe = rtt
+            .last_probe
+            .is_none_or(|last_probe| last_probe + rtt.probe_interval <= now);
+        if !probe || state.is_disconnected() {
+            continue;
+        }
+
+        let mut send_queue = if let Ok(send_queue) = socket_query.get_mut(socket_id.0) {
+            send_queue
+        } else {
+            continue;
+        };
+
+        let id = rtt.next_id;
+        rtt.next_id = rtt.next_id.wrapping_add(1).max(1);
+        rtt.last_probe = Some(now);
+        if rtt.in_flight.len() == MAX_IN_FLIGHT {
+            rtt.in_flight.pop_front();
+        }
+        rtt.in_flight.push_back((id, now));
+        send_queue.send_probe(addr.0, Probe { id });
+    }
+}
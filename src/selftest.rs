@@ -0,0 +1,278 @@
+use std::{
+    error::Error,
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    bind, CloseSocket, ConnectionIndex, DiagnosticEcho, NetworkClock, Probe, ReceiveQueue,
+    SendQueue, Socket, SocketMarker,
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The poll interval of the sockets bound by a self test.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Probes are unreliable, so they are resent at this interval until one is echoed.
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An event requesting a network self test, whose outcome is reported by a [`SelfTestCompleted`]
+/// event.
+///
+/// The test binds ephemeral sockets and loops [`Probe`]s through the full poll, receive and send
+/// pipeline on the loopback interface. When an echo endpoint is configured, probes are also sent
+/// to it, which requires the endpoint to have a [`DiagnosticEcho`]. The sockets, and any
+/// connections they spawn, are closed once the test completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RunSelfTest {
+    /// An external endpoint expected to echo probes.
+    pub echo_endpoint: Option<SocketAddr>,
+    /// The time allowed for each probe to be echoed.
+    pub timeout: Duration,
+}
+
+impl Default for RunSelfTest {
+    fn default() -> Self {
+        Self {
+            echo_endpoint: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl RunSelfTest {
+    /// Additionally tests an external endpoint expected to echo probes.
+    pub fn with_echo_endpoint(mut self, endpoint: SocketAddr) -> Self {
+        self.echo_endpoint = Some(endpoint);
+        self
+    }
+
+    /// Sets the time allowed for each probe to be echoed.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// A failed check of a self test, see [`SelfTestCompleted`].
+#[derive(Debug)]
+pub enum SelfTestError {
+    /// An ephemeral socket could not be bound.
+    Bind(laminar::ErrorKind),
+    /// No probe was echoed within the timeout.
+    TimedOut,
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bind(error) => write!(f, "failed to bind: {}", error),
+            Self::TimedOut => write!(f, "no probe was echoed within the timeout"),
+        }
+    }
+}
+
+impl Error for SelfTestError {}
+
+/// An event emitted once a self test requested by [`RunSelfTest`] completes.
+///
+/// Each check yields the round trip time of the first probe echoed.
+#[derive(Debug)]
+pub struct SelfTestCompleted {
+    /// The outcome of looping probes through a pair of local sockets.
+    pub loopback: Result<Duration, SelfTestError>,
+    /// The outcome of probing the echo endpoint, if one was configured.
+    pub echo: Option<Result<Duration, SelfTestError>>,
+}
+
+#[derive(Debug)]
+enum Target {
+    Socket(Entity),
+    Address(SocketAddr),
+}
+
+#[derive(Debug)]
+struct Check {
+    /// The socket sending probes and their target, unless the socket failed to bind.
+    probe: Option<(Entity, Target)>,
+    sent: Vec<(u32, Instant)>,
+    outcome: Option<Result<Duration, SelfTestError>>,
+}
+
+impl Check {
+    fn new(socket: Entity, target: Target) -> Self {
+        Self {
+            probe: Some((socket, target)),
+            sent: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    fn failed(error: SelfTestError) -> Self {
+        Self {
+            probe: None,
+            sent: Vec::new(),
+            outcome: Some(Err(error)),
+        }
+    }
+}
+
+/// A [`Component`] tracking a running self test.
+#[derive(Debug, Component)]
+pub(crate) struct SelfTest {
+    timeout: Duration,
+    started: Option<Instant>,
+    sockets: Vec<Entity>,
+    loopback: Check,
+    echo: Option<Check>,
+}
+
+fn spawn_socket(
+    address: SocketAddr,
+    sockets: &mut Vec<Entity>,
+    commands: &mut Commands,
+) -> Result<Entity, SelfTestError> {
+    let bundle = bind(address, POLL_INTERVAL).map_err(SelfTestError::Bind)?;
+    let socket = commands.spawn_bundle(bundle).id();
+    sockets.push(socket);
+    Ok(socket)
+}
+
+pub(crate) fn start_self_tests(
+    mut request_reader: EventReader<RunSelfTest>,
+    mut commands: Commands,
+) {
+    let loopback = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+
+    for request in request_reader.iter() {
+        trace!(message = "starting self test", ?request);
+
+        let mut sockets = Vec::new();
+        let loopback_check = match (
+            spawn_socket(loopback, &mut sockets, &mut commands),
+            spawn_socket(loopback, &mut sockets, &mut commands),
+        ) {
+            (Ok(client), Ok(server)) => {
+                commands.entity(server).insert(DiagnosticEcho);
+                Check::new(client, Target::Socket(server))
+            }
+            (Err(error), _) | (_, Err(error)) => Check::failed(error),
+        };
+
+        let echo_check = request.echo_endpoint.map(|endpoint| {
+            let unspecified = if endpoint.is_ipv4() {
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+            } else {
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+            };
+            match spawn_socket(unspecified, &mut sockets, &mut commands) {
+                Ok(client) => Check::new(client, Target::Address(endpoint)),
+                Err(error) => Check::failed(error),
+            }
+        });
+
+        commands.spawn().insert(SelfTest {
+            timeout: request.timeout,
+            started: None,
+            sockets,
+            loopback: loopback_check,
+            echo: echo_check,
+        });
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn run_self_tests(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    index: Res<ConnectionIndex>,
+    mut test_query: Query<(Entity, &mut SelfTest)>,
+    mut socket_query: Query<(&Socket, &mut SendQueue), With<SocketMarker>>,
+    mut queue_query: Query<&mut ReceiveQueue>,
+    mut completed_writer: EventWriter<SelfTestCompleted>,
+    mut commands: Commands,
+) {
+    // Fetch current instant
+    let now = if let Some(some) = clock.now(&time) {
+        some
+    } else {
+        return;
+    };
+
+    for (entity, mut test) in test_query.iter_mut() {
+        let test = &mut *test;
+        let started = *test.started.get_or_insert(now);
+        let timed_out = started + test.timeout <= now;
+
+        for check in std::iter::once(&mut test.loopback).chain(test.echo.as_mut()) {
+            if check.outcome.is_some() {
+                continue;
+            }
+            let (socket, target) = match &check.probe {
+                Some((socket, target)) => (*socket, target),
+                None => continue,
+            };
+            let target = match *target {
+                Target::Address(address) => Some(address),
+                Target::Socket(server) => socket_query
+                    .get(server)
+                    .ok()
+                    .and_then(|(server, _)| server.local_addr()),
+            };
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            // Look for an echo among the packets the target sent back
+            let echoed = index.get(socket, target).iter().find_map(|connection| {
+                let mut queue = queue_query.get_mut(*connection).ok()?;
+                let echo = queue.drain().find_map(|packet| Probe::from_echo(&packet))?;
+                check.sent.iter().find(|(id, _)| *id == echo.id).copied()
+            });
+            if let Some((_, sent)) = echoed {
+                check.outcome = Some(Ok(now.saturating_duration_since(sent)));
+                continue;
+            }
+
+            if timed_out {
+                check.outcome = Some(Err(SelfTestError::TimedOut));
+                continue;
+            }
+
+            let resend = check
+                .sent
+                .last()
+                .is_none_or(|(_, last_sent)| *last_sent + RESEND_INTERVAL <= now);
+            if let (true, Ok((_, mut send_queue))) = (resend, socket_query.get_mut(socket)) {
+                let id = check.sent.len() as u32 + 1;
+                check.sent.push((id, now));
+                send_queue.send_probe(target, Probe { id });
+            }
+        }
+
+        let pending = std::iter::once(&test.loopback)
+            .chain(test.echo.as_ref())
+            .any(|check| check.outcome.is_none());
+        if pending {
+            continue;
+        }
+
+        let loopback = test.loopback.outcome.take().expect("outcome is set");
+        let echo = test
+            .echo
+            .as_mut()
+            .map(|check| check.outcome.take().expect("outcome is set"));
+        debug!(message = "self test completed", ?loopback, ?echo);
+
+        for socket in test.sockets.drain(..) {
+            commands.add(CloseSocket::new(socket));
+        }
+        commands.entity(entity).despawn();
+        completed_writer.send(SelfTestCompleted { loopback, echo });
+    }
+}
@@ -0,0 +1,89 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    client::DEFAULT_POLL_INTERVAL, descriptor::spawn_descriptor, Authenticator, ConnectionIndex,
+    ConnectionMarker, ConnectionOptions, DescriptorBindError, NetworkPlugin, SocketDescriptor,
+};
+
+/// A [`Plugin`] setting up a server listening for clients.
+///
+/// At startup a socket is bound to the listening address and the [`ServerSocket`] resource is
+/// inserted. Clients are then spawned connection entities as they are first heard from, and may
+/// be queried with [`Clients`]. Should the socket fail to bind, a [`DescriptorBindError`] event is
+/// emitted instead.
+///
+/// The [`NetworkPlugin`] is added with its defaults, unless it was added beforehand.
+#[derive(Debug, Clone)]
+pub struct ServerPlugin {
+    descriptor: SocketDescriptor,
+    authenticator: Option<Authenticator>,
+}
+
+impl ServerPlugin {
+    /// Creates a new [`ServerPlugin`] listening on `address`.
+    pub fn listen(address: SocketAddr) -> Self {
+        Self {
+            descriptor: SocketDescriptor::new(address, DEFAULT_POLL_INTERVAL),
+            authenticator: None,
+        }
+    }
+
+    /// Sets the socket's [`PollInterval`](crate::PollInterval), defaulting to 10 milliseconds.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.descriptor.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the socket's [`ConnectionOptions`].
+    pub fn with_options(mut self, options: ConnectionOptions) -> Self {
+        self.descriptor.options = options;
+        self
+    }
+
+    /// Requires clients to authenticate, inserting the [`Authenticator`] on the socket.
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        if app.world.get_resource::<ConnectionIndex>().is_none() {
+            app.add_plugin(NetworkPlugin::always());
+        }
+
+        let descriptor = self.descriptor.clone();
+        let authenticator_opt = self.authenticator.clone();
+        let start_server = move |mut error_writer: EventWriter<DescriptorBindError>,
+                                 mut commands: Commands| {
+            let address = descriptor.address;
+            if let Some(socket) =
+                spawn_descriptor(descriptor.clone(), &mut error_writer, &mut commands)
+            {
+                if let Some(authenticator) = authenticator_opt.clone() {
+                    commands.entity(socket).insert(authenticator);
+                }
+                commands.insert_resource(ServerSocket { socket, address });
+            }
+        };
+
+        app.add_startup_system(start_server);
+    }
+}
+
+/// A resource holding the listening socket, inserted by the [`ServerPlugin`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServerSocket {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The listening address, as given to [`ServerPlugin::listen`].
+    pub address: SocketAddr,
+}
+
+/// A [`Query`] over connection entities, which for a [`ServerPlugin`] are its clients.
+///
+/// The filter `F` is combined with [`With<ConnectionMarker>`].
+pub type Clients<'w, 's, Q, F = ()> = Query<'w, 's, Q, (With<ConnectionMarker>, F)>;
@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    wire::SETTINGS_TAG, ChannelMask, ConnectionMarker, ConnectionSendQueue, Delivery, PollInterval,
+    ReceiveQueue, SocketId, SocketMarker,
+};
+
+/// Settings are sent reliably on the default stream, so that partial pushes apply in order.
+const SETTINGS_DELIVERY: Delivery = Delivery::ReliableOrdered(None);
+
+/// The shortest [`PollInterval`] a server may push, below which a socket spins.
+pub const MIN_PUSHED_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The longest [`PollInterval`] a server may push, above which a socket all but stops polling.
+pub const MAX_PUSHED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A [`Component`] holding how far behind the latest received state a client renders its peers'
+/// entities, set on the connection to the server.
+///
+/// The plugin does not interpolate by itself: the delay is meant to be read by the app's own
+/// interpolation, and may be tuned by the server with [`ClientSettings`].
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InterpolationDelay(pub Duration);
+
+/// A marker [`Component`] which, when present on a connection entity, or on a socket entity for
+/// all of its connections, applies the [`ClientSettings`] pushed by the peer.
+///
+/// Settings are ignored on connections without it, so that clients cannot tune a server. The
+/// [`ClientPlugin`](crate::ClientPlugin) inserts it on its socket.
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct AcceptSettings;
+
+/// Networking settings a server pushes to a client with [`ConnectionSendQueue::send_settings`],
+/// applied to the client's components as they are received, in [`NetworkStage::PostRecv`].
+///
+/// Settings left to `None` are left unchanged, so that a push may tune a single setting. The
+/// [`PollInterval`] is set on the client's socket, clamped between [`MIN_PUSHED_POLL_INTERVAL`]
+/// and [`MAX_PUSHED_POLL_INTERVAL`], the [`InterpolationDelay`] and [`ChannelMask`] on its
+/// connection to the server, and a [`ClientSettingsApplied`] event is emitted. Sockets
+/// polled by a thread, see [`PollingMode::Threaded`](crate::PollingMode::Threaded), keep polling
+/// at the interval they were spawned with.
+///
+/// [`NetworkStage::PostRecv`]: crate::NetworkStage::PostRecv
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSettings {
+    /// The [`PollInterval`] of the client's socket, which sets how often it sends.
+    pub poll_interval: Option<Duration>,
+    /// The [`InterpolationDelay`] of the connection to the server.
+    pub interpolation_delay: Option<Duration>,
+    /// The [`ChannelMask`] of the connection to the server, muting the message types the client
+    /// sends.
+    pub channel_mask: Option<ChannelMask>,
+}
+
+impl ClientSettings {
+    /// Creates new [`ClientSettings`] leaving every setting unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the [`PollInterval`] of the client's socket.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Sets the [`InterpolationDelay`] of the connection to the server.
+    pub fn with_interpolation_delay(mut self, delay: Duration) -> Self {
+        self.interpolation_delay = Some(delay);
+        self
+    }
+
+    /// Sets the [`ChannelMask`] of the connection to the server.
+    pub fn with_channel_mask(mut self, mask: ChannelMask) -> Self {
+        self.channel_mask = Some(mask);
+        self
+    }
+}
+
+/// An event emitted when [`ClientSettings`] pushed by a server are applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSettingsApplied {
+    /// The connection entity to the server.
+    pub connection: Entity,
+    /// The settings applied.
+    pub settings: ClientSettings,
+}
+
+impl ConnectionSendQueue {
+    /// Pushes [`ClientSettings`] to the peer, to be applied should it accept them, see
+    /// [`AcceptSettings`].
+    pub fn send_settings(&mut self, settings: &ClientSettings) -> Result<(), bincode::Error> {
+        self.send(SETTINGS_DELIVERY, encode(settings)?);
+        Ok(())
+    }
+}
+
+pub(crate) fn encode(settings: &ClientSettings) -> Result<Vec<u8>, bincode::Error> {
+    let mut payload = SETTINGS_TAG.to_vec();
+    payload.extend(bincode::serialize(settings)?);
+    Ok(payload)
+}
+
+/// Decodes pushed settings and encodes them again, see [`WireCapture::verify`].
+///
+/// [`WireCapture::verify`]: crate::WireCapture::verify
+pub(crate) fn reencode(payload: &[u8]) -> Option<Vec<u8>> {
+    let body = payload.strip_prefix(&SETTINGS_TAG)?;
+    let settings = bincode::deserialize::<ClientSettings>(body).ok()?;
+    encode(&settings).ok()
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn apply_client_settings(
+    mut connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &mut ReceiveQueue,
+            Option<&AcceptSettings>,
+            Option<&mut ChannelMask>,
+        ),
+        (With<ConnectionMarker>, Changed<ReceiveQueue>),
+    >,
+    mut socket_query: Query<(Option<&AcceptSettings>, &mut PollInterval), With<SocketMarker>>,
+    mut applied_writer: EventWriter<ClientSettingsApplied>,
+    mut commands: Commands,
+) {
+    for (connection, socket_id, mut queue, accept_opt, mut mask_opt) in connection_query.iter_mut()
+    {
+        // Avoid flagging the queue as changed unless there are settings to remove
+        if !queue
+            .iter()
+            .any(|packet| packet.payload().starts_with(&SETTINGS_TAG))
+        {
+            continue;
+        }
+
+        let mut socket_opt = socket_query.get_mut(socket_id.0).ok();
+        let is_accepted = accept_opt.is_some()
+            || socket_opt
+                .as_ref()
+                .is_some_and(|(accept, _)| accept.is_some());
+
+        let mut pushed = Vec::new();
+        queue.0.retain(|packet| {
+            let body = match packet.payload().strip_prefix(&SETTINGS_TAG) {
+                Some(body) => body,
+                None => return true,
+            };
+            if !is_accepted {
+                debug!(message = "ignoring settings pushed by peer", ?connection);
+                return false;
+            }
+            match bincode::deserialize::<ClientSettings>(body) {
+                Ok(settings) => pushed.push(settings),
+                Err(error) => warn!(message = "failed to decode settings", ?connection, %error),
+            }
+            false
+        });
+
+        for mut settings in pushed {
+            if let Some(poll_interval) = settings.poll_interval {
+                let clamped =
+                    poll_interval.clamp(MIN_PUSHED_POLL_INTERVAL, MAX_PUSHED_POLL_INTERVAL);
+                if clamped != poll_interval {
+                    warn!(
+                        message = "clamping poll interval pushed by peer",
+                        ?connection,
+                        ?poll_interval,
+                        ?clamped
+                    );
+                    settings.poll_interval = Some(clamped);
+                }
+            }
+            debug!(
+                message = "applying settings pushed by peer",
+                ?connection,
+                ?settings
+            );
+            if let (Some(poll_interval), Some((_, interval))) =
+                (settings.poll_interval, socket_opt.as_mut())
+            {
+                interval.0 = poll_interval;
+            }
+            if let Some(delay) = settings.interpolation_delay {
+                commands
+                    .entity(connection)
+                    .insert(InterpolationDelay(delay));
+            }
+            if let Some(mask) = settings.channel_mask.clone() {
+                match mask_opt.as_mut() {
+                    Some(current) => **current = mask,
+                    None => {
+                        commands.entity(connection).insert(mask);
+                    }
+                }
+            }
+            applied_writer.send(ClientSettingsApplied {
+                connection,
+                settings,
+            });
+        }
+    }
+}
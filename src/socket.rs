@@ -1,22 +1,242 @@
 use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Debug,
     net::SocketAddr,
     time::{Duration, Instant},
 };
 
-use bevy::{ecs::system::EntityCommands, prelude::*};
-use laminar::Packet;
+use bevy::{
+    app::Events,
+    ecs::system::{Command, EntityCommands},
+    prelude::*,
+};
+use laminar::{Packet, SocketEvent};
+
+use crate::{
+    diagnostics::FlushMetrics, hooks, normalize_address, polling::PollingThread,
+    pool::despawn_connection, ConnectionAddress, ConnectionMarker, ConnectionOptions,
+    ConnectionState, Delivery, EventBridge, EventBridgeStats, HostId, MiddlewareChain,
+    NetworkClock, NetworkStats, SocketId, Transport,
+};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 /// A marker [`Component`] for the socket entity.
-#[cfg_attr(features = "serde", Serialize, Deserialize)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
 pub struct SocketMarker;
 
+/// A handle to a spawned socket entity, returned by [`SpawnSocket::spawn_socket`].
+///
+/// The handle may be stored, for example as a resource, and used to target that specific socket
+/// with [`Query::get`] rather than filtering by [`SocketMarker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketHandle(pub Entity);
+
+/// An extension trait for [`Commands`] spawning a socket [`Bundle`].
+pub trait SpawnSocket {
+    /// Spawns a socket [`Bundle`], such as that returned by [`bind`](crate::bind), returning its
+    /// [`SocketHandle`].
+    fn spawn_socket<B>(&mut self, bundle: B) -> SocketHandle
+    where
+        B: Bundle;
+}
+
+impl SpawnSocket for Commands<'_, '_> {
+    fn spawn_socket<B>(&mut self, bundle: B) -> SocketHandle
+    where
+        B: Bundle,
+    {
+        SocketHandle(self.spawn_bundle(bundle).id())
+    }
+}
+
+/// A [`Command`] closing a socket.
+///
+/// Packets left on the socket's [`SendQueue`] are handed to laminar and polled out before the
+/// socket is dropped, releasing its port. The socket entity is then despawned along with its
+/// connections, unless they are orphaned, in which case they are moved to
+/// [`ConnectionState::Disconnected`](crate::ConnectionState::Disconnected) and kept. A
+/// [`SocketClosed`] event is emitted.
+///
+/// Queue it with [`Commands::add`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloseSocket {
+    socket: Entity,
+    orphan: bool,
+}
+
+impl CloseSocket {
+    /// Creates a new [`CloseSocket`] which despawns the socket's connections.
+    pub fn new(socket: Entity) -> Self {
+        Self {
+            socket,
+            orphan: false,
+        }
+    }
+
+    /// Keeps the socket's connections, marking them disconnected.
+    pub fn orphan_connections(mut self) -> Self {
+        self.orphan = true;
+        self
+    }
+}
+
+impl Command for CloseSocket {
+    fn write(self, world: &mut World) {
+        let now = match (
+            world.get_resource::<Time>(),
+            world.get_resource::<NetworkClock>(),
+        ) {
+            (Some(time), Some(clock)) => clock.now(time),
+            _ => None,
+        }
+        .unwrap_or_else(Instant::now);
+
+        let mut entity = match world.get_entity_mut(self.socket) {
+            Some(entity) if entity.contains::<SocketMarker>() => entity,
+            _ => return,
+        };
+        let packets: Vec<Packet> = entity
+            .get_mut::<SendQueue>()
+            .map(|mut queue| queue.drain().map(|(packet, _)| packet).collect())
+            .unwrap_or_default();
+        let packets: Vec<Packet> = match entity.get_mut::<MiddlewareChain>() {
+            Some(mut middleware) => packets
+                .into_iter()
+                .filter_map(|packet| middleware.send(packet))
+                .collect(),
+            None => packets,
+        };
+        if let Some(mut socket) = entity.remove::<Socket>() {
+            for packet in packets {
+                if let Err(error) = socket.send(packet) {
+                    error!(message = "failed to send", %error);
+                }
+            }
+            socket.poll(now);
+        }
+
+        trace!(message = "closing socket", socket = ?self.socket);
+
+        let connections: Vec<_> = world
+            .query_filtered::<(Entity, &SocketId, &ConnectionAddress, &ConnectionState), With<ConnectionMarker>>()
+            .iter(world)
+            .filter(|(_, id, _, _)| id.0 == self.socket)
+            .map(|(connection, _, addr, state)| (connection, addr.0, *state))
+            .collect();
+        for &(connection, address, state) in &connections {
+            if !self.orphan {
+                despawn_connection(world, connection);
+            } else if !state.is_disconnected() {
+                hooks::transition_world(
+                    world,
+                    ConnectionState::Disconnected,
+                    (connection, self.socket, address),
+                );
+            }
+        }
+        world.despawn(self.socket);
+
+        if let Some(mut events) = world.get_resource_mut::<Events<SocketClosed>>() {
+            events.send(SocketClosed {
+                socket: self.socket,
+                connections: connections.len(),
+            });
+        }
+    }
+}
+
+/// An event emitted when a socket is closed by [`CloseSocket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketClosed {
+    /// The despawned socket entity.
+    pub socket: Entity,
+    /// The number of connections despawned or orphaned.
+    pub connections: usize,
+}
+
+/// A socket's [`Transport`], polled either in the frame or by a [`PollingThread`].
 #[derive(Debug, Component)]
-pub(crate) struct Socket(pub(crate) laminar::Socket);
+pub(crate) struct Socket {
+    transport: Option<Box<dyn Transport>>,
+    thread: Option<PollingThread>,
+}
+
+impl Socket {
+    pub(crate) fn new<T>(transport: T) -> Self
+    where
+        T: Transport,
+    {
+        Self {
+            transport: Some(Box::new(transport)),
+            thread: None,
+        }
+    }
+
+    pub(crate) fn send(&mut self, packet: Packet) -> laminar::Result<()> {
+        match (&mut self.transport, &self.thread) {
+            (Some(transport), _) => transport.send(packet),
+            (None, Some(thread)) => thread.send(packet),
+            (None, None) => unreachable!("socket without transport or polling thread"),
+        }
+    }
+
+    pub(crate) fn recv(&mut self) -> Option<SocketEvent> {
+        match (&mut self.transport, &self.thread) {
+            (Some(transport), _) => transport.recv(),
+            (None, Some(thread)) => thread.recv(),
+            (None, None) => None,
+        }
+    }
+
+    /// Polls the socket, unless it is polled by a thread.
+    pub(crate) fn poll(&mut self, now: Instant) {
+        if let Some(transport) = &mut self.transport {
+            transport.poll(now);
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        match (&self.transport, &self.thread) {
+            (Some(transport), _) => transport.local_addr(),
+            (None, Some(thread)) => thread.local_addr(),
+            (None, None) => None,
+        }
+    }
+
+    /// Moves the socket to a [`PollingThread`], unless its transport must be polled in the frame,
+    /// returning whether it was moved.
+    pub(crate) fn start_thread(&mut self, poll_interval: Duration, bridge: EventBridge) -> bool {
+        let channels = match self
+            .transport
+            .as_ref()
+            .and_then(|transport| transport.channels())
+        {
+            Some(some) => some,
+            None => return false,
+        };
+        if let Some(transport) = self.transport.take() {
+            self.thread = Some(PollingThread::spawn(
+                transport,
+                channels,
+                poll_interval,
+                bridge,
+            ));
+        }
+        true
+    }
+
+    /// Returns the stats of the polling thread's [`EventBridge`], if polled by a thread.
+    pub(crate) fn bridge_stats(&self) -> Option<EventBridgeStats> {
+        self.thread.as_ref().map(PollingThread::stats)
+    }
+}
+
+/// The [`Config`](laminar::Config) the socket was bound with.
+#[derive(Debug, Clone, Component)]
+pub(crate) struct SocketConfig(pub(crate) laminar::Config);
 
 /// A [`Component`] representing the minimum interval between socket polls.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -28,20 +248,163 @@ pub(crate) struct LastPoll(pub(crate) Option<Instant>);
 
 /// A [`Component`] storing all packets to be sent to a peer.
 #[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
-pub struct SendQueue(pub(crate) Vec<Packet>);
+pub struct SendQueue {
+    /// The packets, along with whether each is a control frame sent as is, see
+    /// [`SendQueue::send_control`].
+    packets: Vec<(Packet, bool)>,
+    latest: HashMap<(SocketAddr, u64), usize>,
+}
 
 impl SendQueue {
     /// Sends a [`Packet`] to a peer.
     pub fn send(&mut self, packet: Packet) {
-        self.0.push(packet)
+        self.packets.push((packet, false))
+    }
+
+    /// Sends a control frame, carrying no application data, which payload transforms such as
+    /// encryption leave untouched.
+    pub(crate) fn send_control(&mut self, packet: Packet) {
+        self.packets.push((packet, true))
+    }
+
+    /// Sends a payload to a peer with the given [`Delivery`], without constructing the [`Packet`].
+    pub fn send_with(&mut self, delivery: Delivery, addr: SocketAddr, payload: Vec<u8>) {
+        self.send(delivery.packet(addr, payload))
+    }
+
+    /// Sends a [`Packet`] to a peer, replacing any packet previously sent with the same `key` to
+    /// the same peer since the last flush.
+    ///
+    /// Only the latest packet per key is sent, in the position of the first. This suits state
+    /// which supersedes itself, such as the newest transform of an entity keyed by
+    /// [`Entity::to_bits`].
+    pub fn send_latest(&mut self, key: u64, packet: Packet) {
+        match self.latest.entry((packet.addr(), key)) {
+            Entry::Occupied(entry) => self.packets[*entry.get()] = (packet, false),
+            Entry::Vacant(entry) => {
+                entry.insert(self.packets.len());
+                self.packets.push((packet, false));
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Packet> {
+        self.packets.iter().map(|(packet, _)| packet)
+    }
+
+    /// Drains the packets, along with whether each is a control frame.
+    pub(crate) fn drain(&mut self) -> std::vec::Drain<'_, (Packet, bool)> {
+        self.latest.clear();
+        self.packets.drain(..)
+    }
+}
+
+/// A [`Component`] storing payloads to be sent to every peer of a socket.
+///
+/// Payloads are addressed to each connection which is not disconnected, once per address, and
+/// moved to the socket's [`SendQueue`] before it is flushed.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
+pub struct BroadcastQueue(pub(crate) Vec<(Delivery, Vec<u8>)>);
+
+impl BroadcastQueue {
+    /// Sends a payload to every peer with the given [`Delivery`].
+    pub fn send(&mut self, delivery: Delivery, payload: Vec<u8>) {
+        self.0.push((delivery, payload))
+    }
+
+    /// Returns the number of payloads.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the queue has a length of 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
+#[allow(clippy::type_complexity)]
+pub(crate) fn forward_broadcasts(
+    mut socket_query: Query<
+        (Entity, &mut BroadcastQueue, &mut SendQueue),
+        (With<SocketMarker>, Changed<BroadcastQueue>),
+    >,
+    connection_query: Query<
+        (
+            &SocketId,
+            &ConnectionAddress,
+            &ConnectionState,
+            Option<&HostId>,
+        ),
+        With<ConnectionMarker>,
+    >,
+    mut metrics: ResMut<FlushMetrics>,
+) {
+    let start = Instant::now();
+
+    for (socket_id, mut broadcast_queue, mut send_queue) in socket_query.iter_mut() {
+        if broadcast_queue.is_empty() {
+            continue;
+        }
+
+        // Virtual hosts behind the same address are each sent their own copy
+        let mut peers = HashSet::new();
+        for (id, addr, state, host_opt) in connection_query.iter() {
+            if id.0 == socket_id
+                && !state.is_disconnected()
+                && peers.insert((addr.normalized(), host_opt.copied()))
+            {
+                for (delivery, payload) in broadcast_queue.0.iter() {
+                    let payload = match host_opt {
+                        Some(host) => host.prefix(payload),
+                        None => payload.clone(),
+                    };
+                    send_queue.send(delivery.packet(addr.0, payload));
+                }
+            }
+        }
+
+        trace!(message = "broadcasting", socket = ?socket_id, peers = peers.len());
+        broadcast_queue.0.clear();
+    }
+
+    metrics.serialize_time += start.elapsed();
+}
+
+/// The decision of a [`ConnectionBuilder`] on whether to spawn a connection to a new peer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConnectionDecision {
+    /// Spawn the connection.
+    Accept,
+    /// Drop the peer's packets without spawning a connection, emitting a [`ConnectionRejected`]
+    /// event with the reason.
+    Reject(String),
+}
+
+/// An event emitted when a [`ConnectionBuilder`] rejects a new peer, or when a peer fails the
+/// handshake of a [`Protocol`](crate::Protocol).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConnectionRejected {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The rejected address.
+    pub address: SocketAddr,
+    /// The reason given by the [`ConnectionBuilder`], or the handshake.
+    pub reason: String,
+}
+
+type BuildFn = dyn Fn(SocketAddr, &mut EntityCommands) + Send + Sync + 'static;
+type DecideFn = dyn Fn(SocketAddr) -> ConnectionDecision + Send + Sync + 'static;
+
 /// A [`Component`] whose presence on a socket entity causes a modification to new connections.
+///
+/// A builder may also decide whether new peers are accepted, see
+/// [`ConnectionBuilder::with_decision`].
 #[derive(Component)]
-pub struct ConnectionBuilder(
-    pub(crate) Box<dyn Fn(SocketAddr, &mut EntityCommands) + Send + Sync + 'static>,
-);
+pub struct ConnectionBuilder {
+    build: Box<BuildFn>,
+    decide: Option<Box<DecideFn>>,
+}
 
 impl ConnectionBuilder {
     /// Creates a new [`ConnectionBuilder`] from a closure. This closure is run against the
@@ -50,7 +413,10 @@ impl ConnectionBuilder {
     where
         F: Fn(SocketAddr, &mut EntityCommands) + Send + Sync + 'static,
     {
-        Self(Box::new(f))
+        Self {
+            build: Box::new(f),
+            decide: None,
+        }
     }
 
     /// Creates a new [`ConnectionBuilder`] which adjoins a component onto new connections.
@@ -58,9 +424,44 @@ impl ConnectionBuilder {
     where
         C: Component + Clone,
     {
-        Self(Box::new(move |_, commands| {
+        Self::new(move |_, commands| {
             commands.insert(component.clone());
-        }))
+        })
+    }
+
+    /// Creates a new [`ConnectionBuilder`] which only decides whether new peers are accepted, see
+    /// [`ConnectionBuilder::with_decision`].
+    pub fn decide<F>(f: F) -> Self
+    where
+        F: Fn(SocketAddr) -> ConnectionDecision + Send + Sync + 'static,
+    {
+        Self::new(|_, _| {}).with_decision(f)
+    }
+
+    /// Sets a closure deciding whether a connection is spawned for a peer the socket has heard
+    /// from, run before the entity is created and before the
+    /// [`ConnectionLimit`](crate::ConnectionLimit) is applied.
+    ///
+    /// A rejected peer's packets are dropped and a [`ConnectionRejected`] event is emitted. The
+    /// peer is decided on again should it send anything in a later tick. Connections opened with
+    /// [`Connect`](crate::Connect) are not subject to the decision.
+    pub fn with_decision<F>(mut self, f: F) -> Self
+    where
+        F: Fn(SocketAddr) -> ConnectionDecision + Send + Sync + 'static,
+    {
+        self.decide = Some(Box::new(f));
+        self
+    }
+
+    pub(crate) fn build(&self, addr: SocketAddr, commands: &mut EntityCommands) {
+        (self.build)(addr, commands)
+    }
+
+    pub(crate) fn decide_on(&self, addr: SocketAddr) -> ConnectionDecision {
+        match &self.decide {
+            Some(decide) => decide(addr),
+            None => ConnectionDecision::Accept,
+        }
     }
 }
 
@@ -72,21 +473,202 @@ impl Debug for ConnectionBuilder {
     }
 }
 
+/// A [`Component`] determining how packets arriving for a connection in
+/// [`ConnectionState::Disconnected`](crate::ConnectionState::Disconnected) or
+/// [`ConnectionState::TimedOut`](crate::ConnectionState::TimedOut) are handled.
+///
+/// Sockets without this component use [`DisconnectedPolicy::ResurrectToPending`]. In all cases a
+/// [`DisconnectedTraffic`](crate::DisconnectedTraffic) event is emitted.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub enum DisconnectedPolicy {
+    /// Return the connection to
+    /// [`ConnectionState::Pending`](crate::ConnectionState::Pending) and deliver the packets.
+    #[default]
+    ResurrectToPending,
+    /// Drop the packets, leaving the connection disconnected.
+    Drop,
+    /// Leave the disconnected connection as is and spawn a new connection for the packets.
+    SpawnNew,
+}
+
+/// A [`Component`] determining whether connections which are
+/// [disconnected](crate::ConnectionState::is_disconnected) are despawned.
+///
+/// Sockets without this component use [`DespawnPolicy::Keep`]. A
+/// [`ConnectionReaped`](crate::ConnectionReaped) event is emitted for each despawned connection.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub enum DespawnPolicy {
+    /// Keep disconnected connections until despawned by the user.
+    #[default]
+    Keep,
+    /// Despawn connections which have remained disconnected for the given grace period.
+    After(Duration),
+}
+
+/// A [`Component`] which, when present on a socket entity, detects addresses which never respond.
+///
+/// Laminar drops a peer which has not been heard from within its idle timeout, only for it to be
+/// tracked again by the next packet sent to it. Each time this happens to an address without a
+/// connection entity, that is one which has only ever been sent to, a failure is counted. Once
+/// the threshold is reached an [`UnreachableAddress`] event is emitted and packets queued for the
+/// address are purged, until a packet is received from it or it is cleared.
+#[derive(Debug, Clone, Component, PartialEq, Eq)]
+pub struct UnreachableAddresses {
+    threshold: u32,
+    failures: HashMap<SocketAddr, u32>,
+}
+
+impl UnreachableAddresses {
+    /// Creates a new [`UnreachableAddresses`] considering addresses unreachable after `threshold`
+    /// failures.
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of failures counted for an address.
+    pub fn failures(&self, address: &SocketAddr) -> u32 {
+        self.failures.get(address).copied().unwrap_or_default()
+    }
+
+    /// Returns `true` if the address has been deemed unreachable.
+    pub fn is_unreachable(&self, address: &SocketAddr) -> bool {
+        self.failures(address) >= self.threshold
+    }
+
+    /// Clears the failures counted for an address, allowing packets to be sent to it again.
+    pub fn clear(&mut self, address: &SocketAddr) {
+        self.failures.remove(address);
+    }
+
+    /// Counts a failure, returning `true` if it caused the address to become unreachable.
+    pub(crate) fn record_failure(&mut self, address: SocketAddr) -> bool {
+        let failures = self.failures.entry(address).or_default();
+        *failures += 1;
+        *failures == self.threshold
+    }
+}
+
+/// An event emitted when an address is deemed unreachable by [`UnreachableAddresses`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnreachableAddress {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The unreachable address.
+    pub address: SocketAddr,
+}
+
+/// A [`Component`] which, when present on a socket entity, ignores all packets received from its
+/// addresses.
+///
+/// Addresses are blocked by [`Disconnect`](crate::Disconnect) and compared once normalized.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq)]
+pub struct BlockedAddresses(HashSet<SocketAddr>);
+
+impl BlockedAddresses {
+    /// Blocks an address.
+    pub fn block(&mut self, address: SocketAddr) {
+        self.0.insert(normalize_address(address));
+    }
+
+    /// Unblocks an address, returning `true` if it was blocked.
+    pub fn unblock(&mut self, address: &SocketAddr) -> bool {
+        self.0.remove(&normalize_address(*address))
+    }
+
+    /// Returns `true` if the address is blocked.
+    pub fn is_blocked(&self, address: &SocketAddr) -> bool {
+        self.0.contains(&normalize_address(*address))
+    }
+}
+
+/// An event emitted when a socket fails to send a [`Packet`].
+#[derive(Debug)]
+pub struct SendError {
+    /// The socket entity.
+    pub socket: Entity,
+    /// The address the packet was sent to.
+    pub address: SocketAddr,
+    /// The length of the packet's payload.
+    pub len: usize,
+    /// The error returned by laminar.
+    pub error: laminar::ErrorKind,
+}
+
+/// A [`Component`] which, when present on a socket entity, collects a histogram of the payload
+/// sizes sent and received.
+///
+/// Bucket `i` counts payloads no larger than [`PacketSizeHistogram::BOUNDS`]`[i]` bytes, and larger
+/// than the previous bound. A final bucket counts payloads larger than every bound, the last being
+/// the `fragment_size` of laminar's default [`Config`](laminar::Config), above which reliable
+/// payloads are sent in fragments.
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq, Hash)]
+pub struct PacketSizeHistogram {
+    sent: [u64; PacketSizeHistogram::BUCKETS],
+    received: [u64; PacketSizeHistogram::BUCKETS],
+}
+
+impl PacketSizeHistogram {
+    /// The inclusive upper bounds, in bytes, of each bucket but the last.
+    pub const BOUNDS: [usize; 5] = [64, 128, 256, 512, 1024];
+
+    /// The number of buckets.
+    pub const BUCKETS: usize = Self::BOUNDS.len() + 1;
+
+    /// Returns the bucket counts of payloads sent.
+    pub fn sent(&self) -> &[u64; Self::BUCKETS] {
+        &self.sent
+    }
+
+    /// Returns the bucket counts of payloads received.
+    pub fn received(&self) -> &[u64; Self::BUCKETS] {
+        &self.received
+    }
+
+    /// Resets all counts to zero.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn bucket(len: usize) -> usize {
+        Self::BOUNDS
+            .iter()
+            .position(|bound| len <= *bound)
+            .unwrap_or(Self::BOUNDS.len())
+    }
+
+    pub(crate) fn record_sent(&mut self, len: usize) {
+        self.sent[Self::bucket(len)] += 1;
+    }
+
+    pub(crate) fn record_received(&mut self, len: usize) {
+        self.received[Self::bucket(len)] += 1;
+    }
+}
+
 #[derive(Bundle)]
 pub(crate) struct SocketBundle {
     pub(crate) marker: SocketMarker,
     pub(crate) socket: Socket,
+    pub(crate) config: SocketConfig,
     pub(crate) last_poll: LastPoll,
     pub(crate) poll_interval: PollInterval,
     pub(crate) send_queue: SendQueue,
+    pub(crate) broadcast_queue: BroadcastQueue,
+    pub(crate) stats: NetworkStats,
 }
 
 pub(crate) fn socket_poll(
     time: Res<Time>,
+    clock: Res<NetworkClock>,
     mut query: Query<(&mut Socket, &mut LastPoll, &PollInterval)>,
 ) {
     // Fetch current instant
-    let now = if let Some(some) = time.last_update() {
+    let now = if let Some(some) = clock.now(&time) {
         some
     } else {
         return;
@@ -106,6 +688,29 @@ pub(crate) fn socket_poll(
             *last_poll = LastPoll(Some(now));
         }
 
-        socket.0.manual_poll(now);
+        socket.poll(now);
+    }
+}
+
+/// Warns about poll intervals which conflict with the laminar timeouts of the socket.
+pub(crate) fn validate_poll_interval(
+    query: Query<(Entity, &PollInterval, &SocketConfig), Changed<PollInterval>>,
+) {
+    for (socket_id, poll_interval, config) in query.iter() {
+        let options = ConnectionOptions::from(&config.0);
+        if let Err(error) = options.validate(poll_interval.0) {
+            warn!(message = "invalid connection options", socket = ?socket_id, %error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_bounds_end_at_fragment_size() {
+        let fragment_size = usize::from(laminar::Config::default().fragment_size);
+        assert_eq!(PacketSizeHistogram::BOUNDS.last(), Some(&fragment_size));
     }
 }
@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+
+/// A [`Component`] counting the traffic of a socket or connection entity.
+///
+/// Both sockets and connections are spawned with one. Byte counts are of payloads, excluding
+/// laminar's headers. Traffic sent to an address shared by several connections, as with
+/// [`HostId`](crate::HostId)s, is attributed to the most recently spawned of them.
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq, Hash)]
+pub struct NetworkStats {
+    /// The number of packets sent.
+    pub packets_sent: u64,
+    /// The number of payload bytes sent.
+    pub bytes_sent: u64,
+    /// The number of packets received.
+    pub packets_received: u64,
+    /// The number of payload bytes received.
+    pub bytes_received: u64,
+    /// The number of packets which failed to send, see [`SendError`](crate::SendError).
+    pub send_errors: u64,
+    /// The network time, see [`NetworkClock`](crate::NetworkClock), at which a packet was last
+    /// sent or received.
+    pub last_activity: Option<Instant>,
+    /// The network time at which a packet was last sent.
+    pub last_sent: Option<Instant>,
+    /// The network time at which a packet was last received.
+    pub last_received: Option<Instant>,
+}
+
+impl NetworkStats {
+    /// Resets all counts, keeping the times of last activity.
+    pub fn reset(&mut self) {
+        *self = Self {
+            last_activity: self.last_activity,
+            last_sent: self.last_sent,
+            last_received: self.last_received,
+            ..Self::default()
+        };
+    }
+
+    pub(crate) fn record_sent(&mut self, len: usize, now: Option<Instant>) {
+        self.packets_sent += 1;
+        self.bytes_sent += len as u64;
+        self.last_activity = now.or(self.last_activity);
+        self.last_sent = now.or(self.last_sent);
+    }
+
+    pub(crate) fn record_received(&mut self, len: usize, now: Option<Instant>) {
+        self.packets_received += 1;
+        self.bytes_received += len as u64;
+        self.last_activity = now.or(self.last_activity);
+        self.last_received = now.or(self.last_received);
+    }
+
+    pub(crate) fn record_send_error(&mut self) {
+        self.send_errors += 1;
+    }
+}
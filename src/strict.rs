@@ -0,0 +1,87 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::{
+    AddressFilter, ConnectionAddress, ConnectionMarker, ConnectionSendQueue, ConnectionState,
+    ReceiveQueue, SendQueue, SocketId, SocketMarker,
+};
+
+/// Reports a misuse of the plugin, panicking in debug builds.
+fn misuse(args: fmt::Arguments<'_>) {
+    if cfg!(debug_assertions) {
+        panic!("network misuse: {}", args);
+    }
+    error!(message = "network misuse", reason = %args);
+}
+
+/// Catches misuse which would otherwise fail silently, see
+/// [`NetworkPlugin::with_strict_mode`](crate::NetworkPlugin::with_strict_mode).
+#[allow(clippy::type_complexity)]
+pub(crate) fn check_misuse(
+    connection_query: Query<
+        (
+            Entity,
+            &SocketId,
+            &ConnectionAddress,
+            &ConnectionState,
+            &ConnectionSendQueue,
+        ),
+        (With<ConnectionMarker>, Changed<ConnectionSendQueue>),
+    >,
+    socket_query: Query<(), With<SocketMarker>>,
+    send_query: Query<
+        (Entity, &SendQueue, &AddressFilter),
+        (With<SocketMarker>, Changed<SendQueue>),
+    >,
+    socket_queue_query: Query<
+        Entity,
+        (
+            With<SocketMarker>,
+            Or<(Added<ReceiveQueue>, Added<ConnectionSendQueue>)>,
+        ),
+    >,
+    connection_queue_query: Query<Entity, (With<ConnectionMarker>, Added<SendQueue>)>,
+) {
+    for (connection, socket_id, addr, state, queue) in connection_query.iter() {
+        if queue.is_empty() {
+            continue;
+        }
+        if state.is_disconnected() {
+            misuse(format_args!(
+                "payloads queued on connection {:?} to {} after it became {:?}",
+                connection, addr.0, state
+            ));
+        }
+        if socket_query.get(socket_id.0).is_err() {
+            misuse(format_args!(
+                "payloads queued on connection {:?} to {} whose socket {:?} no longer exists",
+                connection, addr.0, socket_id.0
+            ));
+        }
+    }
+
+    for (socket, queue, filter) in send_query.iter() {
+        if let Some(packet) = queue.iter().find(|packet| filter.rejects(&packet.addr())) {
+            misuse(format_args!(
+                "socket {:?} sending to {}, whose replies its AddressFilter rejects",
+                socket,
+                packet.addr()
+            ));
+        }
+    }
+
+    for socket in socket_queue_query.iter() {
+        misuse(format_args!(
+            "queue inserted on socket {:?}, connection queues are never filled nor sent on sockets",
+            socket
+        ));
+    }
+
+    for connection in connection_queue_query.iter() {
+        misuse(format_args!(
+            "SendQueue inserted on connection {:?}, use its ConnectionSendQueue instead",
+            connection
+        ));
+    }
+}
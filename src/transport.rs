@@ -0,0 +1,85 @@
+use std::{fmt::Debug, net::SocketAddr, time::Instant};
+
+use crossbeam_channel::{Receiver, Sender};
+use laminar::{ConnectionManager, DatagramSocket, Packet, SocketEvent, VirtualConnection};
+
+/// The backend under a socket entity, moving packets to and from peers, see
+/// [`bind_with_transport`](crate::bind_with_transport).
+///
+/// Connections, queues and every system of the plugin sit above the transport, which only sends
+/// packets, polls and reports what was received as laminar [`SocketEvent`]s. It is implemented for
+/// laminar's UDP [`Socket`](laminar::Socket), and for laminar's [`ConnectionManager`] over any
+/// [`DatagramSocket`], so that a backend exchanging raw datagrams, such as WebRTC data channels or
+/// Steam sockets, gets laminar's reliability and connection events by implementing
+/// [`DatagramSocket`] alone.
+pub trait Transport: Debug + Send + Sync + 'static {
+    /// Queues a packet, sent by the next poll.
+    fn send(&mut self, packet: Packet) -> laminar::Result<()>;
+
+    /// Returns the next event received, if any.
+    fn recv(&mut self) -> Option<SocketEvent>;
+
+    /// Sends the queued packets, receives those from peers and updates connections.
+    fn poll(&mut self, now: Instant);
+
+    /// Returns the local address, if there is one.
+    fn local_addr(&self) -> Option<SocketAddr>;
+
+    /// Returns the channels [`Transport::send`] and [`Transport::recv`] go through, so that the
+    /// transport may be moved to a polling thread, see
+    /// [`PollingMode::Threaded`](crate::PollingMode::Threaded). Transports returning `None`, as
+    /// is the default, are always polled within the frame.
+    fn channels(&self) -> Option<(Sender<Packet>, Receiver<SocketEvent>)> {
+        None
+    }
+}
+
+impl Transport for laminar::Socket {
+    fn send(&mut self, packet: Packet) -> laminar::Result<()> {
+        laminar::Socket::send(self, packet)
+    }
+
+    fn recv(&mut self) -> Option<SocketEvent> {
+        laminar::Socket::recv(self)
+    }
+
+    fn poll(&mut self, now: Instant) {
+        self.manual_poll(now);
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        laminar::Socket::local_addr(self).ok()
+    }
+
+    fn channels(&self) -> Option<(Sender<Packet>, Receiver<SocketEvent>)> {
+        Some((self.get_packet_sender(), self.get_event_receiver()))
+    }
+}
+
+impl<S> Transport for ConnectionManager<S, VirtualConnection>
+where
+    S: DatagramSocket + Send + Sync + 'static,
+{
+    fn send(&mut self, packet: Packet) -> laminar::Result<()> {
+        self.event_sender()
+            .send(packet)
+            .expect("laminar holds the receiver");
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<SocketEvent> {
+        self.event_receiver().try_recv().ok()
+    }
+
+    fn poll(&mut self, now: Instant) {
+        self.manual_poll(now);
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket().local_addr().ok()
+    }
+
+    fn channels(&self) -> Option<(Sender<Packet>, Receiver<SocketEvent>)> {
+        Some((self.event_sender().clone(), self.event_receiver().clone()))
+    }
+}
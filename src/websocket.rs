@@ -0,0 +1,314 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    mem,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use laminar::{Config, Packet, SocketEvent};
+use tungstenite::{
+    handshake::{
+        server::{NoCallback, ServerHandshake},
+        MidHandshake,
+    },
+    protocol::WebSocketConfig,
+    HandshakeError, Message, WebSocket,
+};
+
+use crate::Transport;
+
+const DEFAULT_MAX_PEERS: usize = 1024;
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Packets a peer may fall behind on before it is disconnected.
+const MAX_BACKLOG: usize = 64;
+
+/// A stream carrying a WebSocket, either plain TCP or wrapped by TLS.
+trait Stream: Read + Write + Send + Sync + 'static {}
+
+impl<S> Stream for S where S: Read + Write + Send + Sync + 'static {}
+
+type BoxedStream = Box<dyn Stream>;
+
+type TlsWrapper = Box<dyn Fn(TcpStream) -> io::Result<BoxedStream> + Send + Sync>;
+
+type Handshake = MidHandshake<ServerHandshake<BoxedStream, NoCallback>>;
+
+fn would_block(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(error) if error.kind() == io::ErrorKind::WouldBlock)
+}
+
+/// A [`Transport`] accepting WebSocket connections, such as those of browser clients which cannot
+/// use UDP, see [`bind_websocket`](crate::bind_websocket).
+///
+/// Each packet is carried as a single binary message, without laminar's headers, so that clients
+/// need only send and receive binary messages holding payloads. Peers are identified by the
+/// address they connected from: a connection emits a connect event once its WebSocket handshake
+/// completes, and a disconnect event once closed. WebSockets run over TCP, so every packet is
+/// delivered reliably and in order whatever its [`Delivery`](crate::Delivery), and packets to
+/// addresses which are not connected are dropped.
+///
+/// Connections are plain WS unless a TLS wrapper is set with [`WebSocketTransport::with_tls`],
+/// serving WSS. Alternatively, WSS may be terminated by a reverse proxy in front of the server.
+///
+/// Connections beyond [`WebSocketTransport::with_max_peers`], counting those still handshaking,
+/// are closed as soon as they are accepted, and handshakes taking longer than
+/// [`WebSocketTransport::with_handshake_timeout`] are dropped. Messages larger than the maximum
+/// packet size are refused, closing the connection, as are peers which fall too far behind on
+/// reading what is sent to them or whose stream fails.
+pub struct WebSocketTransport {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    tls: Option<TlsWrapper>,
+    config: WebSocketConfig,
+    max_peers: usize,
+    handshake_timeout: Duration,
+    handshakes: Vec<(SocketAddr, Instant, Handshake)>,
+    peers: HashMap<SocketAddr, WebSocket<BoxedStream>>,
+    outbox: Vec<Packet>,
+    events: VecDeque<SocketEvent>,
+}
+
+impl std::fmt::Debug for WebSocketTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocketTransport")
+            .field("local_addr", &self.local_addr)
+            .field("tls", &self.tls.is_some())
+            .field("max_peers", &self.max_peers)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("handshakes", &self.handshakes.len())
+            .field("peers", &self.peers.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebSocketTransport {
+    /// Listens for WebSocket connections on `addresses`.
+    pub fn bind<A>(addresses: A) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addresses)?;
+        listener.set_nonblocking(true)?;
+        let transport = Self {
+            local_addr: listener.local_addr()?,
+            listener,
+            tls: None,
+            config: WebSocketConfig::default(),
+            max_peers: DEFAULT_MAX_PEERS,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            handshakes: Vec::new(),
+            peers: HashMap::new(),
+            outbox: Vec::new(),
+            events: VecDeque::new(),
+        };
+        Ok(transport.with_max_packet_size(Config::default().max_packet_size))
+    }
+
+    /// Sets the largest message peers may send and the size of the buffer for messages sent to
+    /// them, defaulting to laminar's maximum packet size.
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.config = WebSocketConfig {
+            write_buffer_size: max_packet_size,
+            max_write_buffer_size: max_packet_size * MAX_BACKLOG,
+            max_message_size: Some(max_packet_size),
+            max_frame_size: Some(max_packet_size),
+            ..WebSocketConfig::default()
+        };
+        self
+    }
+
+    /// Sets the maximum number of peers, connected or handshaking, defaulting to 1024.
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// Sets how long peers are given to complete their WebSocket handshake, defaulting to 5
+    /// seconds.
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Serves WSS, wrapping each accepted stream with TLS, such as with rustls'
+    /// `StreamOwned::new(ServerConnection::new(config.clone())?, stream)`.
+    ///
+    /// Streams are non-blocking, so the wrapper should not complete the TLS handshake itself,
+    /// leaving it to be driven as the stream is read and written.
+    pub fn with_tls<F, S>(mut self, wrap: F) -> Self
+    where
+        F: Fn(TcpStream) -> io::Result<S> + Send + Sync + 'static,
+        S: Read + Write + Send + Sync + 'static,
+    {
+        self.tls = Some(Box::new(move |stream| {
+            wrap(stream).map(|stream| Box::new(stream) as BoxedStream)
+        }));
+        self
+    }
+
+    /// Returns the number of connected peers.
+    pub fn peers(&self) -> usize {
+        self.peers.len()
+    }
+
+    fn accept(&mut self, now: Instant) {
+        loop {
+            let (stream, address) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    warn!(message = "failed to accept websocket", %error);
+                    return;
+                }
+            };
+            // Dropping the stream closes it
+            if self.peers.len() + self.handshakes.len() >= self.max_peers {
+                debug!(message = "too many websockets", %address);
+                continue;
+            }
+
+            let stream = stream
+                .set_nonblocking(true)
+                .and_then(|()| stream.set_nodelay(true))
+                .and_then(|()| match &self.tls {
+                    Some(wrap) => wrap(stream),
+                    None => Ok(Box::new(stream) as BoxedStream),
+                });
+            match stream {
+                Ok(stream) => {
+                    let result = tungstenite::accept_with_config(stream, Some(self.config));
+                    self.advance(address, now, result);
+                }
+                Err(error) => debug!(message = "failed to set up websocket", %address, %error),
+            }
+        }
+    }
+
+    fn advance(
+        &mut self,
+        address: SocketAddr,
+        started: Instant,
+        result: Result<
+            WebSocket<BoxedStream>,
+            HandshakeError<ServerHandshake<BoxedStream, NoCallback>>,
+        >,
+    ) {
+        match result {
+            Ok(websocket) => {
+                trace!(message = "websocket connected", %address);
+                // A peer reconnecting from the same address replaces its previous socket
+                self.peers.insert(address, websocket);
+                self.events.push_back(SocketEvent::Connect(address));
+            }
+            Err(HandshakeError::Interrupted(handshake)) => {
+                self.handshakes.push((address, started, handshake));
+            }
+            Err(HandshakeError::Failure(error)) => {
+                debug!(message = "websocket handshake failed", %address, %error);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let mut closed = Vec::new();
+        for packet in self.outbox.drain(..) {
+            let address = packet.addr();
+            let websocket = match self.peers.get_mut(&address) {
+                Some(some) => some,
+                None => {
+                    trace!(message = "dropping packet to unconnected websocket", %address);
+                    continue;
+                }
+            };
+            // Frames which could not be written yet are buffered and retried on flush, until the
+            // buffer is full
+            if let Err(error) = websocket.write(Message::Binary(packet.payload().to_vec())) {
+                if !would_block(&error) && !closed.contains(&address) {
+                    debug!(message = "failed to write websocket", %address, %error);
+                    closed.push(address);
+                }
+            }
+        }
+
+        for (address, websocket) in self.peers.iter_mut() {
+            match websocket.flush() {
+                Ok(()) => {}
+                Err(error) if would_block(&error) => {}
+                Err(error) => {
+                    if !closed.contains(address) {
+                        debug!(message = "failed to flush websocket", %address, %error);
+                        closed.push(*address);
+                    }
+                }
+            }
+        }
+
+        for address in closed {
+            self.close(address);
+        }
+    }
+
+    fn close(&mut self, address: SocketAddr) {
+        if self.peers.remove(&address).is_some() {
+            self.events.push_back(SocketEvent::Disconnect(address));
+        }
+    }
+
+    fn read(&mut self) {
+        let mut closed = Vec::new();
+        for (address, websocket) in self.peers.iter_mut() {
+            loop {
+                match websocket.read() {
+                    Ok(Message::Binary(payload)) => {
+                        let packet = Packet::reliable_ordered(*address, payload, None);
+                        self.events.push_back(SocketEvent::Packet(packet));
+                    }
+                    // Pings are answered as the socket is flushed, text is not part of the protocol
+                    Ok(_) => {}
+                    Err(error) if would_block(&error) => break,
+                    Err(error) => {
+                        trace!(message = "websocket closed", %address, %error);
+                        closed.push(*address);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for address in closed {
+            self.close(address);
+        }
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn send(&mut self, packet: Packet) -> laminar::Result<()> {
+        self.outbox.push(packet);
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<SocketEvent> {
+        self.events.pop_front()
+    }
+
+    fn poll(&mut self, now: Instant) {
+        self.accept(now);
+        for (address, started, handshake) in mem::take(&mut self.handshakes) {
+            if now.saturating_duration_since(started) >= self.handshake_timeout {
+                debug!(message = "websocket handshake timed out", %address);
+                continue;
+            }
+            self.advance(address, started, handshake.handshake());
+        }
+        self.flush();
+        self.read();
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(self.local_addr)
+    }
+}
@@ -0,0 +1,487 @@
+use std::{error::Error, fmt};
+
+#[cfg(feature = "serde")]
+use std::{fs, io, path::Path};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{echo, group, handshake};
+#[cfg(feature = "serde")]
+use crate::{message, replication, rpc, settings};
+
+pub(crate) const HANDSHAKE_TAG: [u8; 4] = *b"STKH";
+pub(crate) const AUTH_TAG: [u8; 4] = *b"STKA";
+pub(crate) const REQUEST_TAG: [u8; 4] = *b"STKQ";
+pub(crate) const REPLY_TAG: [u8; 4] = *b"STKR";
+pub(crate) const GROUP_TAG: [u8; 4] = *b"STKG";
+pub(crate) const REPLICATION_TAG: [u8; 4] = *b"STKE";
+pub(crate) const RPC_TAG: [u8; 4] = *b"STKC";
+pub(crate) const SEALED_TAG: [u8; 4] = *b"STKX";
+pub(crate) const COMPRESSED_TAG: [u8; 4] = *b"STKZ";
+pub(crate) const TRACE_TAG: [u8; 4] = *b"STKT";
+pub(crate) const SETTINGS_TAG: [u8; 4] = *b"STKS";
+
+/// The version of the wire format, bumped by any release changing the bytes of a frame the plugin
+/// sends, so that peers and captures of different releases may be told apart.
+///
+/// This is independent of the [`Protocol`](crate::Protocol) version, which is the app's own.
+pub const WIRE_VERSION: u32 = 1;
+
+/// The kind of a payload on the wire, told by the tag the plugin prefixes its own frames with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    /// A hello, accept or reject frame of the handshake.
+    Handshake,
+    /// The [`Credentials`](crate::Credentials) a client authenticates with.
+    Credentials,
+    /// A [`Probe`](crate::Probe) sent to be echoed.
+    ProbeRequest,
+    /// The echo of a [`Probe`](crate::Probe).
+    ProbeReply,
+    /// A [`MessageGroup`](crate::MessageGroup).
+    Group,
+    /// Replicated components, sent with the `serde` feature.
+    Replication,
+    /// A remote call or its response, sent with the `serde` feature.
+    Rpc,
+    /// An encrypted payload, sent with the `encryption` feature.
+    Sealed,
+    /// A compressed payload, sent with the `compression` feature.
+    Compressed,
+    /// A typed message stamped with a trace id, sent with the `serde` feature.
+    Traced,
+    /// Client settings pushed by a server, sent with the `serde` feature.
+    Settings,
+    /// A payload of the app, carrying none of the plugin's tags.
+    Application,
+}
+
+impl FrameKind {
+    /// Returns the kind of `payload`.
+    pub fn of(payload: &[u8]) -> Self {
+        let tag: [u8; 4] = match payload.get(..4).and_then(|tag| tag.try_into().ok()) {
+            Some(some) => some,
+            None => return Self::Application,
+        };
+        match tag {
+            HANDSHAKE_TAG => Self::Handshake,
+            AUTH_TAG => Self::Credentials,
+            REQUEST_TAG => Self::ProbeRequest,
+            REPLY_TAG => Self::ProbeReply,
+            GROUP_TAG => Self::Group,
+            REPLICATION_TAG => Self::Replication,
+            RPC_TAG => Self::Rpc,
+            SEALED_TAG => Self::Sealed,
+            COMPRESSED_TAG => Self::Compressed,
+            TRACE_TAG => Self::Traced,
+            SETTINGS_TAG => Self::Settings,
+            _ => Self::Application,
+        }
+    }
+
+    /// Decodes and re-encodes `payload`, returning `None` if it does not decode, or `Some(None)`
+    /// if frames of this kind are opaque to this build.
+    fn reencode(self, payload: &[u8]) -> Option<Option<Vec<u8>>> {
+        let reencoded = match self {
+            Self::Handshake => handshake::reencode(payload),
+            Self::ProbeRequest | Self::ProbeReply => echo::reencode(payload),
+            Self::Group => group::reencode(payload),
+            #[cfg(feature = "serde")]
+            Self::Replication => replication::reencode(payload),
+            #[cfg(feature = "serde")]
+            Self::Rpc => rpc::reencode(payload),
+            #[cfg(feature = "serde")]
+            Self::Traced => message::reencode(payload),
+            #[cfg(feature = "serde")]
+            Self::Settings => settings::reencode(payload),
+            // Sealed and compressed frames depend on keys and dictionaries, credentials and
+            // application payloads on the app
+            _ => return Some(None),
+        };
+        reencoded.map(Some)
+    }
+}
+
+/// A recording of payloads as they were on the wire, to be checked against later releases of the
+/// plugin with [`WireCapture::verify`].
+///
+/// Captures are stamped with the [`WIRE_VERSION`] they were recorded with. With the `serde`
+/// feature they may be saved to and loaded from disk, so that captures recorded by a release can
+/// be kept along an app and verified after upgrading, catching changes which would break
+/// compatibility between clients and servers built with different releases.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WireCapture {
+    version: u32,
+    payloads: Vec<Vec<u8>>,
+}
+
+impl Default for WireCapture {
+    fn default() -> Self {
+        Self {
+            version: WIRE_VERSION,
+            payloads: Vec::new(),
+        }
+    }
+}
+
+impl WireCapture {
+    /// Creates an empty [`WireCapture`] of the current [`WIRE_VERSION`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the [`WIRE_VERSION`] the capture was recorded with.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Records a payload, such as that of a [`Packet`](crate::Packet) sent or received.
+    pub fn record(&mut self, payload: Vec<u8>) {
+        self.payloads.push(payload);
+    }
+
+    /// Returns the number of payloads recorded.
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    /// Returns `true` if no payload was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+
+    /// Iterates over the payloads recorded, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.payloads.iter().map(Vec::as_slice)
+    }
+
+    /// Checks that this release still reads the capture as it was written, returning the first
+    /// mismatch found.
+    ///
+    /// Each of the plugin's frames is decoded and encoded again, and must yield the bytes
+    /// recorded. Frames the plugin cannot reproduce, being encrypted, compressed or built by the
+    /// app, are left unchecked, as are frames only decoded with the `serde` feature without it.
+    pub fn verify(&self) -> Result<(), WireMismatch> {
+        if self.version > WIRE_VERSION {
+            return Err(WireMismatch::NewerVersion {
+                version: self.version,
+            });
+        }
+
+        for (index, payload) in self.payloads.iter().enumerate() {
+            let kind = FrameKind::of(payload);
+            match kind.reencode(payload) {
+                None => return Err(WireMismatch::Malformed { index, kind }),
+                Some(Some(reencoded)) if reencoded != *payload => {
+                    return Err(WireMismatch::Reencoded { index, kind })
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl WireCapture {
+    /// Loads a capture saved at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        bincode::deserialize(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Saves the capture to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = bincode::serialize(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(path, bytes)
+    }
+}
+
+/// A difference found by [`WireCapture::verify`] between a capture and the current wire format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WireMismatch {
+    /// The capture was recorded with a newer wire format than this release reads.
+    NewerVersion {
+        /// The version of the capture.
+        version: u32,
+    },
+    /// A payload no longer decodes as the frame its tag announces.
+    Malformed {
+        /// The index of the payload within the capture.
+        index: usize,
+        /// The kind of the payload.
+        kind: FrameKind,
+    },
+    /// A payload decodes, but is encoded differently by this release.
+    Reencoded {
+        /// The index of the payload within the capture.
+        index: usize,
+        /// The kind of the payload.
+        kind: FrameKind,
+    },
+}
+
+impl fmt::Display for WireMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NewerVersion { version } => write!(
+                f,
+                "capture has wire version {}, newer than {}",
+                version, WIRE_VERSION
+            ),
+            Self::Malformed { index, kind } => {
+                write!(f, "payload {} no longer decodes as {:?}", index, kind)
+            }
+            Self::Reencoded { index, kind } => {
+                write!(
+                    f,
+                    "payload {} of kind {:?} re-encodes differently",
+                    index, kind
+                )
+            }
+        }
+    }
+}
+
+impl Error for WireMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handshake::Frame, MessageGroup, Probe, Protocol};
+
+    #[cfg(feature = "serde")]
+    use crate::{
+        encode_traced_message,
+        replication::ReplicationOp,
+        rpc::{self, CallId},
+        ChannelMask, ClientSettings, NetworkId, NetworkMessage, TraceId,
+    };
+    #[cfg(feature = "serde")]
+    use std::time::Duration;
+
+    const HELLO: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x48, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x00, 0x00,
+        0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x00,
+    ];
+    const ACCEPT: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x48, 0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x00, 0x00,
+        0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x00,
+    ];
+    const REJECT: &[u8] = &[0x53, 0x54, 0x4b, 0x48, 0x02, 0x62, 0x61, 0x64];
+    const PROBE_REQUEST: &[u8] = &[0x53, 0x54, 0x4b, 0x51, 0x01, 0x02, 0x03, 0x04];
+    const PROBE_REPLY: &[u8] = &[0x53, 0x54, 0x4b, 0x52, 0x01, 0x02, 0x03, 0x04];
+    const GROUP: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x47, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x61, 0x62, 0x00, 0x00, 0x00,
+        0x01, 0x63,
+    ];
+    #[cfg(feature = "serde")]
+    const RPC_REQUEST: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x43, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x07, 0x0d, 0x0c, 0x0b, 0x0a,
+    ];
+    #[cfg(feature = "serde")]
+    const RPC_RESPONSE: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x43, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x07, 0x0d, 0x0c, 0x0b, 0x0a,
+    ];
+    #[cfg(feature = "serde")]
+    #[rustfmt::skip]
+    const REPLICATION: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x45,
+        // Four operations
+        0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // Spawn
+        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // Insert of a one byte component
+        0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xab,
+        // Remove
+        0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00,
+        // Despawn
+        0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    #[cfg(feature = "serde")]
+    #[rustfmt::skip]
+    const SETTINGS: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x53,
+        // Poll interval of 10 milliseconds
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x96, 0x98, 0x00,
+        // No interpolation delay
+        0x00,
+        // Channel mask muting a single message type
+        0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00,
+    ];
+    #[cfg(feature = "serde")]
+    const TRACED: &[u8] = &[
+        0x53, 0x54, 0x4b, 0x54, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x00, 0x07, 0x0d,
+        0x0c, 0x0b, 0x0a,
+    ];
+
+    /// The capture checked in alongside the crate, recorded with [`WIRE_VERSION`] 1.
+    #[cfg(feature = "serde")]
+    const CAPTURE_V1: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/captures/wire_v1.bin");
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Ping(u32);
+
+    #[cfg(feature = "serde")]
+    impl NetworkMessage for Ping {
+        const ID: u16 = 7;
+    }
+
+    fn protocol() -> Protocol {
+        Protocol::new(0x0102_0304_0506_0708, 3).with_min_version(2)
+    }
+
+    fn golden() -> Vec<(FrameKind, &'static [u8])> {
+        let golden = vec![
+            (FrameKind::Handshake, HELLO),
+            (FrameKind::Handshake, ACCEPT),
+            (FrameKind::Handshake, REJECT),
+            (FrameKind::ProbeRequest, PROBE_REQUEST),
+            (FrameKind::ProbeReply, PROBE_REPLY),
+            (FrameKind::Group, GROUP),
+        ];
+        #[cfg(feature = "serde")]
+        let golden = golden
+            .into_iter()
+            .chain([
+                (FrameKind::Rpc, RPC_REQUEST),
+                (FrameKind::Rpc, RPC_RESPONSE),
+                (FrameKind::Replication, REPLICATION),
+                (FrameKind::Settings, SETTINGS),
+                (FrameKind::Traced, TRACED),
+            ])
+            .collect();
+        golden
+    }
+
+    #[test]
+    fn handshake() {
+        let protocol = protocol();
+        assert_eq!(protocol.frame(handshake::HELLO_KIND, None, None), HELLO);
+        assert_eq!(protocol.frame(handshake::ACCEPT_KIND, None, None), ACCEPT);
+        assert_eq!(Frame::reject("bad"), REJECT);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_handshake() {
+        let frame =
+            protocol()
+                .with_encryption()
+                .frame(handshake::HELLO_KIND, Some([0x11; 32]), None);
+        assert_eq!(frame[..21], HELLO[..21]);
+        assert_eq!(frame[21], 0x01);
+        assert_eq!(frame[22..], [0x11; 32]);
+        assert_eq!(FrameKind::of(&frame), FrameKind::Handshake);
+        assert_eq!(handshake::reencode(&frame).as_deref(), Some(&*frame));
+    }
+
+    #[test]
+    fn probe() {
+        let probe = Probe { id: 0x0102_0304 };
+        let addr = "127.0.0.1:0".parse().unwrap();
+        assert_eq!(probe.packet(addr).payload(), PROBE_REQUEST);
+        assert_eq!(probe.echo(addr).payload(), PROBE_REPLY);
+    }
+
+    #[test]
+    fn group() {
+        let group = MessageGroup::new()
+            .with(b"ab".to_vec())
+            .unwrap()
+            .with(b"c".to_vec())
+            .unwrap();
+        assert_eq!(group.encode().unwrap(), GROUP);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rpc() {
+        let call = CallId(5);
+        let request = rpc::encode_frame(rpc::REQUEST_KIND, call, &Ping(0x0a0b_0c0d)).unwrap();
+        assert_eq!(request, RPC_REQUEST);
+        let response = rpc::encode_frame(rpc::RESPONSE_KIND, call, &Ping(0x0a0b_0c0d)).unwrap();
+        assert_eq!(response, RPC_RESPONSE);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn replication() {
+        let id = NetworkId(1);
+        let ops = [
+            ReplicationOp::Spawn(id),
+            ReplicationOp::Insert(id, 2, vec![0xab]),
+            ReplicationOp::Remove(id, 2),
+            ReplicationOp::Despawn(id),
+        ];
+        assert_eq!(replication::encode(&ops), REPLICATION);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn settings() {
+        let settings = ClientSettings::new()
+            .with_poll_interval(Duration::from_millis(10))
+            .with_channel_mask(ChannelMask::default().with_muted::<Ping>());
+        assert_eq!(settings::encode(&settings).unwrap(), SETTINGS);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn traced() {
+        let trace_id = TraceId(0x1122_3344_5566_7788);
+        assert_eq!(
+            encode_traced_message(&Ping(0x0a0b_0c0d), trace_id).unwrap(),
+            TRACED
+        );
+    }
+
+    #[test]
+    fn kinds() {
+        for (kind, payload) in golden() {
+            assert_eq!(FrameKind::of(payload), kind);
+        }
+        assert_eq!(FrameKind::of(b"STK"), FrameKind::Application);
+        assert_eq!(FrameKind::of(b"ping"), FrameKind::Application);
+    }
+
+    #[test]
+    fn verify() {
+        let mut capture = WireCapture::new();
+        for (_, payload) in golden() {
+            capture.record(payload.to_vec());
+        }
+        assert_eq!(capture.verify(), Ok(()));
+
+        capture.record(GROUP[..GROUP.len() - 1].to_vec());
+        assert_eq!(
+            capture.verify(),
+            Err(WireMismatch::Malformed {
+                index: golden().len(),
+                kind: FrameKind::Group
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checked_in_capture() {
+        let capture = WireCapture::load(CAPTURE_V1).unwrap();
+        assert_eq!(capture.version(), 1);
+        let golden = golden();
+        assert_eq!(
+            capture.iter().collect::<Vec<_>>(),
+            golden
+                .iter()
+                .map(|(_, payload)| *payload)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(capture.verify(), Ok(()));
+    }
+}